@@ -89,7 +89,7 @@ pub fn sync(recipe: &Recipe, paths: &Paths) -> Result<(), Error> {
 
                 Ok(()) as Result<_, Error>
             })
-            .buffer_unordered(moss::environment::MAX_NETWORK_CONCURRENCY)
+            .buffer_unordered(moss::request::concurrency())
             .try_collect::<()>(),
     )?;
 
@@ -56,6 +56,8 @@ pub fn process() -> Result<(), Error> {
 
     let env = Env::new(global.cache_dir, global.config_dir, global.data_dir, global.moss_root)?;
 
+    moss::request::configure(moss::request::Config::load(&env.config));
+
     if global.verbose {
         match subcommand {
             Subcommand::Version(_) => (),
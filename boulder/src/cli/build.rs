@@ -11,7 +11,7 @@ use boulder::package::Packager;
 use boulder::{container, package, profile, timing, Env, Timing};
 use chrono::Local;
 use clap::Parser;
-use moss::signal::inhibit;
+use moss::{signal::inhibit, signing};
 use thiserror::Error;
 use thread_priority::{thread_native_id, NormalThreadSchedulePolicy, ThreadPriority, ThreadSchedulePolicy};
 
@@ -51,6 +51,8 @@ pub struct Command {
         help = "Specify the build release number used for this build"
     )]
     build_release: NonZeroU64,
+    #[arg(long, help = "Sign the resulting stones with the secret key at this path")]
+    sign: Option<PathBuf>,
 }
 
 pub fn handle(command: Command, env: Env) -> Result<(), Error> {
@@ -62,6 +64,7 @@ pub fn handle(command: Command, env: Env) -> Result<(), Error> {
         update,
         normal_priority,
         build_release,
+        sign,
         ..
     } = command;
 
@@ -72,6 +75,12 @@ pub fn handle(command: Command, env: Env) -> Result<(), Error> {
         return Err(Error::MissingOutput(output));
     }
 
+    // Load the key upfront so a bad path fails before building
+    let key = sign
+        .map(|path| signing::SecretKey::load(&path))
+        .transpose()
+        .map_err(Error::LoadKey)?;
+
     let builder = Builder::new(&recipe_path, env, profile, ccache, output)?;
     builder.setup(&mut timing, timer, update)?;
 
@@ -118,6 +127,10 @@ pub fn handle(command: Command, env: Env) -> Result<(), Error> {
         Ok(())
     })?;
 
+    if let Some(key) = &key {
+        package::sign_artefacts(paths, key)?;
+    }
+
     // Copy artefacts to host recipe dir
     package::sync_artefacts(paths).map_err(Error::SyncArtefacts)?;
 
@@ -137,6 +150,8 @@ pub enum Error {
     Build(#[from] build::Error),
     #[error("package artifacts")]
     Package(#[from] package::Error),
+    #[error("load signing key")]
+    LoadKey(#[source] signing::Error),
     #[error("sync artefacts")]
     SyncArtefacts(#[source] io::Error),
     #[error("container")]
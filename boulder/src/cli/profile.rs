@@ -10,7 +10,7 @@ use thiserror::Error;
 use url::Url;
 
 use boulder::{profile, Env, Profile};
-use moss::{repository, runtime, Installation, Repository};
+use moss::{repository, request, runtime, signing, Installation, Repository};
 
 #[derive(Debug, Parser)]
 #[command(about = "Manage boulder profiles")]
@@ -34,7 +34,7 @@ pub enum Subcommand {
         help = "profile repositories",
         value_parser = parse_repository,
         help = "repository to add to profile, can be passed multiple times",
-        long_help = "repository to add to profile\n\nExample: --repo name=volatile,uri=https://dev.serpentos.com/volatile/x86_64/stone.index,priority=100"
+        long_help = "repository to add to profile, optionally with a mirror, a trusted signing key, stone signature policy (ignore, verify or require) and credentials (username & password_file, or token_file)\n\nExample: --repo name=volatile,uri=https://dev.serpentos.com/volatile/x86_64/stone.index,priority=100"
         )]
         repos: Vec<(repository::Id, Repository)>,
     },
@@ -64,14 +64,54 @@ fn parse_repository(s: &str) -> Result<(repository::Id, Repository), String> {
         .transpose()
         .map_err(|e| e.to_string())?
         .unwrap_or_default();
+    let mirrors = key_values
+        .get("mirror")
+        .map(|uri| uri.parse::<Url>())
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .map(|uri| repository::Mirror { uri, weight: 0 })
+        .into_iter()
+        .collect();
+    let trusted_keys = key_values
+        .get("key")
+        .map(|key| key.parse::<signing::PublicKey>())
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    let credentials = match (
+        key_values.get("username"),
+        key_values.get("password_file"),
+        key_values.get("token_file"),
+    ) {
+        (_, _, Some(token_file)) => Some(request::Credentials::Bearer {
+            token_file: token_file.into(),
+        }),
+        (Some(username), Some(password_file), None) => Some(request::Credentials::Basic {
+            username: username.to_string(),
+            password_file: password_file.into(),
+        }),
+        (None, None, None) => None,
+        _ => return Err("username and password_file must be set together".into()),
+    };
+    let stone_signatures = key_values
+        .get("signatures")
+        .map(|policy| policy.parse::<repository::SignaturePolicy>())
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
 
     Ok((
         id,
         Repository {
             description: String::default(),
             uri,
+            mirrors,
             priority: repository::Priority::new(priority),
             active: true,
+            trusted_keys,
+            stone_signatures,
+            credentials,
         },
     ))
 }
@@ -53,6 +53,7 @@ fn scan_autotools(state: &mut State, path: &Path) -> Result<(), Error> {
             state.add_dependency(Dependency {
                 kind: dependency::Kind::PkgConfig,
                 name,
+                constraint: None,
             });
         }
     }
@@ -48,6 +48,7 @@ fn scan_meson(state: &mut State, path: &Path) -> Result<(), Error> {
             state.add_dependency(Dependency {
                 kind: dependency::Kind::PkgConfig,
                 name,
+                constraint: None,
             });
         }
     }
@@ -65,6 +66,7 @@ fn scan_meson(state: &mut State, path: &Path) -> Result<(), Error> {
             state.add_dependency(Dependency {
                 kind: dependency::Kind::Binary,
                 name,
+                constraint: None,
             });
         }
     }
@@ -2,7 +2,7 @@ use std::{io, path::Path, process::ExitStatus, time::Duration};
 
 use fs_err::tokio::{self as fs, File};
 use futures::{stream, StreamExt, TryStreamExt};
-use moss::{environment, request, runtime};
+use moss::{request, runtime};
 use sha2::{Digest, Sha256};
 use thiserror::Error;
 use tokio::{io::AsyncWriteExt, process::Command};
@@ -51,7 +51,7 @@ pub fn fetch_and_extract(upstreams: &[Url], extract_root: &Path) -> Result<Vec<U
 
                 Ok(Upstream { uri: uri.clone(), hash })
             })
-            .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
+            .buffer_unordered(request::concurrency())
             .try_collect(),
     );
 
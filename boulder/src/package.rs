@@ -6,6 +6,7 @@ use std::{io, num::NonZeroU64};
 
 use fs_err as fs;
 use itertools::Itertools;
+use moss::signing;
 use thiserror::Error;
 
 use stone::write::digest;
@@ -211,6 +212,26 @@ fn resolve_packages(
     Ok(packages)
 }
 
+/// Sign the emitted stones in the host artefacts directory, appending
+/// a signature payload to each
+pub fn sign_artefacts(paths: &Paths, key: &signing::SecretKey) -> Result<(), Error> {
+    let stones = util::enumerate_files(&paths.artefacts().host, |path| {
+        path.extension().is_some_and(|ext| ext == "stone")
+    })
+    .map_err(Error::SignArtefacts)?;
+
+    for path in stones {
+        signing::sign_stone(key, &path)?;
+
+        println!(
+            "Signed {}",
+            path.file_name().and_then(|p| p.to_str()).unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
 pub fn sync_artefacts(paths: &Paths) -> Result<(), io::Error> {
     for path in util::enumerate_files(&paths.artefacts().host, |_| true)? {
         let filename = path.file_name().and_then(|p| p.to_str()).unwrap_or_default();
@@ -238,4 +259,8 @@ pub enum Error {
     Emit(#[from] emit::Error),
     #[error("container")]
     Container(#[from] container::Error),
+    #[error("enumerate artefacts")]
+    SignArtefacts(#[source] io::Error),
+    #[error("sign stone")]
+    Sign(#[from] signing::Error),
 }
@@ -115,6 +115,7 @@ pub fn pkg_config(bucket: &mut BucketMut, info: &mut PathInfo) -> Result<Respons
         bucket.dependencies.insert(Dependency {
             kind,
             name: dep.to_string(),
+            constraint: None,
         });
     }
 
@@ -175,6 +176,7 @@ pub fn python(bucket: &mut BucketMut, info: &mut PathInfo) -> Result<Response, B
         bucket.dependencies.insert(Dependency {
             kind: dependency::Kind::Python,
             name: format!("{}({})", &dep, &python_version.to_string().trim_end()),
+            constraint: None,
         });
     }
 
@@ -133,6 +133,7 @@ fn parse_dynamic_section(
                 bucket.dependencies.insert(Dependency {
                     kind: dependency::Kind::SharedLibrary,
                     name: format!("{name}({depends_isa})"),
+                    constraint: None,
                 });
             }
         }
@@ -226,6 +227,7 @@ fn parse_interp_section(elf: &mut elf::ElfStream<AnyEndian, File>, bucket: &mut
         bucket.dependencies.insert(Dependency {
             kind: dependency::Kind::Interpreter,
             name: format!("{content}({depends_isa})"),
+            constraint: None,
         });
     }
 }
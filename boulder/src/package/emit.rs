@@ -91,6 +91,7 @@ impl<'a> Package<'a> {
             uri: None,
             hash: None,
             download_size: None,
+            delta: None,
         }
     }
 }
@@ -54,14 +54,19 @@ impl Manager {
     }
 
     pub fn load<T: Config>(&self) -> Vec<T> {
+        self.load_with_paths().into_iter().map(|(_, config)| config).collect()
+    }
+
+    /// Same as [`Manager::load`], along with the path each config was loaded from
+    pub fn load_with_paths<T: Config>(&self) -> Vec<(PathBuf, T)> {
         let domain = T::domain();
 
         let mut configs = vec![];
 
         for (entry, resolve) in self.scope.load_with() {
             for path in enumerate_paths(entry, resolve, &domain) {
-                if let Some(config) = read_config(path) {
-                    configs.push(config);
+                if let Some(config) = read_config(&path) {
+                    configs.push((path, config));
                 }
             }
         }
@@ -69,6 +74,11 @@ impl Manager {
         configs
     }
 
+    /// Path the config `name` is saved to
+    pub fn save_path<T: Config>(&self, name: impl fmt::Display) -> PathBuf {
+        self.scope.save_dir(&T::domain()).join(format!("{name}.{EXTENSION}"))
+    }
+
     pub fn save<T: Config + Serialize>(&self, name: impl fmt::Display, config: &T) -> Result<(), SaveError> {
         let domain = T::domain();
 
@@ -144,7 +154,7 @@ fn enumerate_paths(entry: Entry, resolve: Resolve<'_>, domain: &str) -> Vec<Path
     }
 }
 
-fn read_config<T: Config>(path: PathBuf) -> Option<T> {
+fn read_config<T: Config>(path: &Path) -> Option<T> {
     let bytes = fs::read(path).ok()?;
     serde_yaml::from_slice(&bytes).ok()
 }
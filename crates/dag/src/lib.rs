@@ -82,6 +82,11 @@ where
         self.0.node_indices().map(|i| &self.0[i])
     }
 
+    /// Return the direct successors of the node at the given index
+    pub fn successors(&self, index: NodeIndex) -> impl Iterator<Item = &'_ N> {
+        self.0.neighbors(index).map(|i| &self.0[i])
+    }
+
     /// Perform a depth-first search, given the start index
     pub fn dfs(&self, start: NodeIndex) -> impl Iterator<Item = &'_ N> {
         let dfs = Dfs::new(&self.0, start);
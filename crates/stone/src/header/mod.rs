@@ -78,6 +78,12 @@ impl Header {
         }
     }
 
+    pub fn file_type(&self) -> v1::FileType {
+        match self {
+            Header::V1(header) => header.file_type,
+        }
+    }
+
     pub fn encode<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
         let version = u32::to_be_bytes(self.version() as u32);
 
@@ -132,6 +132,16 @@ pub enum Tag {
     SourcePath = 19,
     // Ref/commit of the upstream source
     SourceRef = 20,
+    // Runtime dependency with a version constraint, i.e. `zlib >= 1.3`
+    VersionedDepends = 21,
+    // Deltas index specific (Hash of the package a delta applies to)
+    DeltaFrom = 22,
+    // Deltas index specific (relative URI of the delta)
+    DeltaURI = 23,
+    // Deltas index specific (Delta hash)
+    DeltaHash = 24,
+    // Deltas index specific (Delta size on disk)
+    DeltaSize = 25,
 }
 
 /// Helper to decode a dependency's encoded kind
@@ -176,6 +186,11 @@ impl Record for Meta {
             18 => Tag::SourceURI,
             19 => Tag::SourcePath,
             20 => Tag::SourceRef,
+            21 => Tag::VersionedDepends,
+            22 => Tag::DeltaFrom,
+            23 => Tag::DeltaURI,
+            24 => Tag::DeltaHash,
+            25 => Tag::DeltaSize,
             t => return Err(DecodeError::UnknownMetaTag(t)),
         };
 
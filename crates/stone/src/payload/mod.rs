@@ -6,6 +6,7 @@ mod attribute;
 mod index;
 pub mod layout;
 pub mod meta;
+mod signature;
 
 use std::io::{self, Read, Write};
 
@@ -15,6 +16,7 @@ pub use self::attribute::Attribute;
 pub use self::index::Index;
 pub use self::layout::Layout;
 pub use self::meta::Meta;
+pub use self::signature::Signature;
 use crate::{ReadExt, WriteExt};
 
 #[repr(u8)]
@@ -32,6 +34,8 @@ pub enum Kind {
     Attributes = 5,
     // For Writer interim
     Dumb = 6,
+    // Detached signatures over the other payloads
+    Signature = 7,
 }
 
 #[repr(u8)]
@@ -72,6 +76,7 @@ impl Header {
             4 => Kind::Index,
             5 => Kind::Attributes,
             6 => Kind::Dumb,
+            7 => Kind::Signature,
             k => return Err(DecodeError::UnknownKind(k)),
         };
 
@@ -152,6 +157,8 @@ pub enum DecodeError {
     UnknownFileType(u8),
     #[error("Unknown dependency type: {0}")]
     UnknownDependency(u8),
+    #[error("Invalid signature lengths: key {0}, signature {1}")]
+    InvalidSignatureLength(u64, u64),
     #[error("io")]
     Io(#[from] io::Error),
 }
@@ -0,0 +1,57 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::io::{Read, Write};
+
+use super::{DecodeError, EncodeError, Record};
+use crate::{ReadExt, WriteExt};
+
+/// A detached signature over the stone header and the headers and
+/// sha256 body digests of all other payloads, as returned by
+/// [`crate::Reader::signed_message`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// Public key of the signer
+    pub key: Vec<u8>,
+
+    /// Raw signature bytes
+    pub signature: Vec<u8>,
+}
+
+impl Signature {
+    /// Length of an ed25519 public key
+    pub const KEY_LENGTH: u64 = 32;
+    /// Length of an ed25519 signature
+    pub const SIGNATURE_LENGTH: u64 = 64;
+}
+
+impl Record for Signature {
+    fn decode<R: Read>(mut reader: R) -> Result<Self, DecodeError> {
+        let key_length = reader.read_u64()?;
+        let signature_length = reader.read_u64()?;
+
+        // Lengths come from an untrusted stone, so never allocate based on them
+        if key_length != Self::KEY_LENGTH || signature_length != Self::SIGNATURE_LENGTH {
+            return Err(DecodeError::InvalidSignatureLength(key_length, signature_length));
+        }
+
+        let key = reader.read_vec(key_length as usize)?;
+        let signature = reader.read_vec(signature_length as usize)?;
+
+        Ok(Self { key, signature })
+    }
+
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<(), EncodeError> {
+        writer.write_u64(self.key.len() as u64)?;
+        writer.write_u64(self.signature.len() as u64)?;
+        writer.write_all(&self.key)?;
+        writer.write_all(&self.signature)?;
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        8 + 8 + self.key.len() + self.signature.len()
+    }
+}
@@ -2,10 +2,11 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
+use sha2::{Digest, Sha256};
 use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use thiserror::Error;
 
-use crate::payload::{Attribute, Compression, Index, Layout, Meta};
+use crate::payload::{Attribute, Compression, Index, Layout, Meta, Signature};
 use crate::{header, Payload};
 use crate::{payload, Header};
 
@@ -28,6 +29,22 @@ pub fn read_bytes(bytes: &[u8]) -> Result<Reader<Cursor<&[u8]>>, Error> {
     read(Cursor::new(bytes))
 }
 
+/// Read a stone front to back from a source which can't seek,
+/// such as a download which is still in progress
+pub fn stream<R: Read>(reader: R) -> Result<Stream<R>, Error> {
+    let mut reader = Positioned {
+        inner: reader,
+        position: 0,
+    };
+    let header = Header::decode(&mut reader).map_err(Error::HeaderDecode)?;
+
+    Ok(Stream {
+        header,
+        reader,
+        hasher: digest::Hasher::new(),
+    })
+}
+
 pub struct Reader<R> {
     pub header: Header,
     reader: R,
@@ -45,6 +62,57 @@ impl<R: Read + Seek> Reader<R> {
             .flat_map(|_| PayloadKind::decode(&mut self.reader, &mut self.hasher).transpose()))
     }
 
+    /// Returns the message covered by a stone signature: the stone header,
+    /// then the header and sha256 digest of the stored body of every payload,
+    /// excluding signature payloads.
+    ///
+    /// Signature payloads are excluded (and the stone header rewritten as if
+    /// they were absent) so signatures can be appended to an existing stone.
+    pub fn signed_message(&mut self) -> Result<Vec<u8>, Error> {
+        self.reader.seek(SeekFrom::Start(Header::SIZE as u64))?;
+
+        let mut payloads = vec![];
+
+        for _ in 0..self.header.num_payloads() {
+            let header = match payload::Header::decode(&mut self.reader) {
+                Ok(header) => header,
+                Err(payload::DecodeError::Io(error)) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(Error::PayloadDecode(error)),
+            };
+
+            if header.kind == payload::Kind::Signature {
+                self.reader.seek(SeekFrom::Current(header.stored_size as i64))?;
+                continue;
+            }
+
+            // Payload checksums aren't cryptographic, so the body itself must be covered
+            let mut digest = Sha256::new();
+            let copied = io::copy(&mut (&mut self.reader).take(header.stored_size), &mut digest)?;
+            if copied != header.stored_size {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            payloads.push((header, digest.finalize()));
+        }
+
+        let mut message = vec![];
+
+        let header = match self.header {
+            Header::V1(header) => Header::V1(header::v1::Header {
+                num_payloads: payloads.len() as u16,
+                ..header
+            }),
+        };
+        header.encode(&mut message)?;
+
+        for (header, digest) in payloads {
+            header.encode(&mut message)?;
+            message.extend_from_slice(&digest);
+        }
+
+        Ok(message)
+    }
+
     pub fn unpack_content<W>(&mut self, content: &Payload<Content>, writer: &mut W) -> Result<(), Error>
     where
         W: Write,
@@ -65,6 +133,72 @@ impl<R: Read + Seek> Reader<R> {
     }
 }
 
+pub struct Stream<R> {
+    pub header: Header,
+    reader: Positioned<R>,
+    hasher: digest::Hasher,
+}
+
+impl<R: Read> Stream<R> {
+    /// Decode all payloads in order, unpacking the content payload into `writer` as it's reached.
+    ///
+    /// The returned content payload refers to its offset within the stone, so it
+    /// can still be read from a complete copy with [`Reader::unpack_content`].
+    pub fn unpack<W: Write>(mut self, writer: &mut W) -> Result<Vec<PayloadKind>, Error> {
+        let mut payloads = vec![];
+
+        for _ in 0..self.header.num_payloads() {
+            let header = match payload::Header::decode(&mut self.reader) {
+                Ok(header) => header,
+                Err(payload::DecodeError::Io(error)) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(Error::PayloadDecode(error)),
+            };
+
+            if header.kind != payload::Kind::Content {
+                payloads.push(PayloadKind::decode_records(header, &mut self.reader, &mut self.hasher)?);
+                continue;
+            }
+
+            if payloads.iter().any(|payload| payload.content().is_some()) {
+                return Err(Error::MultipleContent);
+            }
+
+            let offset = self.reader.position;
+            self.hasher.reset();
+
+            let mut hashed = digest::Reader::new(&mut self.reader, &mut self.hasher);
+            let mut framed = (&mut hashed).take(header.stored_size);
+
+            io::copy(&mut PayloadReader::new(&mut framed, header.compression)?, writer)?;
+            // Trailing bytes of the frame still count towards the checksum
+            io::copy(&mut framed, &mut io::sink())?;
+
+            validate_checksum(&self.hasher, &header)?;
+
+            payloads.push(PayloadKind::Content(Payload {
+                header,
+                body: Content { offset },
+            }));
+        }
+
+        Ok(payloads)
+    }
+}
+
+/// Tracks the position of a reader which can't seek
+struct Positioned<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for Positioned<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Content {
     offset: u64,
@@ -100,71 +234,84 @@ pub enum PayloadKind {
     Layout(Payload<Vec<Layout>>),
     Index(Payload<Vec<Index>>),
     Content(Payload<Content>),
+    Signature(Payload<Vec<Signature>>),
 }
 
 impl PayloadKind {
     fn decode<R: Read + Seek>(mut reader: R, hasher: &mut digest::Hasher) -> Result<Option<Self>, Error> {
         match payload::Header::decode(&mut reader) {
-            Ok(header) => {
-                hasher.reset();
-                let mut hashed = digest::Reader::new(&mut reader, hasher);
-                let mut framed = (&mut hashed).take(header.stored_size);
-
-                let payload = match header.kind {
-                    payload::Kind::Meta => PayloadKind::Meta(Payload {
-                        header,
-                        body: payload::decode_records(
-                            PayloadReader::new(&mut framed, header.compression)?,
-                            header.num_records,
-                        )?,
-                    }),
-                    payload::Kind::Layout => PayloadKind::Layout(Payload {
-                        header,
-                        body: payload::decode_records(
-                            PayloadReader::new(&mut framed, header.compression)?,
-                            header.num_records,
-                        )?,
-                    }),
-                    payload::Kind::Index => PayloadKind::Index(Payload {
-                        header,
-                        body: payload::decode_records(
-                            PayloadReader::new(&mut framed, header.compression)?,
-                            header.num_records,
-                        )?,
-                    }),
-                    payload::Kind::Attributes => PayloadKind::Attributes(Payload {
-                        header,
-                        body: payload::decode_records(
-                            PayloadReader::new(&mut framed, header.compression)?,
-                            header.num_records,
-                        )?,
-                    }),
-                    payload::Kind::Content => {
-                        let offset = reader.stream_position()?;
-
-                        // Skip past, these are read by user later
-                        reader.seek(SeekFrom::Current(header.stored_size as i64))?;
-
-                        PayloadKind::Content(Payload {
-                            header,
-                            body: Content { offset },
-                        })
-                    }
-                    payload::Kind::Dumb => unimplemented!("??"),
-                };
-
-                // Validate hash for non-content payloads
-                if !matches!(header.kind, payload::Kind::Content) {
-                    validate_checksum(hasher, &header)?;
-                }
-
-                Ok(Some(payload))
+            Ok(header) if header.kind == payload::Kind::Content => {
+                let offset = reader.stream_position()?;
+
+                // Skip past, these are read by user later
+                reader.seek(SeekFrom::Current(header.stored_size as i64))?;
+
+                Ok(Some(PayloadKind::Content(Payload {
+                    header,
+                    body: Content { offset },
+                })))
             }
+            Ok(header) => Self::decode_records(header, reader, hasher).map(Some),
             Err(payload::DecodeError::Io(error)) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
             Err(error) => Err(Error::PayloadDecode(error)),
         }
     }
 
+    /// Decode the body of any payload but [`payload::Kind::Content`]
+    fn decode_records<R: Read>(
+        header: payload::Header,
+        mut reader: R,
+        hasher: &mut digest::Hasher,
+    ) -> Result<Self, Error> {
+        hasher.reset();
+        let mut hashed = digest::Reader::new(&mut reader, hasher);
+        let mut framed = (&mut hashed).take(header.stored_size);
+
+        let payload = match header.kind {
+            payload::Kind::Meta => PayloadKind::Meta(Payload {
+                header,
+                body: payload::decode_records(
+                    PayloadReader::new(&mut framed, header.compression)?,
+                    header.num_records,
+                )?,
+            }),
+            payload::Kind::Layout => PayloadKind::Layout(Payload {
+                header,
+                body: payload::decode_records(
+                    PayloadReader::new(&mut framed, header.compression)?,
+                    header.num_records,
+                )?,
+            }),
+            payload::Kind::Index => PayloadKind::Index(Payload {
+                header,
+                body: payload::decode_records(
+                    PayloadReader::new(&mut framed, header.compression)?,
+                    header.num_records,
+                )?,
+            }),
+            payload::Kind::Attributes => PayloadKind::Attributes(Payload {
+                header,
+                body: payload::decode_records(
+                    PayloadReader::new(&mut framed, header.compression)?,
+                    header.num_records,
+                )?,
+            }),
+            payload::Kind::Signature => PayloadKind::Signature(Payload {
+                header,
+                body: payload::decode_records(
+                    PayloadReader::new(&mut framed, header.compression)?,
+                    header.num_records,
+                )?,
+            }),
+            payload::Kind::Content => unreachable!("content is never decoded as records"),
+            payload::Kind::Dumb => unimplemented!("??"),
+        };
+
+        validate_checksum(hasher, &header)?;
+
+        Ok(payload)
+    }
+
     pub fn meta(&self) -> Option<&Payload<Vec<Meta>>> {
         if let Self::Meta(meta) = self {
             Some(meta)
@@ -204,6 +351,14 @@ impl PayloadKind {
             None
         }
     }
+
+    pub fn signature(&self) -> Option<&Payload<Vec<Signature>>> {
+        if let Self::Signature(signatures) = self {
+            Some(signatures)
+        } else {
+            None
+        }
+    }
 }
 
 fn validate_checksum(hasher: &digest::Hasher, header: &payload::Header) -> Result<(), Error> {
@@ -225,6 +380,8 @@ pub enum Error {
     HeaderDecode(#[from] header::DecodeError),
     #[error("payload decode")]
     PayloadDecode(#[from] payload::DecodeError),
+    #[error("payload encode")]
+    PayloadEncode(#[from] payload::EncodeError),
     #[error("payload checksum mismatch: got {got:02x}, expected {expected:02x}")]
     PayloadChecksum { got: u64, expected: u64 },
     #[error("io")]
@@ -289,4 +446,73 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn stream_bash_completion() {
+        let bytes = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+
+        let mut streamed_content = vec![];
+        let streamed = stream(&bytes[..])
+            .expect("valid stone")
+            .unpack(&mut streamed_content)
+            .expect("valid payloads");
+
+        let mut stone = read_bytes(bytes).expect("valid stone");
+        let payloads = stone
+            .payloads()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("seek payloads");
+        assert_eq!(streamed.len(), payloads.len());
+
+        let content = streamed.iter().find_map(PayloadKind::content).expect("content payload");
+        let mut unpacked_content = vec![];
+        stone
+            .unpack_content(content, &mut unpacked_content)
+            .expect("valid content");
+        assert_eq!(streamed_content, unpacked_content);
+    }
+
+    #[test]
+    fn append_signature() {
+        let mut bytes = Cursor::new(include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone").to_vec());
+
+        let message = read(&mut bytes).unwrap().signed_message().expect("signed message");
+
+        let signature = Signature {
+            key: vec![1; 32],
+            signature: vec![2; 64],
+        };
+        crate::write::append_signature(&mut bytes, std::slice::from_ref(&signature)).expect("append signature");
+
+        bytes.set_position(0);
+        let mut stone = read(&mut bytes).expect("valid stone");
+        assert_eq!(stone.signed_message().unwrap(), message);
+
+        let payloads = stone
+            .payloads()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("seek payloads");
+
+        assert!(payloads.iter().any(|p| p.content().is_some()));
+        assert_eq!(
+            payloads.iter().find_map(PayloadKind::signature).map(|p| &p.body),
+            Some(&vec![signature])
+        );
+    }
+
+    #[test]
+    fn reject_invalid_signature_length() {
+        use crate::payload::Record;
+
+        let mut record = vec![];
+        record.extend_from_slice(&u64::MAX.to_be_bytes());
+        record.extend_from_slice(&64u64.to_be_bytes());
+
+        assert!(matches!(
+            Signature::decode(record.as_slice()),
+            Err(payload::DecodeError::InvalidSignatureLength(u64::MAX, 64))
+        ));
+    }
 }
@@ -7,7 +7,7 @@ use thiserror::Error;
 
 use crate::{
     header,
-    payload::{self, Attribute, Index, Layout, Meta},
+    payload::{self, Attribute, Index, Layout, Meta, Signature},
     Header,
 };
 
@@ -158,6 +158,40 @@ where
     }
 }
 
+/// Append a signature payload to the stone in `file`, signing the message
+/// returned by [`crate::Reader::signed_message`]
+pub fn append_signature<F>(mut file: F, signatures: &[Signature]) -> Result<(), Error>
+where
+    F: Read + Write + Seek,
+{
+    file.seek(SeekFrom::Start(0))?;
+    let header = Header::decode(&mut file)?;
+
+    let payload = encode_payload(
+        InnerPayload::Signature(signatures),
+        &mut digest::Hasher::new(),
+        &mut zstd::Encoder::new()?,
+    )?;
+
+    file.seek(SeekFrom::End(0))?;
+    payload.header.encode(&mut file)?;
+    file.write_all(&payload.content)?;
+
+    // Account for the new payload in the stone header
+    let header = match header {
+        Header::V1(header) => Header::V1(header::v1::Header {
+            num_payloads: header.num_payloads + 1,
+            ..header
+        }),
+    };
+    file.seek(SeekFrom::Start(0))?;
+    header.encode(&mut file)?;
+
+    file.flush()?;
+
+    Ok(())
+}
+
 pub struct Content<B> {
     buffer: B,
     plain_size: u64,
@@ -195,12 +229,15 @@ impl<'a> From<Payload<'a>> for InnerPayload<'a> {
 
 /// Different from [`Payload`] so public API
 /// doesn't support passing in `Index` payloads
-/// since it's a side-effect of [`Writer::add_content`]
+/// since it's a side-effect of [`Writer::add_content`],
+/// nor `Signature` payloads which are appended once
+/// the stone is written via [`append_signature`]
 enum InnerPayload<'a> {
     Meta(&'a [Meta]),
     Attributes(&'a [Attribute]),
     Layout(&'a [Layout]),
     Index(&'a [Index]),
+    Signature(&'a [Signature]),
 }
 
 impl<'a> InnerPayload<'a> {
@@ -210,6 +247,7 @@ impl<'a> InnerPayload<'a> {
             InnerPayload::Attributes(records) => payload::records_total_size(records),
             InnerPayload::Layout(records) => payload::records_total_size(records),
             InnerPayload::Index(records) => payload::records_total_size(records),
+            InnerPayload::Signature(records) => payload::records_total_size(records),
         }
     }
 
@@ -219,6 +257,7 @@ impl<'a> InnerPayload<'a> {
             InnerPayload::Attributes(payload) => payload.len(),
             InnerPayload::Layout(payload) => payload.len(),
             InnerPayload::Index(payload) => payload.len(),
+            InnerPayload::Signature(payload) => payload.len(),
         }
     }
 
@@ -228,6 +267,7 @@ impl<'a> InnerPayload<'a> {
             InnerPayload::Attributes(records) => payload::encode_records(writer, records)?,
             InnerPayload::Layout(records) => payload::encode_records(writer, records)?,
             InnerPayload::Index(records) => payload::encode_records(writer, records)?,
+            InnerPayload::Signature(records) => payload::encode_records(writer, records)?,
         }
         Ok(())
     }
@@ -238,6 +278,7 @@ impl<'a> InnerPayload<'a> {
             InnerPayload::Attributes(_) => payload::Kind::Attributes,
             InnerPayload::Layout(_) => payload::Kind::Layout,
             InnerPayload::Index(_) => payload::Kind::Index,
+            InnerPayload::Signature(_) => payload::Kind::Signature,
         }
     }
 }
@@ -340,6 +381,8 @@ fn finalize<W: Write, B: Read + Seek>(
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("header decode")]
+    HeaderDecode(#[from] header::DecodeError),
     #[error("payload encode")]
     PayloadEncode(#[from] payload::EncodeError),
     #[error("io")]
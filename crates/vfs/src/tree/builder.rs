@@ -135,7 +135,7 @@ impl<T: BlitFile> TreeBuilder<T> {
 
 #[cfg(test)]
 mod tests {
-    use crate::tree::Kind;
+    use crate::tree::{Change, Kind};
 
     use super::{BlitFile, TreeBuilder};
 
@@ -215,4 +215,53 @@ mod tests {
         b.bake();
         b.tree().unwrap();
     }
+
+    #[test]
+    fn test_diff() {
+        let file = |path: &str, kind: Kind, id: &str| CustomFile {
+            path: path.into(),
+            kind,
+            id: id.into(),
+        };
+        let tree = |files: Vec<CustomFile>| {
+            let mut b: TreeBuilder<CustomFile> = TreeBuilder::new();
+            for file in files {
+                b.push(file);
+            }
+            b.bake();
+            b.tree().unwrap()
+        };
+
+        let old = tree(vec![
+            file("/usr/bin/nano", Kind::Regular, "nano-1"),
+            file("/usr/share/nano/nanorc", Kind::Regular, "nano-1"),
+            file("/usr/bin/vim", Kind::Regular, "vim"),
+        ]);
+        let new = tree(vec![
+            file("/usr/bin/nano", Kind::Regular, "nano-2"),
+            file("/usr/bin/vim", Kind::Regular, "vim"),
+            file("/usr/bin/vi", Kind::Symlink("vim".into()), "vim"),
+        ]);
+
+        let changes = old
+            .diff(&new, |a, b| a == b)
+            .into_iter()
+            .map(|change| match change {
+                Change::Removed(file) => format!("-{}", file.path),
+                Change::Added(file) => format!("+{}", file.path),
+                Change::Modified { new, .. } => format!("~{}", new.path),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            changes,
+            [
+                "-/usr/share/nano/nanorc",
+                "-/usr/share/nano",
+                "-/usr/share",
+                "~/usr/bin/nano",
+                "+/usr/bin/vi"
+            ]
+        );
+    }
 }
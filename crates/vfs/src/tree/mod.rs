@@ -205,6 +205,48 @@ impl<T: BlitFile> Tree<T> {
         }
     }
 
+    /// Changes turning this tree into `new`, where `same` decides if the
+    /// items found at the same path in both trees are interchangeable
+    ///
+    /// Removals come first with children before their parents, followed by
+    /// additions and modifications with parents before their children
+    pub fn diff<'a>(&'a self, new: &'a Tree<T>, same: impl Fn(&T, &T) -> bool) -> Vec<Change<'a, T>> {
+        let old_files = self
+            .files()
+            .map(|file| (file.path.as_str(), file))
+            .collect::<BTreeMap<_, _>>();
+        let new_files = new
+            .files()
+            .map(|file| (file.path.as_str(), file))
+            .collect::<BTreeMap<_, _>>();
+
+        let removed = self
+            .files()
+            .filter(|file| !new_files.contains_key(file.path.as_str()))
+            .map(|file| Change::Removed(&file.inner))
+            .collect::<Vec<_>>();
+
+        let changed = new.files().filter_map(|file| match old_files.get(file.path.as_str()) {
+            None => Some(Change::Added(&file.inner)),
+            Some(old) if !same(&old.inner, &file.inner) => Some(Change::Modified {
+                old: &old.inner,
+                new: &file.inner,
+            }),
+            Some(_) => None,
+        });
+
+        removed.into_iter().rev().chain(changed).collect()
+    }
+
+    /// Files attached to the tree in depth-first order, starting at `/`
+    fn files(&self) -> impl Iterator<Item = &File<T>> {
+        self.resolve_node("/")
+            .into_iter()
+            .flat_map(|root| root.descendants(&self.arena))
+            .filter_map(|node| self.arena.get(node))
+            .map(|node| node.get())
+    }
+
     /// Return structured view beginning at `/`
     pub fn structured(&self) -> Option<Element<T>> {
         self.resolve_node("/").map(|root| self.structured_children(root))
@@ -234,6 +276,17 @@ pub enum Element<'a, T: BlitFile> {
     Child(&'a str, &'a T),
 }
 
+/// A difference between two trees, see [`Tree::diff`]
+#[derive(Debug)]
+pub enum Change<'a, T: BlitFile> {
+    /// Only present in the old tree
+    Removed(&'a T),
+    /// Only present in the new tree
+    Added(&'a T),
+    /// Present in both trees, but not interchangeable
+    Modified { old: &'a T, new: &'a T },
+}
+
 /// Simple DFS iterator for a Tree
 pub struct TreeIterator<'a, T: BlitFile> {
     parent: &'a Tree<T>,
@@ -0,0 +1,39 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use clap::{ArgMatches, Command};
+use thiserror::Error;
+
+use moss::{
+    client::{self, Client},
+    environment, Installation,
+};
+
+use super::remove;
+
+pub fn command() -> Command {
+    Command::new("autoremove").about("Remove orphaned packages").long_about(
+        "Remove transitively installed packages which are no longer required by any explicitly installed package",
+    )
+}
+
+/// Handle execution of `moss autoremove`
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let yes = *args.get_one::<bool>("yes").unwrap();
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    remove::remove(&client, vec![], true, yes, "Autoremove")?;
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("remove")]
+    Remove(#[from] remove::Error),
+}
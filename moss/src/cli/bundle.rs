@@ -0,0 +1,235 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use fs_err as fs;
+use thiserror::Error;
+
+use moss::{
+    client::{self, Client},
+    environment, signing, Installation,
+};
+use tui::Styled;
+
+use super::index;
+
+pub fn command() -> Command {
+    Command::new("bundle")
+        .about("Manage offline bundles")
+        .long_about("Export packages from the active repositories for installing on systems without network access")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("create")
+                .about("Create an offline bundle")
+                .long_about(
+                    "Copy the requested packages and their full dependency closure into a directory, \
+                     indexed so it can be added as a file:// repository",
+                )
+                .arg(arg!(<NAME> ... "packages to bundle").value_parser(value_parser!(String)))
+                .arg(
+                    arg!(-o --output <DIR> "directory to write the bundle to")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--sign <KEY> "Sign the bundle index with the secret key file")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+}
+
+/// Handle execution of `moss bundle`
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    match args.subcommand() {
+        Some(("create", args)) => create(args, installation),
+        _ => unreachable!(),
+    }
+}
+
+/// Handle execution of `moss bundle create`
+fn create(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let pkgs = args
+        .get_many::<String>("NAME")
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let output = args.get_one::<PathBuf>("output").unwrap();
+    let secret_key = args
+        .get_one::<PathBuf>("sign")
+        .map(|path| signing::SecretKey::load(path))
+        .transpose()?;
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    let output = write_bundle(&client, &pkgs, output, secret_key)?;
+
+    println!(
+        "\nAdd the bundle as a repository with: moss repo add <NAME> file://{}",
+        output.join("stone.index").display().to_string().bold()
+    );
+
+    Ok(())
+}
+
+/// Copy `pkgs` and their dependency closure into `output` and index them,
+/// returning the canonical path of the bundle
+fn write_bundle(
+    client: &Client,
+    pkgs: &[&str],
+    output: &Path,
+    secret_key: Option<signing::SecretKey>,
+) -> Result<PathBuf, Error> {
+    let bundled = client.bundle(pkgs, output)?;
+
+    println!("Bundled {} packages\n", bundled.len());
+
+    let output = fs::canonicalize(output)?;
+
+    index::index(&output, true, false, secret_key)?;
+
+    Ok(output)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("bundle")]
+    Bundle(#[from] client::bundle::Error),
+
+    #[error("index")]
+    Index(#[from] index::Error),
+
+    #[error("signing key")]
+    Key(#[from] signing::Error),
+
+    #[error("io")]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use moss::{
+        package::{self, Flags},
+        registry::plugin::{self, Plugin},
+        runtime,
+        state::Selection,
+        Dependency, Package, Provider, Registry,
+    };
+    use sha2::{Digest, Sha256};
+    use stone::read::PayloadKind;
+    use url::Url;
+
+    use super::*;
+    use crate::cli::test::repository_plugin;
+
+    /// Metadata of version `version` of a package named `name`, depending on
+    /// the packages named `dependencies`
+    fn meta(name: &str, version: &str, dependencies: &[&str]) -> package::Meta {
+        package::Meta {
+            name: package::Name::from(name.to_string()),
+            version_identifier: version.to_string(),
+            source_release: 1,
+            build_release: 1,
+            dependencies: dependencies
+                .iter()
+                .map(|name| Dependency::from_name(name).unwrap())
+                .collect(),
+            providers: [Provider::from_name(name).unwrap()].into(),
+            ..Default::default()
+        }
+    }
+
+    /// Write the stone of a package named `name`, depending on the packages named
+    /// `dependencies`, into `dir` and return it as published by a repository
+    fn write_stone(dir: &Path, name: &str, dependencies: &[&str]) -> Package {
+        let mut meta = meta(name, "1", dependencies);
+
+        let path = dir.join(format!("{name}-1.stone"));
+        let mut out = fs::File::create(&path).unwrap();
+        let mut writer = stone::Writer::new(&mut out, stone::header::v1::FileType::Binary).unwrap();
+        writer.add_payload(meta.clone().to_stone_payload().as_slice()).unwrap();
+        writer.finalize().unwrap();
+
+        let content = fs::read(&path).unwrap();
+        meta.uri = Some(Url::from_file_path(&path).unwrap().to_string());
+        meta.hash = Some(hex::encode(Sha256::digest(&content)));
+        meta.download_size = Some(content.len() as u64);
+
+        Package {
+            id: package::Id::from(name.to_string()),
+            meta,
+            flags: Flags::new().with_available(),
+        }
+    }
+
+    #[test]
+    fn bundle_closure() {
+        let dir = std::env::temp_dir().join(format!("moss-bundle-{}", std::process::id()));
+        let (repo, root, output) = (dir.join("repo"), dir.join("root"), dir.join("bundle"));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&repo).unwrap();
+        fs::create_dir_all(&root).unwrap();
+
+        let packages = vec![
+            write_stone(&repo, "app", &["lib"]),
+            write_stone(&repo, "lib", &["base"]),
+            write_stone(&repo, "base", &[]),
+            write_stone(&repo, "other", &[]),
+        ];
+
+        let _guard = runtime::init();
+        let mut client = Client::new("test", Installation::open(&root, None).unwrap()).unwrap();
+
+        // An older build of base is installed, which no repository publishes anymore
+        let stale = package::Id::from("base-0".to_string());
+        client.install_db.add(stale.clone(), meta("base", "0", &[])).unwrap();
+        let state = client.state_db.add(&[Selection::explicit(stale)], None, None).unwrap();
+
+        client.registry = Registry::default();
+        client.registry.add_plugin(Plugin::Active(plugin::Active::new(
+            Some(state),
+            client.install_db.clone(),
+        )));
+        client.registry.add_plugin(repository_plugin("test", 0, packages));
+
+        let output = write_bundle(&client, &["app"], &output, None).unwrap();
+
+        // Every stone of the closure is copied, and nothing else
+        let mut stones = fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".stone"))
+            .collect::<Vec<_>>();
+        stones.sort();
+        assert_eq!(stones, ["app-1.stone", "base-1.stone", "lib-1.stone"]);
+
+        // The bundle is indexed as a repository of those stones
+        let mut index = fs::File::open(output.join("stone.index")).unwrap();
+        let payloads = stone::read(&mut index)
+            .unwrap()
+            .payloads()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let mut indexed = payloads
+            .iter()
+            .filter_map(PayloadKind::meta)
+            .map(|payload| package::Meta::from_stone_payload(&payload.body).unwrap())
+            .map(|meta| (meta.name.to_string(), meta.uri.unwrap()))
+            .collect::<Vec<_>>();
+        indexed.sort();
+        assert_eq!(
+            indexed,
+            ["app", "base", "lib"].map(|name| (name.to_string(), format!("{name}-1.stone")))
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use clap::{arg, ArgMatches, Command};
+use dag::Dag;
+use itertools::Itertools;
+use thiserror::Error;
+
+use moss::{
+    client::{self, Client},
+    environment, package,
+    package::Flags,
+    registry::transaction,
+    Installation, Package, Provider,
+};
+use tui::Styled;
+
+/// Which way dependency edges are followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Packages required by the target
+    Forward,
+    /// Packages requiring the target
+    Reverse,
+}
+
+pub fn command() -> Command {
+    Command::new("depends")
+        .about("Show the dependency tree of a package")
+        .long_about(
+            "Show the dependency tree of a package. Installed packages are shown as they \
+             are installed, otherwise dependencies are resolved against active repositories",
+        )
+        .arg(arg!(<NAME> "Package to query").value_parser(clap::value_parser!(String)))
+        .arg(arg!(-d --depth <DEPTH> "Maximum depth of the tree").value_parser(clap::value_parser!(usize)))
+}
+
+pub fn reverse_command() -> Command {
+    Command::new("rdepends")
+        .about("Show the reverse dependency tree of a package")
+        .long_about(
+            "Show the reverse dependency tree of a package. Installed packages are shown with \
+             their installed dependents, otherwise all available dependents are shown",
+        )
+        .arg(arg!(<NAME> "Package to query").value_parser(clap::value_parser!(String)))
+        .arg(arg!(-d --depth <DEPTH> "Maximum depth of the tree").value_parser(clap::value_parser!(usize)))
+}
+
+/// Handle execution of `moss depends` and `moss rdepends`
+pub fn handle(args: &ArgMatches, installation: Installation, direction: Direction) -> Result<(), Error> {
+    let name = args.get_one::<String>("NAME").unwrap();
+    let max_depth = args.get_one::<usize>("depth").copied();
+    let lookup = Provider::from_name(name).unwrap();
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    // Installed candidates take priority
+    let target = client
+        .registry
+        .by_provider(&lookup, Flags::default())
+        .next()
+        .ok_or_else(|| Error::NotFound(name.clone()))?;
+
+    let (graph, packages) = match (direction, target.flags.installed) {
+        (_, true) => {
+            let installed = client
+                .registry
+                .list_installed(Flags::default())
+                .map(|p| p.id)
+                .collect::<Vec<_>>();
+            let transaction = client.registry.transaction_with_installed(installed)?;
+            resolved(&transaction)
+        }
+        (Direction::Forward, false) => {
+            let mut transaction = client.registry.transaction()?;
+            transaction.add(vec![target.id.clone()])?;
+            resolved(&transaction)
+        }
+        (Direction::Reverse, false) => {
+            let available = client.registry.list_available(Flags::default()).collect::<Vec<_>>();
+            dependents(&target, available)
+        }
+    };
+
+    let tree = Tree {
+        graph: match direction {
+            Direction::Forward => graph,
+            Direction::Reverse => graph.transpose(),
+        },
+        packages,
+        direction,
+        max_depth,
+    };
+
+    println!("{}", label(&target));
+    tree.print(&target, "", 1, &mut BTreeSet::from([target.id.clone()]));
+
+    Ok(())
+}
+
+/// The dependency graph and packages of a resolved transaction
+fn resolved(transaction: &transaction::Transaction<'_>) -> (Dag<package::Id>, BTreeMap<package::Id, Package>) {
+    let graph = transaction.graph().clone();
+    let packages = graph
+        .iter_nodes()
+        .filter_map(|id| transaction.package(id))
+        .map(|package| (package.id.clone(), package.clone()))
+        .collect();
+
+    (graph, packages)
+}
+
+/// Build a dependency graph of all `available` packages which
+/// transitively depend on `target`
+fn dependents(target: &Package, available: Vec<Package>) -> (Dag<package::Id>, BTreeMap<package::Id, Package>) {
+    let mut graph = Dag::new();
+    let mut packages = BTreeMap::from([(target.id.clone(), target.clone())]);
+    let mut queue = VecDeque::from([target.clone()]);
+
+    graph.add_node_or_get_index(target.id.clone());
+
+    while let Some(dependency) = queue.pop_front() {
+        for dependent in available.iter().filter(|p| p.dependency_on(&dependency).is_some()) {
+            let a = graph.add_node_or_get_index(dependent.id.clone());
+            let b = graph.add_node_or_get_index(dependency.id.clone());
+            graph.add_edge(a, b);
+
+            if !packages.contains_key(&dependent.id) {
+                packages.insert(dependent.id.clone(), dependent.clone());
+                queue.push_back(dependent.clone());
+            }
+        }
+    }
+
+    (graph, packages)
+}
+
+struct Tree {
+    graph: Dag<package::Id>,
+    packages: BTreeMap<package::Id, Package>,
+    direction: Direction,
+    max_depth: Option<usize>,
+}
+
+impl Tree {
+    /// Print the children of `parent`, marking those already
+    /// printed elsewhere in the tree rather than expanding them again
+    fn print(&self, parent: &Package, prefix: &str, depth: usize, visited: &mut BTreeSet<package::Id>) {
+        if self.max_depth.is_some_and(|max| depth > max) {
+            return;
+        }
+        let Some(index) = self.graph.get_index(&parent.id) else {
+            return;
+        };
+
+        let children = self
+            .graph
+            .successors(index)
+            .filter_map(|id| self.packages.get(id))
+            .sorted_by_key(|p| p.meta.name.to_string())
+            .collect::<Vec<_>>();
+        let count = children.len();
+
+        for (i, child) in children.into_iter().enumerate() {
+            let last = i + 1 == count;
+            let (branch, indent) = if last { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+
+            let dependency = match self.direction {
+                Direction::Forward => parent.dependency_on(child),
+                Direction::Reverse => child.dependency_on(parent),
+            };
+            let via = dependency
+                .map(|dependency| format!(" ({dependency})").dim().to_string())
+                .unwrap_or_default();
+
+            let seen = !visited.insert(child.id.clone());
+            let marker = if seen { " (*)".dim().to_string() } else { String::new() };
+
+            println!("{prefix}{branch}{}{via}{marker}", label(child));
+
+            if !seen {
+                self.print(child, &format!("{prefix}{indent}"), depth + 1, visited);
+            }
+        }
+    }
+}
+
+fn label(package: &Package) -> String {
+    format!(
+        "{} {}-{}",
+        package.meta.name.to_string().bold(),
+        package.meta.version_identifier.clone().magenta(),
+        package.meta.source_release.to_string().dim()
+    )
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No such package {0}")]
+    NotFound(String),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("transaction")]
+    Transaction(#[from] transaction::Error),
+}
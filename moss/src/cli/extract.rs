@@ -9,9 +9,9 @@ use std::{
     path::PathBuf,
 };
 
-use clap::{arg, ArgMatches, Command};
+use clap::{arg, Arg, ArgAction, ArgMatches, Command};
 use fs_err::{create_dir_all, hard_link, remove_dir_all, remove_file, File};
-use moss::package::{self, MissingMetaFieldError};
+use moss::{package, signing, Installation};
 use stone::{payload::layout, read::PayloadKind};
 use thiserror::{self, Error};
 use tui::{ProgressBar, ProgressStyle};
@@ -21,16 +21,30 @@ pub fn command() -> Command {
         .about("Extract a `.stone` content to disk")
         .long_about("For all valid content-bearing archives, extract to disk")
         .arg(arg!(<PATH> ... "files to inspect").value_parser(clap::value_parser!(PathBuf)))
+        .arg(
+            Arg::new("trusted-key")
+                .short('k')
+                .long("trusted-key")
+                .help("Require the stones to be signed by this public key, can be passed multiple times")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(signing::PublicKey)),
+        )
 }
 
 /// Handle the `extract` command
-pub fn handle(args: &ArgMatches) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
     let paths = args
         .get_many::<PathBuf>("PATH")
         .into_iter()
         .flatten()
         .cloned()
         .collect::<Vec<_>>();
+    let signing = signing::Config::load(&config::Manager::system(&installation.root, "moss")).with_trusted_keys(
+        args.get_many::<signing::PublicKey>("trusted-key")
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
 
     // Begin unpack
     create_dir_all(".stoneStore")?;
@@ -40,6 +54,8 @@ pub fn handle(args: &ArgMatches) -> Result<(), Error> {
     for path in paths {
         println!("Extract: {:?}", path);
 
+        signing.check(&path)?;
+
         let rdr = File::open(path).map_err(Error::IO)?;
         let mut reader = stone::read(rdr).map_err(Error::Format)?;
 
@@ -141,11 +157,14 @@ pub enum Error {
     MissingMeta,
 
     #[error("malformed meta")]
-    MalformedMeta(#[from] MissingMetaFieldError),
+    MalformedMeta(#[from] package::meta::Error),
 
     #[error("io")]
     IO(#[from] std::io::Error),
 
     #[error("stone format")]
     Format(#[from] stone::read::Error),
+
+    #[error("signature")]
+    Signing(#[from] signing::Error),
 }
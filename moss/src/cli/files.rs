@@ -0,0 +1,78 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use clap::{arg, ArgMatches, Command};
+use thiserror::Error;
+
+use moss::{
+    client::{self, Client, PendingFile},
+    environment,
+    package::Flags,
+    Installation, Provider,
+};
+use stone::payload::layout;
+use tui::Styled;
+use vfs::tree::BlitFile;
+
+pub fn command() -> Command {
+    Command::new("files")
+        .about("List files of a package")
+        .long_about("List the layout entries of an installed package with their type, mode and target")
+        .arg(arg!(<NAME> "Installed package to list").value_parser(clap::value_parser!(String)))
+}
+
+/// Handle execution of `moss files`
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let name = args.get_one::<String>("NAME").unwrap();
+    let lookup = Provider::from_name(name).unwrap();
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    let package = client
+        .registry
+        .by_provider(&lookup, Flags::new().with_installed())
+        .next()
+        .ok_or_else(|| Error::NotInstalled(name.clone()))?;
+
+    let mut files = client
+        .layout_db
+        .query([&package.id])?
+        .into_iter()
+        .map(|(id, layout)| PendingFile { id, layout })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|file| file.path());
+
+    for file in files {
+        let (kind, target) = match &file.layout.entry {
+            layout::Entry::Regular(..) => ("regular", None),
+            layout::Entry::Symlink(source, _) => ("symlink", Some(source)),
+            layout::Entry::Directory(_) => ("directory", None),
+            layout::Entry::CharacterDevice(_) => ("character", None),
+            layout::Entry::BlockDevice(_) => ("block", None),
+            layout::Entry::Fifo(_) => ("fifo", None),
+            layout::Entry::Socket(_) => ("socket", None),
+        };
+        let mode = format!("{:04o}", file.layout.mode & 0o7777);
+
+        print!("{} {} {}", format!("{kind:9}").dim(), mode.dim(), file.path());
+        match target {
+            Some(target) => println!(" -> {target}"),
+            None => println!(),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} is not installed")]
+    NotInstalled(String),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("db")]
+    DB(#[from] moss::db::Error),
+}
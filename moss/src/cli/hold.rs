@@ -0,0 +1,167 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::PathBuf;
+
+use clap::{arg, ArgMatches, Command};
+use itertools::Itertools;
+use moss::{
+    client::{self, Client},
+    environment,
+    hold::{self, Hold},
+    package::{self, Flags},
+    repository, Installation,
+};
+use thiserror::Error;
+use tui::Styled;
+
+pub fn command() -> Command {
+    Command::new("hold")
+        .about("Manage package holds")
+        .long_about("Hold packages at their installed version, or pin them to a single repository, during sync")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("add")
+                .about("Hold a package")
+                .arg(arg!(<NAME> "package to hold").value_parser(clap::value_parser!(String)))
+                .arg(
+                    arg!(-r --repository <ID> "Sync the package from this repository only, instead of holding its version")
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Release a held package")
+                .arg(arg!(<NAME> "package to release").value_parser(clap::value_parser!(String))),
+        )
+        .subcommand(Command::new("list").about("List held packages"))
+}
+
+/// Handle subcommands to `hold`
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let config = config::Manager::system(&installation.root, "moss");
+    let client = Client::new(environment::NAME, installation)?;
+
+    match args.subcommand() {
+        Some(("add", args)) => add(
+            &client,
+            &config,
+            args.get_one::<String>("NAME").unwrap(),
+            args.get_one::<String>("repository").cloned(),
+        ),
+        Some(("remove", args)) => remove(&client, &config, args.get_one::<String>("NAME").unwrap()),
+        Some(("list", _)) => list(&client),
+        _ => unreachable!(),
+    }
+}
+
+/// Persist a hold on `name` to its own config file
+fn add(client: &Client, config: &config::Manager, name: &str, repository: Option<String>) -> Result<(), Error> {
+    let package_name = package::Name::from(name.to_string());
+    if client.registry.by_name(&package_name, Flags::new()).next().is_none() {
+        return Err(Error::UnknownPackage(name.to_string()));
+    }
+
+    let repository = repository.map(repository::Id::new);
+    if let Some(id) = &repository {
+        if !client.registry.repositories().any(|active| active == id) {
+            return Err(Error::UnknownRepository(id.clone()));
+        }
+    }
+
+    let hold = Hold { repository };
+    config.save(name, &hold::Map::with([(name.to_string(), hold.clone())]))?;
+
+    println!("{} {hold}", name.bold());
+
+    Ok(())
+}
+
+/// Release the hold on `name`, as saved by [`add`]
+///
+/// Holds configured in any other file, such as a vendor config, are left for
+/// the admin to remove as deleting those files may release other holds too.
+fn remove(client: &Client, config: &config::Manager, name: &str) -> Result<(), Error> {
+    if client.holds().get(&package::Name::from(name.to_string())).is_none() {
+        return Err(Error::NotHeld(name.to_string()));
+    }
+
+    let own_path = config.save_path::<hold::Map>(name);
+    let (own, others): (Vec<_>, Vec<_>) = config
+        .load_with_paths::<hold::Map>()
+        .into_iter()
+        .filter(|(_, holds)| holds.get(&package::Name::from(name.to_string())).is_some())
+        .partition(|(path, _)| *path == own_path);
+
+    if !others.is_empty() {
+        let paths = others.into_iter().map(|(path, _)| path).collect();
+        return Err(Error::HeldElsewhere(name.to_string(), paths));
+    }
+
+    // Keep any other holds added to the file by hand
+    match own.into_iter().next() {
+        Some((_, mut holds)) if holds.iter().count() > 1 => {
+            holds.remove(name);
+            config.save(name, &holds)?;
+        }
+        _ => config.delete::<hold::Map>(name)?,
+    }
+
+    println!("{} released", name.bold());
+
+    Ok(())
+}
+
+/// List held packages along with their installed version
+fn list(client: &Client) -> Result<(), Error> {
+    let holds = client.holds();
+    if holds.is_empty() {
+        println!("No packages are held");
+        return Ok(());
+    }
+
+    for (name, hold) in holds.iter() {
+        let installed = client
+            .registry
+            .by_name(&package::Name::from(name.clone()), Flags::new().with_installed())
+            .next();
+
+        match installed {
+            Some(package) => println!(
+                "{} {}-{} {}",
+                name.clone().bold(),
+                package.meta.version_identifier.magenta(),
+                package.meta.source_release.to_string().dim(),
+                format!("({hold})").dim()
+            ),
+            None => println!("{} {}", name.clone().bold(), format!("({hold}, not installed)").dim()),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no package named {0}")]
+    UnknownPackage(String),
+
+    #[error("no active repository named {0}")]
+    UnknownRepository(repository::Id),
+
+    #[error("{0} isn't held")]
+    NotHeld(String),
+
+    #[error("{0} is held by {}, remove it from there to release it", .1.iter().map(|path| format!("{path:?}")).join(", "))]
+    HeldElsewhere(String, Vec<PathBuf>),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("save config")]
+    SaveConfig(#[from] config::SaveError),
+
+    #[error("io")]
+    Io(#[from] std::io::Error),
+}
@@ -2,8 +2,8 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 use std::{
-    collections::{btree_map, BTreeMap},
-    io,
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Read, Seek, SeekFrom},
     path::{Path, PathBuf, StripPrefixError},
     time::Duration,
 };
@@ -12,10 +12,12 @@ use clap::{arg, value_parser, ArgMatches, Command};
 use fs_err as fs;
 use moss::{
     client,
-    package::{self, Meta, MissingMetaFieldError},
+    package::{self, Meta},
+    repository, signing,
 };
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use sha2::{Digest, Sha256};
+use stone::{payload::Layout, read::PayloadKind};
 use thiserror::Error;
 use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
 
@@ -24,12 +26,33 @@ pub fn command() -> Command {
         .visible_alias("ix")
         .about("Index a collection of packages")
         .arg(arg!(<INDEX_DIR> "directory of index files").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--files "Also write a file-list index for searching package contents"))
+        .arg(arg!(--deltas "Also write delta stones from the previous release of each package"))
+        .arg(
+            arg!(--sign <KEY> "Sign the written indexes with the secret key file").value_parser(value_parser!(PathBuf)),
+        )
 }
 
 pub fn handle(args: &ArgMatches) -> Result<(), Error> {
     let dir = args.get_one::<PathBuf>("INDEX_DIR").unwrap().canonicalize()?;
+    let with_files = args.get_flag("files");
+    let with_deltas = args.get_flag("deltas");
+    let secret_key = args
+        .get_one::<PathBuf>("sign")
+        .map(|path| signing::SecretKey::load(path))
+        .transpose()?;
+
+    index(&dir, with_files, with_deltas, secret_key)
+}
 
-    let stone_files = enumerate_stone_files(&dir)?;
+/// Index all stones found in `dir`, writing the indexes alongside them
+pub fn index(
+    dir: &Path,
+    with_files: bool,
+    with_deltas: bool,
+    secret_key: Option<signing::SecretKey>,
+) -> Result<(), Error> {
+    let stone_files = enumerate_stone_files(dir)?;
 
     println!("Indexing {} files\n", stone_files.len());
 
@@ -46,45 +69,99 @@ pub fn handle(args: &ArgMatches) -> Result<(), Error> {
 
     let list = stone_files
         .par_iter()
-        .map(|path| get_meta(path, &dir, &multi_progress, &total_progress))
+        .map(|path| get_meta(path, dir, with_files, &multi_progress, &total_progress))
         .collect::<Result<Vec<_>, _>>()?;
 
+    let mut releases = BTreeMap::<_, Vec<_>>::new();
+
+    // Group releases of each package, skipping deltas
+    // written by previous runs
+    for (meta, layouts) in list.into_iter().flatten() {
+        releases.entry(meta.name.clone()).or_default().push((meta, layouts));
+    }
+
     let mut map = BTreeMap::new();
 
-    // Add each meta to the map, removing
-    // dupes by keeping the latest release
-    for meta in list {
-        match map.entry(meta.name.clone()) {
-            btree_map::Entry::Vacant(entry) => {
-                entry.insert(meta);
-            }
-            btree_map::Entry::Occupied(mut entry) => {
-                match (entry.get().source_release, meta.source_release) {
-                    // Error if dupe is same version
-                    (prev, curr) if prev == curr => {
-                        return Err(Error::DuplicateRelease(meta.name.clone(), meta.source_release));
-                    }
-                    // Update if dupe is newer version
-                    (prev, curr) if prev < curr => {
-                        entry.insert(meta);
-                    }
-                    // Otherwise prev is more recent, don't replace
-                    _ => {}
-                }
+    // Index the latest release of each package
+    for (name, mut candidates) in releases {
+        candidates.sort_by(|(a, _), (b, _)| a.source_release.cmp(&b.source_release).reverse());
+
+        let mut candidates = candidates.into_iter();
+        let Some((mut meta, layouts)) = candidates.next() else {
+            continue;
+        };
+        let previous = candidates.next().map(|(meta, _)| meta);
+
+        // Error if dupe is same version
+        if previous
+            .as_ref()
+            .is_some_and(|previous| previous.source_release == meta.source_release)
+        {
+            return Err(Error::DuplicateRelease(name, meta.source_release));
+        }
+
+        if with_deltas {
+            if let Some(previous) = &previous {
+                total_progress.suspend(|| println!("{} {}", "Generating delta".blue(), name.to_string().bold()));
+                meta.delta = Some(write_delta(dir, previous, &meta, secret_key.as_ref())?);
             }
         }
+
+        map.insert(name, (meta, layouts));
+    }
+
+    if with_files {
+        write_files_index(dir, &map)?;
     }
+    if with_deltas {
+        write_deltas_index(dir, &map)?;
+    }
+    write_constraints_index(dir, &map)?;
 
-    write_index(&dir, map, &total_progress)?;
+    write_index(dir, map, &total_progress)?;
+
+    if let Some(key) = &secret_key {
+        sign(key, &dir.join("stone.index"))?;
+        if with_files {
+            sign(key, &dir.join(repository::FILES_INDEX))?;
+        }
+        if with_deltas {
+            sign(key, &dir.join(repository::DELTAS_INDEX))?;
+        }
+        sign(key, &dir.join(repository::CONSTRAINTS_INDEX))?;
+    }
 
     multi_progress.clear()?;
 
     println!("\nIndex file written to {:?}", dir.join("stone.index").display());
+    if with_files {
+        println!(
+            "File-list index written to {:?}",
+            dir.join(repository::FILES_INDEX).display()
+        );
+    }
+    if with_deltas {
+        println!(
+            "Deltas index written to {:?}",
+            dir.join(repository::DELTAS_INDEX).display()
+        );
+    }
+    println!(
+        "Constraints index written to {:?}",
+        dir.join(repository::CONSTRAINTS_INDEX).display()
+    );
+    if let Some(key) = secret_key {
+        println!("Signed with key {}", key.public_key());
+    }
 
     Ok(())
 }
 
-fn write_index(dir: &Path, map: BTreeMap<package::Name, Meta>, total_progress: &ProgressBar) -> Result<(), Error> {
+fn write_index(
+    dir: &Path,
+    map: BTreeMap<package::Name, (Meta, Vec<Layout>)>,
+    total_progress: &ProgressBar,
+) -> Result<(), Error> {
     total_progress.set_message("Writing index file");
     total_progress.set_style(
         ProgressStyle::with_template("\n {spinner} {wide_msg}")
@@ -97,9 +174,54 @@ fn write_index(dir: &Path, map: BTreeMap<package::Name, Meta>, total_progress: &
 
     let mut writer = stone::Writer::new(&mut file, stone::header::v1::FileType::Repository)?;
 
-    for (_, meta) in map {
-        let payload = meta.to_stone_payload();
+    for (_, (meta, _)) in map {
+        // Constraints are published in their own index
+        let payload = meta.without_constraints().to_stone_payload();
+        writer.add_payload(payload.as_slice())?;
+    }
+
+    writer.finalize()?;
+
+    Ok(())
+}
+
+/// Write the file-list index, where each package is identified by a meta payload
+/// holding its name & hash followed by the layout payload of its stone
+fn write_files_index(dir: &Path, map: &BTreeMap<package::Name, (Meta, Vec<Layout>)>) -> Result<(), Error> {
+    use stone::payload::meta::{Kind, Tag};
+
+    let mut file = fs::File::create(dir.join(repository::FILES_INDEX))?;
+
+    let mut writer = stone::Writer::new(&mut file, stone::header::v1::FileType::Repository)?;
+
+    for (name, (meta, layouts)) in map {
+        let payload = [
+            (Tag::Name, Kind::String(name.to_string())),
+            (Tag::PackageHash, Kind::String(meta.hash.clone().unwrap_or_default())),
+        ]
+        .map(|(tag, kind)| stone::payload::Meta { tag, kind });
+
         writer.add_payload(payload.as_slice())?;
+        writer.add_payload(layouts.as_slice())?;
+    }
+
+    writer.finalize()?;
+
+    Ok(())
+}
+
+/// Write the deltas index, holding a meta payload for each
+/// package which has a delta from its previous release
+fn write_deltas_index(dir: &Path, map: &BTreeMap<package::Name, (Meta, Vec<Layout>)>) -> Result<(), Error> {
+    let mut file = fs::File::create(dir.join(repository::DELTAS_INDEX))?;
+
+    let mut writer = stone::Writer::new(&mut file, stone::header::v1::FileType::Repository)?;
+
+    for (meta, _) in map.values() {
+        if let (Some(hash), Some(delta)) = (&meta.hash, &meta.delta) {
+            let payload = delta.clone().to_stone_payload(hash.clone());
+            writer.add_payload(payload.as_slice())?;
+        }
     }
 
     writer.finalize()?;
@@ -107,12 +229,165 @@ fn write_index(dir: &Path, map: BTreeMap<package::Name, Meta>, total_progress: &
     Ok(())
 }
 
+/// Write the constraints index, holding a meta payload for each
+/// package which has versioned dependencies
+fn write_constraints_index(dir: &Path, map: &BTreeMap<package::Name, (Meta, Vec<Layout>)>) -> Result<(), Error> {
+    let mut file = fs::File::create(dir.join(repository::CONSTRAINTS_INDEX))?;
+
+    let mut writer = stone::Writer::new(&mut file, stone::header::v1::FileType::Repository)?;
+
+    for payload in map.values().filter_map(|(meta, _)| meta.constraints_payload()) {
+        writer.add_payload(payload.as_slice())?;
+    }
+
+    writer.finalize()?;
+
+    Ok(())
+}
+
+/// Write a delta stone holding the meta & layout of the `to` release,
+/// but only the content which isn't part of the `from` release
+fn write_delta(
+    dir: &Path,
+    from: &Meta,
+    to: &Meta,
+    secret_key: Option<&signing::SecretKey>,
+) -> Result<package::meta::Delta, Error> {
+    let from_uri = from.uri.as_ref().ok_or(Error::MissingUri)?;
+    let to_uri = to.uri.as_ref().ok_or(Error::MissingUri)?;
+    let from_hash = from.hash.clone().ok_or(Error::MissingHash)?;
+    let uri = format!("{}.delta.stone", to_uri.trim_end_matches(".stone"));
+
+    let path = dir.join(&uri);
+    let content_path = path.with_extension("content.tmp");
+    let buffer_path = path.with_extension("buffer.tmp");
+
+    let result = write_delta_stone(
+        &dir.join(from_uri),
+        &dir.join(to_uri),
+        &path,
+        &content_path,
+        &buffer_path,
+    );
+
+    // Scratch files are never needed once the delta is written, or failed to be
+    let _ = fs::remove_file(&content_path);
+    let _ = fs::remove_file(&buffer_path);
+
+    if let Err(error) = result {
+        let _ = fs::remove_file(&path);
+        return Err(error);
+    }
+
+    if let Some(key) = secret_key {
+        signing::sign_stone(key, &path)?;
+    }
+
+    let (size, hash) = stat_file(&path, &uri, &ProgressBar::hidden())?;
+
+    Ok(package::meta::Delta {
+        from: package::Id::from(from_hash),
+        uri,
+        hash,
+        size,
+    })
+}
+
+/// Write the delta stone between the stones at `from` and `to` to `path`,
+/// unpacking content to `content_path` and compressing it to `buffer_path`
+fn write_delta_stone(
+    from: &Path,
+    to: &Path,
+    path: &Path,
+    content_path: &Path,
+    buffer_path: &Path,
+) -> Result<(), Error> {
+    // Digests of all content shipped by the previous release
+    let existing = {
+        let mut file = fs::File::open(from)?;
+        let mut reader = stone::read(&mut file)?;
+        let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
+
+        payloads
+            .iter()
+            .filter_map(PayloadKind::index)
+            .flat_map(|indices| &indices.body)
+            .map(|index| index.digest)
+            .collect::<BTreeSet<_>>()
+    };
+
+    let mut file = fs::File::open(to)?;
+    let mut reader = stone::read(&mut file)?;
+    let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
+
+    let mut out = fs::File::create(path)?;
+    let mut writer = stone::Writer::new(&mut out, stone::header::v1::FileType::Delta)?;
+
+    for meta in payloads.iter().filter_map(PayloadKind::meta) {
+        writer.add_payload(meta.body.as_slice())?;
+    }
+    for layouts in payloads.iter().filter_map(PayloadKind::layout) {
+        writer.add_payload(layouts.body.as_slice())?;
+    }
+
+    let changed = payloads
+        .iter()
+        .filter_map(PayloadKind::index)
+        .flat_map(|indices| &indices.body)
+        .filter(|index| !existing.contains(&index.digest))
+        .collect::<Vec<_>>();
+
+    match payloads.iter().find_map(PayloadKind::content) {
+        Some(content) if !changed.is_empty() => {
+            let mut unpacked = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(content_path)?;
+            reader.unpack_content(content, &mut unpacked)?;
+
+            let mut buffer = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(buffer_path)?;
+            let pledged_size = changed.iter().map(|index| index.end - index.start).sum();
+            let num_workers = std::thread::available_parallelism().map_or(1, |n| n.get() as u32);
+            let mut writer = writer.with_content(&mut buffer, Some(pledged_size), num_workers)?;
+
+            for index in changed {
+                unpacked.seek(SeekFrom::Start(index.start))?;
+                writer.add_content(&mut (&mut unpacked).take(index.end - index.start))?;
+            }
+
+            writer.finalize()?;
+        }
+        _ => writer.finalize()?,
+    }
+
+    Ok(())
+}
+
+/// Write a detached signature of the file at `path` next to it
+fn sign(key: &signing::SecretKey, path: &Path) -> Result<(), Error> {
+    let signature = key.sign(&fs::read(path)?);
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(format!(".{}", signing::SIGNATURE_EXTENSION));
+
+    fs::write(signature_path, format!("{signature}\n"))?;
+
+    Ok(())
+}
+
 fn get_meta(
     path: &Path,
     dir: &Path,
+    with_files: bool,
     multi_progress: &MultiProgress,
     total_progress: &ProgressBar,
-) -> Result<Meta, Error> {
+) -> Result<Option<(Meta, Vec<Layout>)>, Error> {
     let relative_path = format!("{}", path.strip_prefix(dir)?.display());
 
     let progress = multi_progress.insert_before(total_progress, ProgressBar::new_spinner());
@@ -129,6 +404,14 @@ fn get_meta(
 
     let mut file = fs::File::open(path)?;
     let mut reader = stone::read(&mut file)?;
+
+    if reader.header.file_type() == stone::header::v1::FileType::Delta {
+        progress.finish();
+        multi_progress.remove(&progress);
+        total_progress.inc(1);
+        return Ok(None);
+    }
+
     let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
 
     let payload = payloads
@@ -141,12 +424,22 @@ fn get_meta(
     meta.download_size = Some(size);
     meta.uri = Some(relative_path.clone());
 
+    let layouts = if with_files {
+        payloads
+            .iter()
+            .filter_map(|payload| payload.layout())
+            .flat_map(|payload| payload.body.clone())
+            .collect()
+    } else {
+        vec![]
+    };
+
     progress.finish();
     multi_progress.remove(&progress);
     multi_progress.suspend(|| println!("{} {}", "Indexed".green(), relative_path.bold()));
     total_progress.inc(1);
 
-    Ok(meta)
+    Ok(Some((meta, layouts)))
 }
 
 fn stat_file(path: &Path, relative_path: &str, progress: &ProgressBar) -> Result<(u64, String), Error> {
@@ -204,12 +497,197 @@ pub enum Error {
     #[error("meta payload missing")]
     MissingMetaPayload,
 
+    #[error("package uri missing")]
+    MissingUri,
+
+    #[error("package hash missing")]
+    MissingHash,
+
     #[error(transparent)]
-    MissingMetaField(#[from] MissingMetaFieldError),
+    Meta(#[from] package::meta::Error),
 
     #[error(transparent)]
     StripPrefix(#[from] StripPrefixError),
 
     #[error("client")]
     Client(#[from] client::Error),
+
+    #[error("signing key")]
+    Signing(#[from] signing::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use stone::payload::layout;
+    use xxhash_rust::xxh3::xxh3_128;
+
+    use super::*;
+
+    /// Write a stone at `dir/uri` shipping `files`, returning its meta
+    fn write_stone(dir: &Path, uri: &str, release: u64, files: &[(&str, &[u8])]) -> Meta {
+        let layouts = files
+            .iter()
+            .map(|(target, content)| Layout {
+                uid: 0,
+                gid: 0,
+                mode: 0o100644,
+                tag: 0,
+                entry: layout::Entry::Regular(xxh3_128(content), target.to_string()),
+            })
+            .collect::<Vec<_>>();
+
+        let mut out = fs::File::create(dir.join(uri)).unwrap();
+        let mut writer = stone::Writer::new(&mut out, stone::header::v1::FileType::Binary).unwrap();
+        writer.add_payload(layouts.as_slice()).unwrap();
+
+        let mut buffer = io::Cursor::new(vec![]);
+        let mut writer = writer.with_content(&mut buffer, None, 1).unwrap();
+        for (_, content) in files {
+            writer.add_content(&mut &content[..]).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (size, hash) = stat_file(&dir.join(uri), uri, &ProgressBar::hidden()).unwrap();
+
+        Meta {
+            name: package::Name::from("example".to_string()),
+            source_release: release,
+            uri: Some(uri.to_string()),
+            hash: Some(hash),
+            download_size: Some(size),
+            ..Default::default()
+        }
+    }
+
+    /// Unpack the content shipped by the stone at `path`, keyed by digest,
+    /// along with its layouts
+    fn unpack(path: &Path) -> (BTreeMap<u128, Vec<u8>>, Vec<Layout>) {
+        let mut file = fs::File::open(path).unwrap();
+        let mut reader = stone::read(&mut file).unwrap();
+        let payloads = reader.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+
+        let mut content = vec![];
+        if let Some(payload) = payloads.iter().find_map(PayloadKind::content) {
+            reader.unpack_content(payload, &mut content).unwrap();
+        }
+
+        let assets = payloads
+            .iter()
+            .filter_map(PayloadKind::index)
+            .flat_map(|indices| &indices.body)
+            .map(|index| (index.digest, content[index.start as usize..index.end as usize].to_vec()))
+            .collect();
+        let layouts = payloads
+            .iter()
+            .filter_map(PayloadKind::layout)
+            .flat_map(|layouts| layouts.body.clone())
+            .collect();
+
+        (assets, layouts)
+    }
+
+    #[test]
+    fn delta_reconstructs_package() {
+        let dir = std::env::temp_dir().join(format!("moss-index-delta-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let old: [(&str, &[u8]); 2] = [("share/example/same", b"unchanged"), ("share/example/changed", b"old")];
+        let files: [(&str, &[u8]); 2] = [("share/example/same", b"unchanged"), ("share/example/changed", b"new")];
+        let from = write_stone(&dir, "example-1.stone", 1, &old);
+        let to = write_stone(&dir, "example-2.stone", 2, &files);
+
+        let delta = write_delta(&dir, &from, &to, None).unwrap();
+
+        assert_eq!(delta.from, package::Id::from(from.hash.clone().unwrap()));
+        assert_eq!(delta.uri, "example-2.delta.stone");
+        assert_eq!(
+            (delta.size, delta.hash.clone()),
+            stat_file(&dir.join(&delta.uri), &delta.uri, &ProgressBar::hidden()).unwrap()
+        );
+
+        // Scratch files are cleaned up
+        let mut written = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        written.sort();
+        assert_eq!(written, ["example-1.stone", "example-2.delta.stone", "example-2.stone"]);
+
+        // Only the changed content is shipped by the delta, the rest
+        // comes from the release it applies to
+        let (mut assets, _) = unpack(&dir.join(from.uri.unwrap()));
+        let (changed, layouts) = unpack(&dir.join(&delta.uri));
+        assert_eq!(changed.keys().collect::<Vec<_>>(), [&xxh3_128(b"new")]);
+        assets.extend(changed);
+
+        let reconstructed = layouts
+            .into_iter()
+            .map(|layout| match layout.entry {
+                layout::Entry::Regular(digest, target) => {
+                    let content = assets.get(&digest).unwrap().clone();
+                    assert_eq!(xxh3_128(&content), digest);
+                    (target, content)
+                }
+                entry => panic!("unexpected entry {entry:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            reconstructed,
+            files.map(|(target, content)| (target.to_string(), content.to_vec()))
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn constraints_kept_from_stone_index() {
+        let dir = std::env::temp_dir().join(format!("moss-index-constraints-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let meta = Meta {
+            name: package::Name::from("example".to_string()),
+            hash: Some("hash".to_string()),
+            dependencies: ["name(zlib) >= 1.3", "name(bash)"]
+                .map(|dependency| dependency.parse().unwrap())
+                .into(),
+            ..Default::default()
+        };
+        let map = BTreeMap::from([(meta.name.clone(), (meta.clone(), vec![]))]);
+
+        write_constraints_index(&dir, &map).unwrap();
+        write_index(&dir, map, &ProgressBar::hidden()).unwrap();
+
+        let read = |name: &str| {
+            let mut file = fs::File::open(dir.join(name)).unwrap();
+            let payloads = stone::read(&mut file)
+                .unwrap()
+                .payloads()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            payloads
+                .iter()
+                .filter_map(PayloadKind::meta)
+                .map(|payload| payload.body.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Older clients can't read the versioned tag, so the stone index
+        // only lists dependencies by name
+        let [indexed] = read("stone.index").try_into().unwrap();
+        assert!(indexed
+            .iter()
+            .all(|meta| meta.tag != stone::payload::meta::Tag::VersionedDepends));
+        let indexed = Meta::from_stone_payload(&indexed).unwrap();
+        assert_eq!(indexed.dependencies, meta.clone().without_constraints().dependencies);
+
+        // Merging the constraints index restores them
+        let [constraints] = read(repository::CONSTRAINTS_INDEX).try_into().unwrap();
+        let (hash, constrained) = Meta::constraints_from_stone_payload(&constraints).unwrap();
+        assert_eq!(Some(hash), meta.hash);
+        assert_eq!(indexed.with_constraints(constrained).dependencies, meta.dependencies);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -2,8 +2,9 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use clap::{arg, ArgMatches, Command};
+use clap::{arg, Arg, ArgAction, ArgMatches, Command};
 use fs_err::File;
+use moss::{signing, Installation};
 use std::path::PathBuf;
 use stone::payload::layout;
 use stone::payload::meta;
@@ -17,21 +18,37 @@ pub fn command() -> Command {
         .about("Examine raw stone files")
         .long_about("Show detailed (debug) information on a local `.stone` file")
         .arg(arg!(<PATH> ... "files to inspect").value_parser(clap::value_parser!(PathBuf)))
+        .arg(
+            Arg::new("trusted-key")
+                .short('k')
+                .long("trusted-key")
+                .help("Require the stones to be signed by this public key, can be passed multiple times")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(signing::PublicKey)),
+        )
 }
 
 ///
 /// Inspect the given .stone files and print results
 ///
-pub fn handle(args: &ArgMatches) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
     let paths = args
         .get_many::<PathBuf>("PATH")
         .into_iter()
         .flatten()
         .cloned()
         .collect::<Vec<_>>();
+    let signing = signing::Config::load(&config::Manager::system(&installation.root, "moss")).with_trusted_keys(
+        args.get_many::<signing::PublicKey>("trusted-key")
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
 
     // Process each input path in order.
     for path in paths {
+        signing.check(&path)?;
+
         let mut file = File::open(&path)?;
         let mut reader = stone::read(&mut file)?;
 
@@ -132,4 +149,7 @@ pub enum Error {
 
     #[error("stone format")]
     Format(#[from] stone::read::Error),
+
+    #[error("signature")]
+    Signing(#[from] signing::Error),
 }
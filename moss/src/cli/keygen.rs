@@ -0,0 +1,38 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use moss::signing;
+use thiserror::Error;
+use tui::Styled;
+
+pub fn command() -> Command {
+    Command::new("keygen")
+        .about("Generate a signing key")
+        .long_about(
+            "Generate a keypair for signing repository indexes. The secret key is written to PATH \
+             and should be kept offline, the public key is printed for use in repository configs",
+        )
+        .arg(arg!(<PATH> "secret key file to create").value_parser(value_parser!(PathBuf)))
+}
+
+pub fn handle(args: &ArgMatches) -> Result<(), Error> {
+    let path = args.get_one::<PathBuf>("PATH").unwrap();
+
+    let key = signing::SecretKey::generate()?;
+    key.save(path)?;
+
+    println!("Secret key written to {:?}", path.display());
+    println!("{} {}", "Public key".bold(), key.public_key());
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("signing key")]
+    Signing(#[from] signing::Error),
+}
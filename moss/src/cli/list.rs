@@ -9,6 +9,7 @@ use thiserror::Error;
 use moss::{
     client::{self, Client},
     environment,
+    hold::Hold,
     package::Flags,
     Installation,
 };
@@ -81,14 +82,28 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         return Err(Error::NoneFound);
     }
 
+    let holds = client.holds();
+
     // map to renderable state
     let mut set = pkgs
         .into_iter()
         .map(|p| {
-            let sync = sync_available
-                .iter()
+            let hold = sync.as_ref().and_then(|_| holds.get(&p.meta.name));
+
+            let candidate = match hold {
+                // Held at the installed version
+                Some(Hold { repository: None }) => None,
+                Some(Hold {
+                    repository: Some(repository),
+                }) => client
+                    .registry
+                    .by_name_from(&p.meta.name, repository, Flags::new().with_available())
+                    .next(),
                 // Get first (priority based)
-                .find(|u| u.meta.name == p.meta.name)
+                None => sync_available.iter().find(|u| u.meta.name == p.meta.name).cloned(),
+            };
+
+            let sync = candidate
                 // Ensure it's an upgrade (if `upgrades-only`)
                 // otherwise check if it's a change
                 .filter(|u| {
@@ -110,6 +125,7 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
                     release: p.meta.source_release.to_string(),
                 },
                 summary: p.meta.summary,
+                hold: hold.map(Hold::to_string),
                 explicit: if filter_flags == Flags::new().with_installed() {
                     p.flags.explicit
                 } else {
@@ -118,7 +134,13 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
                 sync,
             }
         })
-        .filter(|item| if sync.is_some() { item.sync.is_some() } else { true })
+        .filter(|item| {
+            if sync.is_some() {
+                item.sync.is_some() || item.hold.is_some()
+            } else {
+                true
+            }
+        })
         .collect_vec();
 
     // Thanks to priorities, first in list is the winning candidate in list available.
@@ -157,6 +179,11 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
             print_revision(sync, true);
         }
 
+        // Print hold
+        if let Some(hold) = item.hold {
+            print!(" {}", format!("({hold})").dim());
+        }
+
         println!(" - {}", item.summary);
     }
 
@@ -170,11 +197,15 @@ struct Format {
     revision: Revision,
     explicit: bool,
     sync: Option<Revision>,
+    hold: Option<String>,
 }
 
 impl Format {
     fn size(&self) -> usize {
-        self.name.len() + self.revision.size() + self.sync.as_ref().map(Revision::size).unwrap_or_default()
+        self.name.len()
+            + self.revision.size()
+            + self.sync.as_ref().map(Revision::size).unwrap_or_default()
+            + self.hold.as_ref().map(|hold| hold.len() + 3).unwrap_or_default()
     }
 }
 
@@ -5,21 +5,30 @@
 use std::{env, path::PathBuf};
 
 use clap::{Arg, ArgAction, Command};
-use moss::{installation, runtime, Installation};
+use moss::{installation, request, runtime, Installation};
 use thiserror::Error;
 
+mod autoremove;
+mod bundle;
+mod depends;
 mod extract;
+mod files;
+mod hold;
 mod index;
 mod info;
 mod inspect;
 mod install;
+mod keygen;
 mod list;
+mod owns;
 mod remove;
 mod repo;
 mod search;
+mod search_file;
 mod state;
 mod sync;
 mod version;
+mod why;
 
 /// Generate the CLI command structure
 fn command() -> Command {
@@ -60,18 +69,28 @@ fn command() -> Command {
                 .action(ArgAction::SetTrue),
         )
         .arg_required_else_help(true)
+        .subcommand(autoremove::command())
+        .subcommand(bundle::command())
+        .subcommand(depends::command())
+        .subcommand(depends::reverse_command())
         .subcommand(extract::command())
+        .subcommand(files::command())
+        .subcommand(hold::command())
         .subcommand(index::command())
         .subcommand(info::command())
         .subcommand(inspect::command())
         .subcommand(install::command())
+        .subcommand(keygen::command())
         .subcommand(list::command())
+        .subcommand(owns::command())
         .subcommand(remove::command())
         .subcommand(repo::command())
         .subcommand(search::command())
+        .subcommand(search_file::command())
         .subcommand(state::command())
         .subcommand(sync::command())
         .subcommand(version::command())
+        .subcommand(why::command())
 }
 
 /// Process all CLI arguments
@@ -96,18 +115,37 @@ pub fn process() -> Result<(), Error> {
 
     let installation = Installation::open(root, cache.cloned())?;
 
+    request::configure(request::Config::load(&config::Manager::system(
+        &installation.root,
+        "moss",
+    )));
+
     match matches.subcommand() {
-        Some(("extract", args)) => extract::handle(args).map_err(Error::Extract),
+        Some(("autoremove", args)) => autoremove::handle(args, installation).map_err(Error::Autoremove),
+        Some(("bundle", args)) => bundle::handle(args, installation).map_err(Error::Bundle),
+        Some(("depends", args)) => {
+            depends::handle(args, installation, depends::Direction::Forward).map_err(Error::Depends)
+        }
+        Some(("rdepends", args)) => {
+            depends::handle(args, installation, depends::Direction::Reverse).map_err(Error::Depends)
+        }
+        Some(("extract", args)) => extract::handle(args, installation).map_err(Error::Extract),
+        Some(("files", args)) => files::handle(args, installation).map_err(Error::Files),
+        Some(("hold", args)) => hold::handle(args, installation).map_err(Error::Hold),
         Some(("index", args)) => index::handle(args).map_err(Error::Index),
         Some(("info", args)) => info::handle(args, installation).map_err(Error::Info),
-        Some(("inspect", args)) => inspect::handle(args).map_err(Error::Inspect),
+        Some(("inspect", args)) => inspect::handle(args, installation).map_err(Error::Inspect),
         Some(("install", args)) => install::handle(args, installation).map_err(Error::Install),
+        Some(("keygen", args)) => keygen::handle(args).map_err(Error::Keygen),
         Some(("list", args)) => list::handle(args, installation).map_err(Error::List),
+        Some(("owns", args)) => owns::handle(args, installation).map_err(Error::Owns),
         Some(("remove", args)) => remove::handle(args, installation).map_err(Error::Remove),
         Some(("repo", args)) => repo::handle(args, installation).map_err(Error::Repo),
         Some(("search", args)) => search::handle(args, installation).map_err(Error::Search),
+        Some(("search-file", args)) => search_file::handle(args, installation).map_err(Error::SearchFile),
         Some(("state", args)) => state::handle(args, installation).map_err(Error::State),
         Some(("sync", args)) => sync::handle(args, installation).map_err(Error::Sync),
+        Some(("why", args)) => why::handle(args, installation).map_err(Error::Why),
         Some(("version", args)) => {
             version::handle(args);
             Ok(())
@@ -155,6 +193,15 @@ fn replace_aliases(args: env::Args) -> Vec<String> {
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("autoremove")]
+    Autoremove(#[from] autoremove::Error),
+
+    #[error("bundle")]
+    Bundle(#[from] bundle::Error),
+
+    #[error("depends")]
+    Depends(#[from] depends::Error),
+
     #[error("index")]
     Index(#[from] index::Error),
 
@@ -164,6 +211,9 @@ pub enum Error {
     #[error("install")]
     Install(#[from] install::Error),
 
+    #[error("keygen")]
+    Keygen(#[from] keygen::Error),
+
     #[error("list")]
     List(#[from] list::Error),
 
@@ -173,6 +223,15 @@ pub enum Error {
     #[error("extract")]
     Extract(#[from] extract::Error),
 
+    #[error("files")]
+    Files(#[from] files::Error),
+
+    #[error("hold")]
+    Hold(#[from] hold::Error),
+
+    #[error("owns")]
+    Owns(#[from] owns::Error),
+
     #[error("remove")]
     Remove(#[from] remove::Error),
 
@@ -182,12 +241,51 @@ pub enum Error {
     #[error("search")]
     Search(#[from] search::Error),
 
+    #[error("search-file")]
+    SearchFile(#[from] search_file::Error),
+
     #[error("state")]
     State(#[from] state::Error),
 
     #[error("sync")]
     Sync(#[from] sync::Error),
 
+    #[error("why")]
+    Why(#[from] why::Error),
+
     #[error("installation")]
     Installation(#[from] installation::Error),
 }
+
+/// Fixtures shared by the tests of each command
+#[cfg(test)]
+pub(crate) mod test {
+    use moss::{
+        db,
+        registry::plugin::{self, Plugin},
+        repository::{self, Priority},
+        Package,
+    };
+
+    /// A repository plugin `id` with `priority`, publishing `packages`
+    pub fn repository_plugin(id: &str, priority: u64, packages: Vec<Package>) -> Plugin {
+        let db = db::meta::Database::new(":memory:").unwrap();
+        db.batch_add(packages.into_iter().map(|package| (package.id, package.meta)).collect())
+            .unwrap();
+
+        Plugin::Repository(plugin::Repository::new(repository::Cached {
+            id: repository::Id::new(id.to_string()),
+            repository: moss::Repository {
+                description: String::default(),
+                uri: "https://example.com/stone.index".parse().unwrap(),
+                mirrors: vec![],
+                priority: Priority::new(priority),
+                active: true,
+                trusted_keys: vec![],
+                stone_signatures: Default::default(),
+                credentials: None,
+            },
+            db,
+        }))
+    }
+}
@@ -0,0 +1,88 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::collections::BTreeMap;
+
+use clap::{arg, ArgMatches, Command};
+use thiserror::Error;
+
+use moss::{
+    client::{self, Client},
+    environment,
+    package::Flags,
+    Installation,
+};
+use tui::Styled;
+
+pub fn command() -> Command {
+    Command::new("owns")
+        .about("Find the package owning a path")
+        .long_about("Query which installed package(s) provide the given absolute paths")
+        .arg(arg!(<PATH> ... "Paths to query").value_parser(clap::value_parser!(String)))
+}
+
+/// Handle execution of `moss owns`
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let paths = args
+        .get_many::<String>("PATH")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    // Layouts are retained for cached packages which aren't installed
+    let installed = client
+        .registry
+        .list_installed(Flags::default())
+        .map(|p| (p.id.clone(), p))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut unowned = 0;
+
+    for path in paths {
+        let lookup = client::canonical_path(&path);
+
+        let owners = client
+            .layout_db
+            .owners(&lookup)?
+            .into_iter()
+            .filter_map(|(id, _)| installed.get(&id))
+            .collect::<Vec<_>>();
+
+        if owners.is_empty() {
+            println!("{lookup} is not owned by any installed package");
+            unowned += 1;
+            continue;
+        }
+
+        for owner in owners {
+            println!(
+                "{lookup} is owned by {} {}-{}",
+                owner.meta.name.to_string().bold(),
+                owner.meta.version_identifier.clone().magenta(),
+                owner.meta.source_release.to_string().dim(),
+            );
+        }
+    }
+
+    if unowned > 0 {
+        return Err(Error::NotOwned(unowned));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} path(s) not owned by any installed package")]
+    NotOwned(usize),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("db")]
+    DB(#[from] moss::db::Error),
+}
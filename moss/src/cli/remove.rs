@@ -10,7 +10,7 @@ use thiserror::Error;
 use moss::{
     client::{self, Client},
     environment,
-    package::Flags,
+    package::{self, Flags},
     registry::transaction,
     state::Selection,
     Installation, Provider,
@@ -27,6 +27,7 @@ pub fn command() -> Command {
         .about("Remove packages")
         .long_about("Remove packages by name")
         .arg(arg!(<NAME> ... "packages to install").value_parser(clap::value_parser!(String)))
+        .arg(arg!(--autoremove "Also remove packages no longer required by an explicitly installed package"))
 }
 
 /// Handle execution of `moss remove`
@@ -38,12 +39,12 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         .map(|name| Provider::from_name(name).unwrap())
         .collect::<Vec<_>>();
     let yes = *args.get_one::<bool>("yes").unwrap();
+    let autoremove = args.get_flag("autoremove");
 
     // Grab a client for the target, enumerate packages
     let client = Client::new(environment::NAME, installation)?;
 
     let installed = client.registry.list_installed(Flags::default()).collect::<Vec<_>>();
-    let installed_ids = installed.iter().map(|p| p.id.clone()).collect::<BTreeSet<_>>();
 
     // Separate packages between installed / not installed (or invalid)
     let (for_removal, not_installed): (Vec<_>, Vec<_>) = pkgs.iter().partition_map(|provider| {
@@ -61,13 +62,48 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         return Err(Error::NoSuchPackage);
     }
 
+    remove(&client, for_removal, autoremove, yes, "Remove")
+}
+
+/// Remove `packages` and their reverse dependencies from the active state, recording
+/// the result as a new state. With `autoremove`, any packages no longer required by an
+/// explicitly installed package are also removed.
+pub fn remove(
+    client: &Client,
+    packages: Vec<package::Id>,
+    autoremove: bool,
+    yes: bool,
+    summary: &str,
+) -> Result<(), Error> {
+    let installed_ids = client
+        .registry
+        .list_installed(Flags::default())
+        .map(|p| p.id)
+        .collect::<BTreeSet<_>>();
+    let previous_selections = match client.installation.active_state {
+        Some(id) => client.state_db.get(id)?.selections,
+        None => vec![],
+    };
+
     // Add all installed packages to transaction
     let mut transaction = client
         .registry
         .transaction_with_installed(installed_ids.clone().into_iter().collect())?;
 
     // Remove all pkgs for removal
-    transaction.remove(for_removal);
+    transaction.remove(packages);
+
+    // Drop transitive packages left behind, unless nothing is marked
+    // explicit as then everything would be considered orphaned
+    let explicit = previous_selections
+        .iter()
+        .filter(|s| s.explicit)
+        .map(|s| s.package.clone())
+        .collect::<Vec<_>>();
+    if autoremove && !explicit.is_empty() {
+        let orphans = transaction.orphans(&explicit);
+        transaction.remove(orphans);
+    }
 
     // Finalized tx has all reverse deps removed
     let finalized = transaction.finalize().cloned().collect::<BTreeSet<_>>();
@@ -75,6 +111,11 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     // Resolve all removed packages, where removed is (installed - finalized)
     let removed = client.resolve_packages(installed_ids.difference(&finalized))?;
 
+    if removed.is_empty() {
+        println!("No packages to remove");
+        return Ok(());
+    }
+
     println!("The following package(s) will be removed:");
     println!();
     autoprint_columns(&removed);
@@ -100,11 +141,6 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     // Map finalized state to a [`Selection`] by referencing
     // it's value from the previous state
     let new_state_pkgs = {
-        let previous_selections = match client.installation.active_state {
-            Some(id) => client.state_db.get(id)?.selections,
-            None => vec![],
-        };
-
         finalized
             .into_iter()
             .map(|id| {
@@ -117,18 +153,14 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
                     .unwrap_or_else(|| {
                         eprintln!("Unreachable: previous selection not found during removal for package {id:?}, marking as not explicit");
 
-                        Selection {
-                            package: id,
-                            explicit: false,
-                            reason: None,
-                        }
+                        Selection::transitive(id)
                     })
             })
             .collect::<Vec<_>>()
     };
 
     // Apply state
-    client.new_state(&new_state_pkgs, "Remove")?;
+    client.new_state(&new_state_pkgs, summary)?;
 
     Ok(())
 }
@@ -2,13 +2,13 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::process;
+use std::{path::PathBuf, process};
 
 use clap::{arg, Arg, ArgAction, ArgMatches, Command};
 use itertools::Itertools;
 use moss::{
     repository::{self, Priority},
-    runtime, Installation, Repository,
+    request, runtime, signing, Installation, Repository,
 };
 use thiserror::Error;
 use tui::Styled;
@@ -18,8 +18,8 @@ use url::Url;
 enum Action {
     // Root
     List,
-    // Root, Id, Url, Comment
-    Add(String, Url, String, Priority),
+    // Root, Id, Repository
+    Add(String, Box<Repository>),
     // Root, Id
     Remove(String),
     // Root, Id
@@ -54,6 +54,55 @@ pub fn command() -> Command {
                         .action(ArgAction::Set)
                         .default_value("0")
                         .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("mirror")
+                        .short('m')
+                        .long("mirror")
+                        .help("Mirror of the repository index to fall back to, can be passed multiple times")
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(Url)),
+                )
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .help("Public key trusted to sign the repository index, can be passed multiple times")
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(signing::PublicKey)),
+                )
+                .arg(
+                    Arg::new("username")
+                        .long("username")
+                        .help("Authenticate to the repository as this user")
+                        .requires("password-file")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("password-file")
+                        .long("password-file")
+                        .help("Root-only file holding the password of --username")
+                        .requires("username")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("token-file")
+                        .long("token-file")
+                        .help("Root-only file holding a bearer token to authenticate to the repository with")
+                        .conflicts_with("username")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("stone-signatures")
+                        .short('s')
+                        .long("stone-signatures")
+                        .help("How signatures on stones from the repository are checked: ignore, verify or require")
+                        .action(ArgAction::Set)
+                        .default_value("ignore")
+                        .value_parser(clap::value_parser!(repository::SignaturePolicy)),
                 ),
         )
         .subcommand(
@@ -96,9 +145,31 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     let handler = match args.subcommand() {
         Some(("add", cmd_args)) => Action::Add(
             cmd_args.get_one::<String>("NAME").cloned().unwrap(),
-            cmd_args.get_one::<Url>("URI").cloned().unwrap(),
-            cmd_args.get_one::<String>("comment").cloned().unwrap(),
-            Priority::new(*cmd_args.get_one::<u64>("priority").unwrap()),
+            Box::new(Repository {
+                description: cmd_args.get_one::<String>("comment").cloned().unwrap(),
+                uri: cmd_args.get_one::<Url>("URI").cloned().unwrap(),
+                mirrors: cmd_args
+                    .get_many::<Url>("mirror")
+                    .into_iter()
+                    .flatten()
+                    .map(|uri| repository::Mirror {
+                        uri: uri.clone(),
+                        weight: 0,
+                    })
+                    .collect(),
+                priority: Priority::new(*cmd_args.get_one::<u64>("priority").unwrap()),
+                active: true,
+                trusted_keys: cmd_args
+                    .get_many::<signing::PublicKey>("key")
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect(),
+                stone_signatures: *cmd_args
+                    .get_one::<repository::SignaturePolicy>("stone-signatures")
+                    .unwrap(),
+                credentials: credentials(cmd_args),
+            }),
         ),
         Some(("list", _)) => Action::List,
         Some(("remove", cmd_args)) => Action::Remove(cmd_args.get_one::<String>("NAME").cloned().unwrap()),
@@ -111,7 +182,7 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     // dispatch to runtime handler function
     match handler {
         Action::List => list(installation, config),
-        Action::Add(name, uri, comment, priority) => add(installation, config, name, uri, comment, priority),
+        Action::Add(name, repository) => add(installation, config, name, *repository),
         Action::Remove(name) => remove(installation, config, name),
         Action::Update(name) => update(installation, config, name),
         Action::Enable(name) => enable(installation, config, name),
@@ -120,27 +191,12 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
 }
 
 // Actual implementation of moss repo add
-fn add(
-    installation: Installation,
-    config: config::Manager,
-    name: String,
-    uri: Url,
-    comment: String,
-    priority: Priority,
-) -> Result<(), Error> {
+fn add(installation: Installation, config: config::Manager, name: String, repository: Repository) -> Result<(), Error> {
     let mut manager = repository::Manager::system(config, installation)?;
 
     let id = repository::Id::new(name);
 
-    manager.add_repository(
-        id.clone(),
-        Repository {
-            description: comment,
-            uri,
-            priority,
-            active: true,
-        },
-    )?;
+    manager.add_repository(id.clone(), repository)?;
 
     runtime::block_on(manager.refresh(&id))?;
 
@@ -166,19 +222,60 @@ fn list(installation: Installation, config: config::Manager) -> Result<(), Error
             String::new()
         };
 
-        println!(" - {} = {} [{}]{}", id, repo.uri, repo.priority, disabled);
+        let mirrors = if !repo.mirrors.is_empty() {
+            format!(" ({} mirrors)", repo.mirrors.len()).dim().to_string()
+        } else {
+            String::new()
+        };
+
+        let signed = if !repo.trusted_keys.is_empty() {
+            " (signed)".dim().to_string()
+        } else {
+            String::new()
+        };
+
+        println!(
+            " - {} = {} [{}]{}{}{}",
+            id, repo.uri, repo.priority, mirrors, signed, disabled
+        );
     }
 
     Ok(())
 }
 
+/// Credentials passed to `repo add`, if any
+fn credentials(args: &ArgMatches) -> Option<request::Credentials> {
+    if let Some(token_file) = args.get_one::<PathBuf>("token-file") {
+        return Some(request::Credentials::Bearer {
+            token_file: token_file.clone(),
+        });
+    }
+
+    let username = args.get_one::<String>("username")?;
+    let password_file = args.get_one::<PathBuf>("password-file")?;
+
+    Some(request::Credentials::Basic {
+        username: username.clone(),
+        password_file: password_file.clone(),
+    })
+}
+
 /// Update specific repos or all
 fn update(installation: Installation, config: config::Manager, which: Option<String>) -> Result<(), Error> {
     let mut manager = repository::Manager::system(config, installation)?;
 
     runtime::block_on(async {
         match which {
-            Some(repo) => manager.refresh(&repository::Id::new(repo)).await,
+            Some(repo) => {
+                let id = repository::Id::new(repo);
+
+                match manager.refresh(&id).await? {
+                    repository::manager::Refresh::Updated => println!("{} {id}", "Refreshed".green()),
+                    repository::manager::Refresh::Unchanged => println!("{} {id}", "Unchanged".dim()),
+                }
+
+                Ok(())
+            }
             None => manager.refresh_all().await,
         }
     })?;
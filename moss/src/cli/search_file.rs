@@ -0,0 +1,70 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use clap::{arg, ArgMatches, Command};
+use thiserror::Error;
+
+use moss::{
+    client::{self, Client},
+    environment, Installation,
+};
+use tui::Styled;
+
+pub fn command() -> Command {
+    Command::new("search-file")
+        .visible_alias("sf")
+        .about("Search files of available packages")
+        .long_about(
+            "Search the file-list indexes of active repositories for packages shipping matching paths.\n\n\
+             Patterns without a `/` match file names, otherwise absolute paths, i.e. `rg` or `/usr/bin/r*`",
+        )
+        .arg(arg!(<PATTERN> "Glob pattern to search for").value_parser(clap::value_parser!(String)))
+}
+
+/// Handle execution of `moss search-file`
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let pattern = args.get_one::<String>("PATTERN").unwrap();
+    let pattern = pattern.parse::<fnmatch::Pattern>()?;
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    let mut found = client.search_files(&pattern)?;
+
+    if found.is_empty() {
+        return Err(Error::NoneFound);
+    }
+
+    found.sort_by_key(|(package, path)| (package.meta.name.to_string(), package.id.clone(), path.clone()));
+
+    // Group matched paths under their package
+    let mut current = None;
+
+    for (package, path) in found {
+        if current.as_ref() != Some(&package.id) {
+            println!(
+                "{} {}-{}",
+                package.meta.name.to_string().bold(),
+                package.meta.version_identifier.clone().magenta(),
+                package.meta.source_release.to_string().dim(),
+            );
+            current = Some(package.id);
+        }
+
+        println!("  {path}");
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no matching files found")]
+    NoneFound,
+
+    #[error("invalid pattern")]
+    Pattern(#[from] fnmatch::Error),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+}
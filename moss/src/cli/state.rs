@@ -2,13 +2,17 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use clap::{arg, ArgAction, ArgMatches, Command};
+use std::{cmp::Ordering, collections::BTreeMap, path::PathBuf};
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use fs_err as fs;
 use moss::{
-    client::{self, prune, Client},
-    environment, state, Installation,
+    client::{self, lockfile::Lockfile, prune, Client, PendingFile},
+    dependency, environment, package, state, Installation,
 };
 use thiserror::Error;
 use tui::Styled;
+use vfs::tree::Change;
 
 pub fn command() -> Command {
     Command::new("state")
@@ -19,11 +23,60 @@ pub fn command() -> Command {
         .subcommand(Command::new("list").about("List all states"))
         .subcommand(
             Command::new("activate").about("Activate a state").arg(
-                arg!(<ID> "State id to be activated")
+                arg!(<ID> "State id or tag to be activated")
                     .action(ArgAction::Set)
-                    .value_parser(clap::value_parser!(u64)),
+                    .value_parser(clap::value_parser!(String)),
             ),
         )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two states")
+                .long_about("Show the packages added, removed, upgraded, downgraded or re-selected between two states")
+                .arg(
+                    arg!(<FROM> "State id or tag to compare from")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    arg!(<TO> "State id or tag to compare to")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(arg!(--files "Also list the paths which changed").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export a state as a lockfile")
+                .long_about("Write the exact package IDs and explicit flags of a state to a YAML lockfile")
+                .arg(
+                    arg!(<ID> "State id or tag to be exported")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    arg!(-o --output <FILE> "Write the lockfile to this file instead of stdout")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Apply a lockfile as a new state")
+                .long_about(
+                    "Reproduce the exact selections of a lockfile created by `state export`, \
+                     failing if any package is no longer available or doesn't match the \
+                     recorded version",
+                )
+                .arg(arg!(<FILE> "YAML or JSON lockfile to apply").value_parser(value_parser!(PathBuf)))
+                .arg(
+                    arg!(--to <blit_target> "Blit the lockfile to the provided directory instead of the root")
+                        .long_help(
+                            "Blit the lockfile to the provided directory instead of the root. \n\
+                             \n\
+                             This operation won't be captured as a new state",
+                        )
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
         .subcommand(
             Command::new("prune")
                 .about("Prune archived states")
@@ -40,9 +93,39 @@ pub fn command() -> Command {
         )
         .subcommand(
             Command::new("remove").about("Remove an archived state").arg(
-                arg!(<ID> "State id to be removed")
+                arg!(<ID> "State id or tag to be removed")
                     .action(ArgAction::Set)
-                    .value_parser(clap::value_parser!(u64)),
+                    .value_parser(clap::value_parser!(String)),
+            ),
+        )
+        .subcommand(
+            Command::new("tag")
+                .about("Name a state")
+                .long_about(
+                    "Name a state, protecting it from `state prune`. \
+                     The name can be used wherever a state id is accepted",
+                )
+                .arg(
+                    arg!(<ID> "State id or tag to be named")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    arg!(<NAME> "Name for the state")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    arg!(--note <NOTE> "Describe the state")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("untag").about("Remove the name of a state").arg(
+                arg!(<ID> "State id or tag to be unnamed")
+                    .action(ArgAction::Set)
+                    .value_parser(clap::value_parser!(String)),
             ),
         )
         .subcommand(
@@ -57,8 +140,13 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         Some(("active", _)) => active(installation),
         Some(("list", _)) => list(installation),
         Some(("activate", args)) => activate(args, installation),
+        Some(("diff", args)) => diff(args, installation),
+        Some(("export", args)) => export(args, installation),
+        Some(("apply", args)) => apply(args, installation),
         Some(("prune", args)) => prune(args, installation),
         Some(("remove", args)) => remove(args, installation),
+        Some(("tag", args)) => tag(args, installation),
+        Some(("untag", args)) => untag(args, installation),
         Some(("verify", args)) => verify(args, installation),
         _ => unreachable!(),
     }
@@ -94,10 +182,10 @@ pub fn list(installation: Installation) -> Result<(), Error> {
 }
 
 pub fn activate(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
-    let new_id = *args.get_one::<u64>("ID").unwrap() as i32;
-
     let client = Client::new(environment::NAME, installation)?;
-    let old_id = client.activate_state(new_id.into())?;
+
+    let new_id = resolve(&client, args.get_one::<String>("ID").unwrap())?;
+    let old_id = client.activate_state(new_id)?;
 
     println!(
         "State {} activated {}",
@@ -108,6 +196,173 @@ pub fn activate(args: &ArgMatches, installation: Installation) -> Result<(), Err
     Ok(())
 }
 
+/// Compare the selections, and optionally the files, of two states
+pub fn diff(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let files = args.get_flag("files");
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    let from = client
+        .state_db
+        .get(resolve(&client, args.get_one::<String>("FROM").unwrap())?)?;
+    let to = client
+        .state_db
+        .get(resolve(&client, args.get_one::<String>("TO").unwrap())?)?;
+
+    // Selected packages of a state, keyed by name
+    let packages = |state: &state::State| {
+        state
+            .selections
+            .iter()
+            .map(|selection| {
+                let meta = client.install_db.get(&selection.package)?;
+                Ok((meta.name.to_string(), (meta, selection.explicit)))
+            })
+            .collect::<Result<BTreeMap<_, _>, Error>>()
+    };
+    let old = packages(&from)?;
+    let new = packages(&to)?;
+
+    let mut added = vec![];
+    let mut removed = vec![];
+    let mut upgraded = vec![];
+    let mut downgraded = vec![];
+    let mut changed = vec![];
+    let mut now_explicit = vec![];
+    let mut now_transitive = vec![];
+
+    for (name, (meta, explicit)) in &old {
+        let Some((new_meta, new_explicit)) = new.get(name) else {
+            removed.push(revision(meta));
+            continue;
+        };
+
+        if meta.id() != new_meta.id() {
+            let change = format!("{} => {}", revision(meta), revision(new_meta).green());
+
+            match compare_revisions(new_meta, meta) {
+                Ordering::Greater => upgraded.push(change),
+                Ordering::Less => downgraded.push(change),
+                // Same revision, rebuilt or from another repository
+                Ordering::Equal => changed.push(change),
+            }
+        }
+
+        match (explicit, new_explicit) {
+            (false, true) => now_explicit.push(name.clone().bold().to_string()),
+            (true, false) => now_transitive.push(name.clone().dim().to_string()),
+            _ => {}
+        }
+    }
+    for (name, (meta, _)) in &new {
+        if !old.contains_key(name) {
+            added.push(revision(meta));
+        }
+    }
+
+    let changed_files = if files {
+        let old_tree = client.vfs(from.selections.iter().map(|selection| &selection.package))?;
+        let new_tree = client.vfs(to.selections.iter().map(|selection| &selection.package))?;
+
+        old_tree
+            .diff(&new_tree, PendingFile::blits_same)
+            .into_iter()
+            .map(|change| match change {
+                Change::Removed(file) => format!("{} {file}", "-".red()),
+                Change::Added(file) => format!("{} {file}", "+".green()),
+                Change::Modified { new, .. } => format!("{} {new}", "~".yellow()),
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+
+    println!(
+        "State {} => State {}",
+        from.id.to_string().bold(),
+        to.id.to_string().bold()
+    );
+    println!();
+
+    let sections = [
+        ("Added", added),
+        ("Removed", removed),
+        ("Upgraded", upgraded),
+        ("Downgraded", downgraded),
+        ("Changed", changed),
+        ("Now explicit", now_explicit),
+        ("Now transitive", now_transitive),
+        ("Files", changed_files),
+    ];
+
+    if sections.iter().all(|(_, lines)| lines.is_empty()) {
+        println!("No differences");
+        return Ok(());
+    }
+
+    for (title, lines) in sections.into_iter().filter(|(_, lines)| !lines.is_empty()) {
+        println!("{}", title.bold());
+        for line in lines {
+            println!("  {line}");
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Order two revisions of a package by version, then source & build release
+fn compare_revisions(a: &package::Meta, b: &package::Meta) -> Ordering {
+    dependency::compare_versions(&a.version_identifier, &b.version_identifier)
+        .then(a.source_release.cmp(&b.source_release))
+        .then(a.build_release.cmp(&b.build_release))
+}
+
+/// Package name and full version for the TUI
+fn revision(meta: &package::Meta) -> String {
+    format!(
+        "{} {}-{}",
+        meta.name,
+        meta.version_identifier.clone().magenta(),
+        meta.source_release.to_string().dim()
+    )
+}
+
+/// Export the selections of a state as a lockfile
+pub fn export(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let client = Client::new(environment::NAME, installation)?;
+
+    let id = resolve(&client, args.get_one::<String>("ID").unwrap())?;
+    let lockfile = client.export_state(id)?.to_yaml()?;
+
+    match args.get_one::<PathBuf>("output") {
+        Some(output) => fs::write(output, lockfile)?,
+        None => print!("{lockfile}"),
+    }
+
+    Ok(())
+}
+
+/// Reproduce the selections of a lockfile as a new state
+pub fn apply(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let path = args.get_one::<PathBuf>("FILE").unwrap();
+    let yes = args.get_flag("yes");
+
+    let lockfile = Lockfile::load(path)?;
+
+    let mut client = Client::new(environment::NAME, installation)?;
+
+    if let Some(blit_target) = args.get_one::<PathBuf>("to").cloned() {
+        client = client.ephemeral(blit_target)?;
+    }
+
+    if let Some(state) = client.apply_lockfile(&lockfile, yes)? {
+        println!("State {} created", state.id.to_string().bold());
+    }
+
+    Ok(())
+}
+
 pub fn prune(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
     let keep = *args.get_one::<u64>("keep").unwrap();
     let include_newer = args.get_flag("include-newer");
@@ -120,15 +375,66 @@ pub fn prune(args: &ArgMatches, installation: Installation) -> Result<(), Error>
 }
 
 pub fn remove(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
-    let id = *args.get_one::<u64>("ID").unwrap() as i32;
     let yes = args.get_flag("yes");
 
     let client = Client::new(environment::NAME, installation)?;
-    client.prune(prune::Strategy::Remove(id.into()), yes)?;
+
+    let id = resolve(&client, args.get_one::<String>("ID").unwrap())?;
+    client.prune(prune::Strategy::Remove(id), yes)?;
 
     Ok(())
 }
 
+/// Name a state, optionally replacing its description
+pub fn tag(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let name = args.get_one::<String>("NAME").unwrap();
+    let note = args.get_one::<String>("note");
+
+    // Names must never be mistaken for an id
+    if name.parse::<i32>().is_ok() {
+        return Err(Error::NumericTag(name.clone()));
+    }
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    let id = resolve(&client, args.get_one::<String>("ID").unwrap())?;
+
+    if let Some(tagged) = client.state_db.find_tag(name)? {
+        if tagged != id {
+            return Err(Error::TagExists(name.clone(), tagged));
+        }
+    }
+
+    client.state_db.set_tag(id, Some(name), note.map(String::as_str))?;
+
+    println!("State {} tagged {}", id.to_string().bold(), name.clone().bold());
+
+    Ok(())
+}
+
+/// Remove the name of a state, leaving it to be pruned again
+pub fn untag(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let client = Client::new(environment::NAME, installation)?;
+
+    let id = resolve(&client, args.get_one::<String>("ID").unwrap())?;
+    client.state_db.set_tag(id, None, None)?;
+
+    println!("State {} untagged", id.to_string().bold());
+
+    Ok(())
+}
+
+/// Resolve a state id or tag given on the command line
+fn resolve(client: &Client, id_or_tag: &str) -> Result<state::Id, Error> {
+    match id_or_tag.parse::<i32>() {
+        Ok(id) => Ok(id.into()),
+        Err(_) => client
+            .state_db
+            .find_tag(id_or_tag)?
+            .ok_or_else(|| Error::UnknownTag(id_or_tag.to_string())),
+    }
+}
+
 pub fn verify(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
     let verbose = args.get_flag("verbose");
     let yes = args.get_flag("yes");
@@ -141,9 +447,15 @@ pub fn verify(args: &ArgMatches, installation: Installation) -> Result<(), Error
 
 /// Emit a state description for the TUI
 fn print_state(state: state::State) {
+    let tag = state
+        .tag
+        .map(|tag| format!(" ({tag})").cyan().to_string())
+        .unwrap_or_default();
+
     println!(
-        "State #{} - {}",
+        "State #{}{} - {}",
         state.id.to_string().bold(),
+        tag,
         state.summary.unwrap_or(String::from("system transaction")),
     );
     println!("{} {}", "Created:".bold(), state.created);
@@ -165,4 +477,19 @@ pub enum Error {
 
     #[error("db")]
     DB(#[from] moss::db::Error),
+
+    #[error("no state tagged {0}")]
+    UnknownTag(String),
+
+    #[error("tag {0} already names state {1}")]
+    TagExists(String, state::Id),
+
+    #[error("tag {0} can't be a number")]
+    NumericTag(String),
+
+    #[error("lockfile")]
+    Lockfile(#[from] client::lockfile::Error),
+
+    #[error("io")]
+    Io(#[from] std::io::Error),
 }
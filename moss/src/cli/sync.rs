@@ -10,8 +10,9 @@ use moss::registry::transaction;
 use moss::state::Selection;
 use moss::{
     client::{self, Client},
+    hold::{self, Hold},
     package::{self},
-    Package,
+    system, Package, Provider, Registry,
 };
 use moss::{environment, runtime, Installation};
 use thiserror::Error;
@@ -19,6 +20,7 @@ use thiserror::Error;
 use tui::dialoguer::theme::ColorfulTheme;
 use tui::dialoguer::Confirm;
 use tui::pretty::autoprint_columns;
+use tui::Styled;
 
 pub fn command() -> Command {
     Command::new("sync")
@@ -27,6 +29,14 @@ pub fn command() -> Command {
         .long_about("Sync package selections with candidates from the highest priority repository")
         .arg(arg!(-u --"update" "Update repositories before syncing"))
         .arg(arg!(--"upgrade-only" "Only sync packages that have a version upgrade"))
+        .arg(
+            arg!(--declarative "Converge explicit packages on those declared in system.yaml").long_help(
+                "Converge explicit packages on those declared in system.yaml. \n\
+                 \n\
+                 Declared packages which aren't installed are added, while explicit packages \
+                 which aren't declared are removed unless another package depends on them",
+            ),
+        )
         .arg(
             arg!(--to <blit_target> "Blit this sync to the provided directory instead of the root")
                 .long_help(
@@ -42,6 +52,7 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     let yes_all = *args.get_one::<bool>("yes").unwrap();
     let update = *args.get_one::<bool>("update").unwrap();
     let upgrade_only = *args.get_one::<bool>("upgrade-only").unwrap();
+    let declarative = *args.get_one::<bool>("declarative").unwrap();
 
     let mut client = Client::new(environment::NAME, installation)?;
 
@@ -60,12 +71,20 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         .registry
         .list_installed(package::Flags::default())
         .collect::<Vec<_>>();
-    if installed.is_empty() {
+    if installed.is_empty() && !declarative {
         return Err(Error::NoInstall);
     }
 
+    // Names of the packages which must end up explicit, if declarative
+    let (packages, declared) = if declarative {
+        let (packages, declared) = converge(&client.registry, &client.system_config(), &installed)?;
+        (packages, Some(declared))
+    } else {
+        (installed.clone(), None)
+    };
+
     // Resolve the final state of packages after considering sync updates
-    let finalized = resolve_with_sync(&client, upgrade_only, &installed)?;
+    let finalized = resolve_with_sync(&client, upgrade_only, &packages)?;
 
     // Synced are packages are:
     //
@@ -81,7 +100,37 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         .cloned()
         .collect::<Vec<_>>();
 
-    if synced.is_empty() && removed.is_empty() {
+    // Installed packages which remain, but flip between explicit and transitive
+    let (now_explicit, now_transitive): (Vec<_>, Vec<_>) = installed
+        .iter()
+        .filter(|p| {
+            declared
+                .as_ref()
+                .is_some_and(|declared| declared.contains(&p.meta.name) != p.flags.explicit)
+                && finalized.iter().any(|f| f.meta.name == p.meta.name)
+        })
+        .cloned()
+        .partition(|p| !p.flags.explicit);
+
+    let holds = client.holds();
+    let held = installed
+        .iter()
+        .filter_map(|p| Some((p, holds.get(&p.meta.name)?)))
+        .collect::<Vec<_>>();
+    if !held.is_empty() {
+        println!("The following packages are held back: ");
+        println!();
+        for (package, hold) in held {
+            println!(
+                "  {} {}",
+                package.meta.name.to_string().bold(),
+                format!("({hold})").dim()
+            );
+        }
+        println!();
+    }
+
+    if synced.is_empty() && removed.is_empty() && now_explicit.is_empty() && now_transitive.is_empty() {
         println!("No packages to sync");
         return Ok(());
     }
@@ -98,6 +147,18 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         autoprint_columns(removed.as_slice());
         println!();
     }
+    if !now_explicit.is_empty() {
+        println!("The following packages will be marked explicit: ");
+        println!();
+        autoprint_columns(now_explicit.as_slice());
+        println!();
+    }
+    if !now_transitive.is_empty() {
+        println!("The following packages will be kept as dependencies only: ");
+        println!();
+        autoprint_columns(now_transitive.as_slice());
+        println!();
+    }
 
     // Must we prompt?
     let result = if yes_all {
@@ -125,13 +186,15 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         finalized
             .into_iter()
             .map(|p| {
+                let explicit = declared.as_ref().map(|declared| declared.contains(&p.meta.name));
+
                 // Use old version id to lookup previous selection
                 let lookup_id = installed
                     .iter()
                     .find_map(|i| (i.meta.name == p.meta.name).then_some(&i.id))
                     .unwrap_or(&p.id);
 
-                previous_selections
+                let selection = previous_selections
                     .iter()
                     .find(|s| s.package == *lookup_id)
                     .cloned()
@@ -141,11 +204,13 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
                         ..s
                     })
                     // Must be transitive
-                    .unwrap_or(Selection {
-                        package: p.id,
-                        explicit: false,
-                        reason: None,
-                    })
+                    .unwrap_or(Selection::transitive(p.id));
+
+                // Declared packages take precedence over the previous selection
+                match explicit {
+                    Some(explicit) => Selection { explicit, ..selection },
+                    None => selection,
+                }
             })
             .collect::<Vec<_>>()
     };
@@ -156,24 +221,104 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     Ok(())
 }
 
+/// Returns the installed `packages` with their explicit flag converged on the
+/// packages declared in [`moss::system::Config`], along with any declared package
+/// that isn't installed yet, and the names of all declared packages
+fn converge(
+    registry: &Registry,
+    config: &system::Config,
+    installed: &[Package],
+) -> Result<(Vec<Package>, BTreeSet<package::Name>), Error> {
+    if config.packages.is_empty() {
+        return Err(Error::NoDeclaredPackages);
+    }
+
+    let mut packages = installed.to_vec();
+    let mut declared = BTreeSet::new();
+
+    for name in &config.packages {
+        let provider = Provider::from_name(name).map_err(|_| Error::NoPackage(name.clone()))?;
+
+        if let Some(package) = installed
+            .iter()
+            .find(|package| package.meta.providers.contains(&provider))
+        {
+            declared.insert(package.meta.name.clone());
+            continue;
+        }
+
+        let package = registry
+            .by_provider(&provider, package::Flags::new().with_available())
+            .next()
+            .ok_or_else(|| Error::NoPackage(name.clone()))?;
+
+        declared.insert(package.meta.name.clone());
+        packages.push(package);
+    }
+
+    for package in &mut packages {
+        package.flags.explicit = declared.contains(&package.meta.name);
+    }
+
+    Ok((packages, declared))
+}
+
 /// Returns the resolved package set w/ sync'd changes swapped in using
 /// the provided `packages`
 fn resolve_with_sync(client: &Client, upgrade_only: bool, packages: &[Package]) -> Result<Vec<Package>, Error> {
+    let with_sync = sync_targets(&client.registry, &client.holds(), upgrade_only, packages);
+
+    // Packages that are explicitly installed
+    let explicit = with_sync
+        .iter()
+        .filter_map(|(id, is_explicit, _)| is_explicit.then_some(id.clone()))
+        .collect::<Vec<_>>();
+    // Packages that have an update or are held
+    let pinned = with_sync
+        .iter()
+        .filter_map(|(id, _, is_pinned)| is_pinned.then_some(id.clone()));
+
+    // Build a new tx from this sync'd package set
+    let mut tx = client.registry.transaction()?;
+    // Pin all updated & held packages so dependency
+    // resolution picks these versions
+    tx.pin_providers(pinned);
+    // Add all explicit packages to build the final tx state
+    tx.add(explicit)?;
+
+    // Resolve the tx
+    Ok(client.resolve_packages(tx.finalize())?)
+}
+
+/// Returns the id each of `packages` syncs to, whether it's explicit
+/// and whether it must be pinned during resolution
+fn sync_targets(
+    registry: &Registry,
+    holds: &hold::Map,
+    upgrade_only: bool,
+    packages: &[Package],
+) -> Vec<(package::Id, bool, bool)> {
     let all_ids = packages.iter().map(|p| &p.id).collect::<BTreeSet<_>>();
 
     // For each package, replace it w/ it's sync'd change (if available)
     // or return the original package
-    let with_sync = packages
+    packages
         .iter()
         .map(|p| {
             let is_explicit = p.flags.explicit;
-
-            // Get first available = use highest priority
-            if let Some(lookup) = client
-                .registry
-                .by_name(&p.meta.name, package::Flags::new().with_available())
-                .next()
-            {
+            let available = package::Flags::new().with_available();
+
+            // Get first available = use highest priority, unless
+            // held to the installed version or a single repository
+            let lookup = match holds.get(&p.meta.name) {
+                Some(Hold { repository: None }) => return (p.id.clone(), is_explicit, true),
+                Some(Hold {
+                    repository: Some(repository),
+                }) => registry.by_name_from(&p.meta.name, repository, available).next(),
+                None => registry.by_name(&p.meta.name, available).next(),
+            };
+
+            if let Some(lookup) = lookup {
                 let upgrade_check = if upgrade_only {
                     lookup.meta.source_release > p.meta.source_release
                 } else {
@@ -185,30 +330,10 @@ fn resolve_with_sync(client: &Client, upgrade_only: bool, packages: &[Package])
                 }
             }
 
-            (p.id.clone(), is_explicit, false)
+            // Pinned to this version if held
+            (p.id.clone(), is_explicit, holds.get(&p.meta.name).is_some())
         })
-        .collect::<Vec<_>>();
-
-    // Packages that are explicitly installed
-    let explicit = with_sync
-        .iter()
-        .filter_map(|(id, is_explicit, _)| is_explicit.then_some(id.clone()))
-        .collect::<Vec<_>>();
-    // Packages that have an update
-    let updated = with_sync
-        .iter()
-        .filter_map(|(id, _, is_updated)| is_updated.then_some(id.clone()));
-
-    // Build a new tx from this sync'd package set
-    let mut tx = client.registry.transaction()?;
-    // Pin all updated packages so dependency resolution
-    // picks these versions
-    tx.pin_providers(updated);
-    // Add all explicit packages to build the final tx state
-    tx.add(explicit)?;
-
-    // Resolve the tx
-    Ok(client.resolve_packages(tx.finalize())?)
+        .collect()
 }
 
 #[derive(Debug, Error)]
@@ -219,6 +344,12 @@ pub enum Error {
     #[error("no installation")]
     NoInstall,
 
+    #[error("no packages declared in system.yaml")]
+    NoDeclaredPackages,
+
+    #[error("no package found: {0}")]
+    NoPackage(String),
+
     #[error("client")]
     Client(#[from] client::Error),
 
@@ -234,3 +365,145 @@ pub enum Error {
     #[error("io")]
     Io(#[from] std::io::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use moss::repository;
+
+    use super::*;
+    use crate::cli::test::repository_plugin;
+
+    fn package(id: &str, name: &str, source_release: u64) -> Package {
+        Package {
+            id: package::Id::from(id.to_string()),
+            meta: package::Meta {
+                name: package::Name::from(name.to_string()),
+                version_identifier: source_release.to_string(),
+                source_release,
+                build_release: 1,
+                providers: [Provider::from_name(name).unwrap()].into(),
+                ..Default::default()
+            },
+            flags: package::Flags::new().with_installed().with_explicit(),
+        }
+    }
+
+    #[test]
+    fn held_and_pinned_targets() {
+        let mut registry = Registry::default();
+        registry.add_plugin(repository_plugin(
+            "high",
+            10,
+            vec![
+                package("held-2", "held", 2),
+                package("pinned-2", "pinned", 2),
+                package("free-2", "free", 2),
+            ],
+        ));
+        registry.add_plugin(repository_plugin("low", 1, vec![package("pinned-low", "pinned", 1)]));
+
+        let holds = hold::Map::with([
+            ("held".to_string(), Hold { repository: None }),
+            (
+                "pinned".to_string(),
+                Hold {
+                    repository: Some(repository::Id::new("low".to_string())),
+                },
+            ),
+        ]);
+        let installed = [
+            package("held-1", "held", 1),
+            package("pinned-1", "pinned", 1),
+            package("free-1", "free", 1),
+        ];
+
+        let targets = |upgrade_only| {
+            sync_targets(&registry, &holds, upgrade_only, &installed)
+                .into_iter()
+                .map(|(id, _, pinned)| (String::from(id), pinned))
+                .collect::<Vec<_>>()
+        };
+
+        // Held packages stay at the installed version while pinned packages only
+        // sync from their repository, despite a newer release in another
+        assert_eq!(
+            targets(false),
+            [
+                ("held-1".to_string(), true),
+                ("pinned-low".to_string(), true),
+                ("free-2".to_string(), true),
+            ]
+        );
+        // Pinned packages are still held back from downgrades
+        assert_eq!(
+            targets(true),
+            [
+                ("held-1".to_string(), true),
+                ("pinned-1".to_string(), true),
+                ("free-2".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn converge_on_declared() {
+        let app = Package {
+            meta: package::Meta {
+                dependencies: ["name(lib)".parse().unwrap()].into(),
+                ..package("app-1", "app", 1).meta
+            },
+            ..package("app-1", "app", 1)
+        };
+        let lib = package("lib-1", "lib", 1);
+        let old = package("old-1", "old", 1);
+        let new = package("new-1", "new", 1);
+
+        let mut registry = Registry::default();
+        registry.add_plugin(repository_plugin(
+            "repo",
+            1,
+            vec![app.clone(), lib.clone(), old.clone(), new.clone()],
+        ));
+
+        let config = system::Config {
+            packages: ["app".to_string(), "new".to_string()].into(),
+        };
+        let (packages, declared) = converge(&registry, &config, &[app, lib, old]).unwrap();
+
+        let explicit = |name: &str| {
+            packages
+                .iter()
+                .find(|package| package.meta.name.to_string() == name)
+                .map(|package| package.flags.explicit)
+        };
+
+        // Declared packages are explicit, added if not installed, while
+        // anything else explicitly installed is demoted
+        assert_eq!(
+            declared,
+            ["app", "new"].map(|name| package::Name::from(name.to_string())).into()
+        );
+        assert_eq!(explicit("app"), Some(true));
+        assert_eq!(explicit("new"), Some(true));
+        assert_eq!(explicit("lib"), Some(false));
+        assert_eq!(explicit("old"), Some(false));
+
+        // Resolving the explicit packages keeps demoted packages which are still
+        // required, while the rest are dropped
+        let mut tx = registry.transaction().unwrap();
+        tx.add(
+            packages
+                .iter()
+                .filter(|package| package.flags.explicit)
+                .map(|package| package.id.clone())
+                .collect(),
+        )
+        .unwrap();
+        let resolved = tx
+            .finalize()
+            .map(|id| String::from(id.clone()))
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(resolved, ["app-1", "lib-1", "new-1"].map(str::to_string).into());
+    }
+}
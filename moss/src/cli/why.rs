@@ -0,0 +1,238 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::collections::{BTreeMap, VecDeque};
+
+use clap::{arg, ArgMatches, Command};
+use itertools::Itertools;
+use thiserror::Error;
+
+use moss::{
+    client::{self, Client},
+    environment, package,
+    package::Flags,
+    registry::transaction,
+    Installation, Provider,
+};
+use tui::Styled;
+
+pub fn command() -> Command {
+    Command::new("why")
+        .about("Show why a package is installed")
+        .long_about("Trace the explicitly installed packages which pulled in an installed package")
+        .arg(arg!(<NAME> "Installed package to explain").value_parser(clap::value_parser!(String)))
+}
+
+/// Handle execution of `moss why`
+pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+    let name = args.get_one::<String>("NAME").unwrap();
+    let lookup = Provider::from_name(name).unwrap();
+
+    let client = Client::new(environment::NAME, installation)?;
+
+    let target = client
+        .registry
+        .by_provider(&lookup, Flags::new().with_installed())
+        .next()
+        .ok_or_else(|| Error::NotInstalled(name.clone()))?;
+
+    let selections = match client.installation.active_state {
+        Some(id) => client.state_db.get(id)?.selections,
+        None => vec![],
+    };
+    let selection = |id: &package::Id| selections.iter().find(|s| s.package == *id);
+    let explicit = |id: &package::Id| selection(id).is_some_and(|s| s.explicit);
+
+    let installed = client
+        .registry
+        .list_installed(Flags::default())
+        .map(|p| p.id)
+        .collect::<Vec<_>>();
+    let transaction = client.registry.transaction_with_installed(installed)?;
+
+    if explicit(&target.id) {
+        print!("{} was explicitly installed", target.meta.name.to_string().bold());
+        match selection(&target.id).and_then(|s| s.reason.as_ref()) {
+            Some(reason) => println!(" ({reason})"),
+            None => println!(),
+        }
+    }
+
+    let mut chains = dependent_chains(&transaction, &target.id, &explicit);
+
+    if chains.is_empty() {
+        if !explicit(&target.id) {
+            println!(
+                "{} is not required by any explicitly installed package",
+                target.meta.name.to_string().bold()
+            );
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{} is required by {} explicitly installed package(s):",
+        target.meta.name.to_string().bold(),
+        chains.len()
+    );
+    println!();
+
+    chains.sort_by_key(|chain| transaction.package(&chain[0]).map(|p| p.meta.name.to_string()));
+
+    for chain in chains {
+        let root = &chain[0];
+        let mut line = name_of(&transaction, root).bold().to_string();
+
+        for (current, next) in chain.iter().tuple_windows() {
+            let dependency = transaction
+                .package(current)
+                .zip(transaction.package(next))
+                .and_then(|(dependent, dependency)| dependent.dependency_on(dependency));
+
+            if let Some(dependency) = dependency {
+                line.push_str(&format!(" -> {}", dependency.to_string().dim()));
+            }
+            line.push_str(&format!(" -> {}", name_of(&transaction, next).bold()));
+        }
+
+        if let Some(reason) = selection(root).and_then(|s| s.reason.as_ref()) {
+            line.push_str(&format!(" ({reason})"));
+        }
+
+        println!("  {line}");
+    }
+
+    Ok(())
+}
+
+/// Walk up through the dependents of `target` until reaching explicitly installed
+/// packages, returning the chain from each of them down to `target`
+fn dependent_chains(
+    transaction: &transaction::Transaction<'_>,
+    target: &package::Id,
+    explicit: impl Fn(&package::Id) -> bool,
+) -> Vec<Vec<package::Id>> {
+    let transposed = transaction.graph().transpose();
+    // The package each dependent was reached from
+    let mut reached_from = BTreeMap::new();
+    let mut roots = vec![];
+    let mut queue = VecDeque::from([target.clone()]);
+
+    while let Some(id) = queue.pop_front() {
+        let Some(index) = transposed.get_index(&id) else {
+            continue;
+        };
+
+        for dependent in transposed.successors(index) {
+            if dependent == target || reached_from.contains_key(dependent) {
+                continue;
+            }
+
+            reached_from.insert(dependent.clone(), id.clone());
+
+            if explicit(dependent) {
+                roots.push(dependent.clone());
+            } else {
+                queue.push_back(dependent.clone());
+            }
+        }
+    }
+
+    roots
+        .into_iter()
+        .map(|root| {
+            let mut chain = vec![root];
+            while let Some(next) = chain.last().and_then(|current| reached_from.get(current)) {
+                chain.push(next.clone());
+            }
+            chain
+        })
+        .collect()
+}
+
+fn name_of(transaction: &transaction::Transaction<'_>, id: &package::Id) -> String {
+    transaction
+        .package(id)
+        .map(|p| p.meta.name.to_string())
+        .unwrap_or_else(|| id.to_string())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} is not installed")]
+    NotInstalled(String),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("transaction")]
+    Transaction(#[from] transaction::Error),
+
+    #[error("db")]
+    DB(#[from] moss::db::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use moss::{dependency, Dependency, Package, Registry};
+
+    use super::*;
+    use crate::cli::test::repository_plugin;
+
+    /// A package named `name` depending on the packages named `dependencies`
+    fn package(name: &str, dependencies: &[&str]) -> Package {
+        Package {
+            id: package::Id::from(name.to_string()),
+            meta: package::Meta {
+                name: package::Name::from(name.to_string()),
+                dependencies: dependencies
+                    .iter()
+                    .map(|name| Dependency::from_name(name).unwrap())
+                    .collect(),
+                providers: [Provider {
+                    kind: dependency::Kind::PackageName,
+                    name: name.to_string(),
+                }]
+                .into(),
+                ..Default::default()
+            },
+            flags: Flags::new().with_available(),
+        }
+    }
+
+    #[test]
+    fn reverse_dependency_chains() {
+        let packages = vec![
+            package("app", &["lib"]),
+            package("lib", &["base"]),
+            package("tool", &["base"]),
+            package("base", &[]),
+            package("other", &[]),
+        ];
+        let ids = packages.iter().map(|package| package.id.clone()).collect::<Vec<_>>();
+
+        let mut registry = Registry::default();
+        registry.add_plugin(repository_plugin("test", 0, packages));
+
+        let transaction = registry.transaction_with_installed(ids).unwrap();
+        let base = package::Id::from("base".to_string());
+        let chains = |explicit: &[&str]| {
+            dependent_chains(&transaction, &base, |id| explicit.contains(&id.to_string().as_str()))
+                .into_iter()
+                .map(|chain| chain.into_iter().map(String::from).collect::<Vec<_>>())
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+
+        // Every explicit package is traced down through its dependencies
+        assert_eq!(
+            chains(&["app", "tool", "other"]),
+            [vec!["app", "lib", "base"], vec!["tool", "base"]]
+        );
+        // Walking up stops at the first explicit package
+        assert_eq!(chains(&["app", "lib"]), [vec!["lib", "base"]]);
+        // Nothing explicit requires the target
+        assert!(chains(&["other"]).is_empty());
+    }
+}
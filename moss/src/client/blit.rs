@@ -0,0 +1,163 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Placement of assets from the asset store into state trees
+
+use std::{
+    fmt, io,
+    os::fd::RawFd,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use fs_err as fs;
+use nix::{
+    errno::Errno,
+    fcntl::{self, OFlag},
+    libc,
+    sys::stat::Mode as Permissions,
+    unistd::{close, linkat, unlinkat, LinkatFlags, UnlinkatFlags},
+};
+use serde::{Deserialize, Serialize};
+
+/// `_IOW(0x94, 9, int)` from `linux/fs.h`
+const FICLONE: u64 = 0x4004_9409;
+
+/// How regular files are written to a state tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Hardlink the asset, sharing its inode with every state
+    #[default]
+    Hardlink,
+    /// Clone the asset as a copy-on-write reflink, so writes to the file
+    /// never reach the asset store. Filesystems without reflink support,
+    /// such as ext4, fall back to hardlinks.
+    Reflink,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Hardlink => "hardlink".fmt(f),
+            Mode::Reflink => "reflink".fmt(f),
+        }
+    }
+}
+
+/// Blit settings, loaded from `blit.d` configs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub mode: Mode,
+}
+
+impl Config {
+    /// Load all blit configs of `manager`, later configs taking precedence
+    pub fn load(manager: &config::Manager) -> Self {
+        manager.load::<Self>().into_iter().last().unwrap_or_default()
+    }
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "blit".into()
+    }
+}
+
+/// Writes assets to a tree using the configured [`Mode`]
+pub(super) struct Linker {
+    /// Raw file descriptor of the asset store
+    cache: RawFd,
+    mode: Mode,
+    /// Cleared by the first clone the filesystem refuses
+    reflinks_supported: AtomicBool,
+}
+
+impl Linker {
+    pub fn new(cache: RawFd, mode: Mode) -> Self {
+        Self {
+            cache,
+            mode,
+            reflinks_supported: AtomicBool::new(true),
+        }
+    }
+
+    /// The mode files were actually written with, which is [`Mode::Hardlink`]
+    /// if the filesystem doesn't support reflinks
+    pub fn mode(&self) -> Mode {
+        match self.mode {
+            Mode::Reflink if self.reflinks_supported.load(Ordering::Relaxed) => Mode::Reflink,
+            _ => Mode::Hardlink,
+        }
+    }
+
+    /// Write the asset at `asset`, relative to the asset store, as `name` within `parent`
+    pub fn link(&self, asset: &str, parent: RawFd, name: &str) -> Result<(), Errno> {
+        if self.mode == Mode::Reflink && self.reflinks_supported.load(Ordering::Relaxed) {
+            match self.reflink(asset, parent, name) {
+                Err(Errno::EOPNOTSUPP | Errno::EXDEV | Errno::EINVAL | Errno::ENOTTY) => {
+                    self.reflinks_supported.store(false, Ordering::Relaxed);
+                }
+                result => return result,
+            }
+        }
+
+        linkat(
+            Some(self.cache),
+            asset,
+            Some(parent),
+            name,
+            LinkatFlags::NoSymlinkFollow,
+        )
+    }
+
+    fn reflink(&self, asset: &str, parent: RawFd, name: &str) -> Result<(), Errno> {
+        let source = fcntl::openat(
+            self.cache,
+            asset,
+            OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+            Permissions::empty(),
+        )?;
+        let target = match fcntl::openat(
+            parent,
+            name,
+            OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_CLOEXEC,
+            Permissions::from_bits_truncate(0o644),
+        ) {
+            Ok(target) => target,
+            Err(error) => {
+                close(source)?;
+                return Err(error);
+            }
+        };
+
+        let result = Errno::result(unsafe { libc::ioctl(target, FICLONE as _, source) }).map(drop);
+        close(target)?;
+        close(source)?;
+
+        // Don't leave an empty file behind for the hardlink fallback
+        if result.is_err() {
+            unlinkat(Some(parent), name, UnlinkatFlags::NoRemoveDir)?;
+        }
+
+        result
+    }
+}
+
+/// Record the `mode` a tree at `root` was blitted with
+pub(super) fn record_mode(root: &Path, mode: Mode) -> Result<(), io::Error> {
+    let usr = root.join("usr");
+    fs::create_dir_all(&usr)?;
+    fs::write(usr.join(".blitMode"), mode.to_string())
+}
+
+/// The mode the tree at `root` was blitted with, assuming hardlinks
+/// for trees which predate the record
+pub fn recorded_mode(root: &Path) -> Mode {
+    match fs::read_to_string(root.join("usr").join(".blitMode")) {
+        Ok(mode) if mode.trim() == "reflink" => Mode::Reflink,
+        _ => Mode::Hardlink,
+    }
+}
@@ -0,0 +1,145 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Export a resolved package closure for installing on systems without network access
+
+use std::{io, path::Path, time::Duration};
+
+use fs_err as fs;
+use futures::{stream, StreamExt, TryStreamExt};
+use thiserror::Error;
+use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
+
+use crate::{
+    client::{cache, Client},
+    package,
+    package::Flags,
+    registry::transaction,
+    request, runtime, Package, Provider,
+};
+
+/// Resolve the full dependency closure of `pkgs` against the active repositories
+/// and copy the stones of every package in it into `output`.
+///
+/// Returns the bundled packages, along with the file name of their stone.
+pub fn bundle(client: &Client, pkgs: &[&str], output: &Path) -> Result<Vec<(Package, String)>, Error> {
+    // Installed packages may be stale or locally built, so resolve the
+    // closure from what the repositories publish
+    let registry = client.registry.available();
+
+    let input = pkgs
+        .iter()
+        .map(|name| {
+            let provider = Provider::from_name(name).unwrap();
+            let package = registry.by_provider(&provider, Flags::new().with_available()).next();
+
+            package
+                .map(|package| package.id)
+                .ok_or_else(|| Error::NoPackage(name.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut tx = registry.transaction()?;
+    tx.add(input)?;
+
+    // Only repository metadata knows where to fetch a stone from
+    let mut packages = tx
+        .finalize()
+        .map(|id| {
+            registry
+                .by_id(id)
+                .find(|package| package.flags.available)
+                .ok_or_else(|| Error::Unavailable(id.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    packages.sort_by_key(|package| package.meta.name.to_string());
+
+    fs::create_dir_all(output)?;
+
+    runtime::block_on(fetch(client, &packages))?;
+
+    packages
+        .into_iter()
+        .map(|package| {
+            let hash = package.meta.hash.as_ref().ok_or(cache::Error::MissingHash)?;
+            let uri = package.meta.uri.as_ref().ok_or(cache::Error::MissingUri)?;
+            let file_name = uri.rsplit('/').next().unwrap_or(uri).to_string();
+
+            fs::copy(
+                cache::download_path(&client.installation, hash)?,
+                output.join(&file_name),
+            )?;
+
+            Ok((package, file_name))
+        })
+        .collect()
+}
+
+/// Download the stones of `packages` into the cache
+async fn fetch(client: &Client, packages: &[Package]) -> Result<(), Error> {
+    let multi_progress = MultiProgress::new();
+
+    let total_progress = multi_progress.add(
+        ProgressBar::new(packages.len() as u64).with_style(
+            ProgressStyle::with_template("\n|{bar:20.cyan/blue}| {pos}/{len}")
+                .unwrap()
+                .progress_chars("■≡=- "),
+        ),
+    );
+    total_progress.tick();
+
+    stream::iter(packages)
+        .map(|package| async {
+            let progress_bar = multi_progress.insert_before(
+                &total_progress,
+                ProgressBar::new(package.meta.download_size.unwrap_or_default())
+                    .with_message(format!(
+                        "{} {}",
+                        "Downloading".blue(),
+                        package.meta.name.to_string().bold(),
+                    ))
+                    .with_style(
+                        ProgressStyle::with_template(
+                            " {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ",
+                        )
+                        .unwrap()
+                        .tick_chars("--=≡■≡=--"),
+                    ),
+            );
+            progress_bar.enable_steady_tick(Duration::from_millis(150));
+
+            let origin = client.repositories.origin(&package.id);
+            cache::fetch_stone(&package.meta, &client.installation, origin, |progress| {
+                progress_bar.inc(progress.delta);
+            })
+            .await?;
+
+            multi_progress.remove(&progress_bar);
+            multi_progress.suspend(|| println!("{} {}", "Fetched".green(), package.meta.name.to_string().bold()));
+            total_progress.inc(1);
+
+            Ok(()) as Result<(), Error>
+        })
+        .buffer_unordered(request::concurrency())
+        .try_collect::<()>()
+        .await?;
+
+    multi_progress.clear()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No package found: {0}")]
+    NoPackage(String),
+    #[error("{0} isn't available from any active repository")]
+    Unavailable(package::Id),
+    #[error("transaction")]
+    Transaction(#[from] transaction::Error),
+    #[error("cache")]
+    Cache(#[from] cache::Error),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
@@ -7,19 +7,31 @@
 use std::collections::BTreeSet;
 use std::{
     io,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
+use bytes::Bytes;
 use fs_err::tokio::{self as fs, File};
 use futures::StreamExt;
+use log::debug;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
-use tokio::io::AsyncWriteExt;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+    task,
+};
+use tokio_util::io::ReaderStream;
 use url::Url;
 
 use stone::{payload, read::PayloadKind};
 
-use crate::{package, request, Installation};
+use crate::{
+    package,
+    repository::{self, Repository},
+    request, runtime, signing, Installation,
+};
 
 /// Synchronized set of assets that are currently being
 /// unpacked. Used to prevent unpacking the same asset
@@ -58,15 +70,72 @@ impl Progress {
 }
 
 /// Fetch a package with the provided [`package::Meta`] and [`Installation`] and return a [`Download`] on success.
+///
+/// If a `delta` is provided it's fetched in place of the full stone, unless that's already cached.
+/// Callers must ensure the assets of the package it applies to are available.
+///
+/// The content of the stone is decompressed into the cache while it downloads, so
+/// [`Download::unpack`] only has to move the assets into place. Nothing enters the asset
+/// store until the stone is verified against the signature policy of the `origin`
+/// repository, if known.
 pub async fn fetch(
+    meta: &package::Meta,
+    delta: Option<&package::meta::Delta>,
+    installation: &Installation,
+    origin: Option<&Repository>,
+    on_progress: impl Fn(Progress),
+) -> Result<Download, Error> {
+    fetch_with(meta, delta, installation, origin, true, on_progress).await
+}
+
+/// Fetch the full stone of a package with the provided [`package::Meta`] into the cache,
+/// without preparing it for unpacking
+pub async fn fetch_stone(
     meta: &package::Meta,
     installation: &Installation,
+    origin: Option<&Repository>,
+    on_progress: impl Fn(Progress),
+) -> Result<Download, Error> {
+    fetch_with(meta, None, installation, origin, false, on_progress).await
+}
+
+async fn fetch_with(
+    meta: &package::Meta,
+    delta: Option<&package::meta::Delta>,
+    installation: &Installation,
+    origin: Option<&Repository>,
+    streaming: bool,
     on_progress: impl Fn(Progress),
 ) -> Result<Download, Error> {
-    let url = meta.uri.as_ref().ok_or(Error::MissingUri)?.parse::<Url>()?;
     let hash = meta.hash.as_ref().ok_or(Error::MissingHash)?;
 
-    let destination_path = download_path(installation, hash)?;
+    let full_path = download_path(installation, hash)?;
+
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    // Prefer a cached full stone over fetching the delta
+    let delta = match delta {
+        Some(delta) if !tokio::fs::try_exists(&full_path).await? => Some(delta),
+        _ => None,
+    };
+
+    let (url, destination_path, download_hash, download_size) = match delta {
+        Some(delta) => (
+            &delta.uri,
+            download_path(installation, &delta.hash)?,
+            &delta.hash,
+            Some(delta.size),
+        ),
+        None => (
+            meta.uri.as_ref().ok_or(Error::MissingUri)?,
+            full_path,
+            hash,
+            meta.download_size,
+        ),
+    };
+    let url = url.parse::<Url>()?;
     let partial_path = destination_path.with_extension("part");
 
     if let Some(parent) = destination_path.parent() {
@@ -74,33 +143,55 @@ pub async fn fetch(
     }
 
     if tokio::fs::try_exists(&destination_path).await? {
+        verify(&meta.name, &destination_path, origin, installation).await?;
+
         return Ok(Download {
             id: meta.id().into(),
             path: destination_path,
             installation: installation.clone(),
             was_cached: true,
+            is_delta: delta.is_some(),
+            streamed: None,
         });
     }
 
-    let mut bytes = request::get(url).await?;
-    let mut out = File::create(&partial_path).await?;
+    let content_path = if streaming {
+        let content_path = content_path(installation, &meta.id().into());
 
-    let mut total = 0;
+        if let Some(parent) = content_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
 
-    while let Some(chunk) = bytes.next().await {
-        let bytes = chunk?;
-        let delta = bytes.len() as u64;
-        total += delta;
-        out.write_all(&bytes).await?;
+        Some(content_path)
+    } else {
+        None
+    };
 
-        (on_progress)(Progress {
-            delta,
-            completed: total,
-            total: meta.download_size.unwrap_or(total),
-        });
-    }
+    let failures = repository::mirror::Failures::session();
+    let locations = match origin {
+        Some(origin) => repository::mirror::locations(origin, &url, failures),
+        None => vec![repository::mirror::Location {
+            mirror: url.clone(),
+            url,
+        }],
+    };
 
-    out.flush().await?;
+    let streamed = repository::mirror::failover(locations, failures, |url| {
+        download(
+            url,
+            &partial_path,
+            download_hash,
+            download_size,
+            content_path.as_deref(),
+            &on_progress,
+        )
+    })
+    .await?;
+
+    if let Err(error) = verify(&meta.name, &partial_path, origin, installation).await {
+        fs::remove_file(&partial_path).await?;
+        return Err(error);
+    }
 
     fs::rename(partial_path, &destination_path).await?;
 
@@ -109,15 +200,251 @@ pub async fn fetch(
         path: destination_path,
         installation: installation.clone(),
         was_cached: false,
+        is_delta: delta.is_some(),
+        streamed,
+    })
+}
+
+/// Download `url` into `partial_path`, resuming from whatever an interrupted download
+/// left behind, and ensure the result matches the expected `hash`.
+///
+/// A resumed download which fails validation is restarted from scratch, as the
+/// bytes left behind may belong to a different revision of the file.
+///
+/// If a `content_path` is provided the stone is unpacked into it as it arrives, returning
+/// its payloads unless it couldn't be read that way.
+async fn download(
+    url: Url,
+    partial_path: &Path,
+    hash: &str,
+    size: Option<u64>,
+    content_path: Option<&Path>,
+    on_progress: impl Fn(Progress),
+) -> Result<Option<Vec<PayloadKind>>, Error> {
+    let mut resume = true;
+
+    loop {
+        let existing = match fs::metadata(partial_path).await {
+            Ok(metadata) if resume => metadata.len(),
+            Ok(_) => 0,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error.into()),
+        };
+
+        let (offset, mut bytes) = request::get_from(url.clone(), existing).await?;
+
+        let mut unpack = content_path.map(StreamingUnpack::start);
+
+        let received = async {
+            // A resumed stone is unpacked from the start
+            if let Some(unpack) = unpack.as_mut().filter(|_| offset > 0) {
+                let mut existing = ReaderStream::new(File::open(partial_path).await?.take(offset));
+
+                while let Some(chunk) = existing.next().await {
+                    unpack.feed(chunk?).await;
+                }
+            }
+
+            let mut out = if offset > 0 {
+                fs::OpenOptions::new().append(true).open(partial_path).await?
+            } else {
+                File::create(partial_path).await?
+            };
+
+            let mut total = offset;
+
+            if offset > 0 {
+                (on_progress)(Progress {
+                    delta: offset,
+                    completed: total,
+                    total: size.unwrap_or(total),
+                });
+            }
+
+            while let Some(chunk) = bytes.next().await {
+                let bytes = chunk?;
+                let delta = bytes.len() as u64;
+                total += delta;
+                out.write_all(&bytes).await?;
+
+                if let Some(unpack) = unpack.as_mut() {
+                    unpack.feed(bytes).await;
+                }
+
+                (on_progress)(Progress {
+                    delta,
+                    completed: total,
+                    total: size.unwrap_or(total),
+                });
+            }
+
+            out.flush().await?;
+
+            Ok(()) as Result<(), Error>
+        }
+        .await;
+
+        // Always wait on the unpacker so it's done with the content
+        // file before anything else may write to it
+        let streamed = match unpack {
+            Some(unpack) => unpack.finish().await,
+            None => None,
+        };
+        received?;
+
+        let path = partial_path.to_owned();
+        let actual = runtime::unblock(move || sha256(&path)).await?;
+
+        if actual == hash {
+            return Ok(streamed);
+        }
+
+        fs::remove_file(partial_path).await?;
+
+        if offset == 0 {
+            return Err(Error::HashMismatch {
+                expected: hash.to_string(),
+                actual,
+            });
+        }
+
+        resume = false;
+    }
+}
+
+/// Hex encoded sha256 digest of the file at `path`
+fn sha256(path: &Path) -> Result<String, io::Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs_err::File::open(path)?, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Decompresses the content of a stone on the blocking pool while its bytes are
+/// still being downloaded
+struct StreamingUnpack {
+    sender: Option<mpsc::Sender<Bytes>>,
+    task: task::JoinHandle<Result<Vec<PayloadKind>, Error>>,
+}
+
+impl StreamingUnpack {
+    /// Start unpacking the content of the stone fed to it into `content_path`
+    fn start(content_path: &Path) -> Self {
+        let (sender, receiver) = mpsc::channel(64);
+        let content_path = content_path.to_owned();
+
+        let task = task::spawn_blocking(move || {
+            let mut content = fs_err::File::create(content_path)?;
+            let reader = ChannelReader {
+                receiver,
+                chunk: Bytes::new(),
+            };
+
+            Ok(stone::stream(reader)?.unpack(&mut content)?)
+        });
+
+        Self {
+            sender: Some(sender),
+            task,
+        }
+    }
+
+    /// Feed the next bytes of the stone, which are dropped once unpacking stopped
+    async fn feed(&mut self, bytes: Bytes) {
+        if let Some(sender) = &self.sender {
+            if sender.send(bytes).await.is_err() {
+                self.sender = None;
+            }
+        }
+    }
+
+    /// Wait for unpacking to finish, returning the payloads of the stone
+    /// or `None` if it couldn't be unpacked
+    async fn finish(mut self) -> Option<Vec<PayloadKind>> {
+        drop(self.sender.take());
+
+        match self.task.await.expect("spawn blocking") {
+            Ok(payloads) => Some(payloads),
+            Err(error) => {
+                debug!("Streaming unpack failed: {error}");
+                None
+            }
+        }
+    }
+}
+
+/// Reads the chunks sent over a channel from the blocking pool
+struct ChannelReader {
+    receiver: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl io::Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+
+        Ok(len)
+    }
+}
+
+/// Returns true if the assets of all regular files in `layouts` exist in the
+/// installation, such as those of the package a [`package::meta::Delta`] applies to
+pub fn layout_assets_exist<'a>(
+    layouts: impl IntoIterator<Item = &'a payload::Layout>,
+    installation: &Installation,
+) -> bool {
+    layouts.into_iter().all(|layout| match &layout.entry {
+        payload::layout::Entry::Regular(digest, _) => asset_path(installation, &format!("{digest:02x}")).exists(),
+        _ => true,
     })
 }
 
+/// Check the signatures of the stone at `path` against the policy of its `origin` repository,
+/// or the local [`signing::Config`] of the installation for stones without one
+async fn verify(
+    name: &package::Name,
+    path: &Path,
+    origin: Option<&Repository>,
+    installation: &Installation,
+) -> Result<(), Error> {
+    let (policy, trusted) = match origin {
+        Some(origin) => (origin.stone_signatures, origin.trusted_keys.clone()),
+        None => {
+            let config = signing::Config::load(&config::Manager::system(&installation.root, "moss"));
+            (config.stone_signatures, config.trusted_keys)
+        }
+    };
+    if policy == repository::SignaturePolicy::Ignore {
+        return Ok(());
+    }
+
+    let path = path.to_owned();
+    match runtime::unblock(move || signing::check_stone(policy, &trusted, &path)).await {
+        Ok(_) => Ok(()),
+        Err(signing::Error::UnsignedStone(_)) => Err(Error::Unsigned(name.clone())),
+        Err(signing::Error::UntrustedStone(_)) => Err(Error::UntrustedSignature(name.clone())),
+        Err(error) => Err(error.into()),
+    }
+}
+
 /// A package that has been downloaded to the installation
 pub struct Download {
     id: package::Id,
     path: PathBuf,
     installation: Installation,
     pub was_cached: bool,
+    /// Only holds the assets which changed since an older release
+    pub is_delta: bool,
+    /// Payloads of the stone if its content was already unpacked while downloading
+    streamed: Option<Vec<PayloadKind>>,
 }
 
 /// Upon fetch completion we have this unpacked asset bound with
@@ -130,7 +457,7 @@ impl Download {
     /// Unpack the downloaded package
     // TODO: Return an "Unpacked" struct which has a "blit" method on it?
     pub fn unpack(
-        self,
+        mut self,
         unpacking_in_progress: UnpackingInProgress,
         on_progress: impl Fn(Progress) + Send + 'static,
     ) -> Result<UnpackedAsset, Error> {
@@ -175,46 +502,76 @@ impl Download {
             }
         }
 
-        let content_dir = self.installation.cache_path("content");
-        let content_path = content_dir.join(self.id);
+        let content_path = content_path(&self.installation, &self.id);
+
+        if let Some(parent) = content_path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let streamed = self.streamed.take();
+        let is_streamed = streamed.is_some();
+
+        let (payloads, content_file) = match streamed {
+            // Content was decompressed while downloading
+            Some(payloads) => (payloads, File::open(&content_path)?),
+            None => {
+                let mut reader = stone::read(File::open(&self.path)?)?;
 
-        create_dir_all(&content_dir)?;
+                let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
+                let indices = payloads
+                    .iter()
+                    .filter_map(PayloadKind::index)
+                    .flat_map(|p| &p.body)
+                    .collect::<Vec<_>>();
 
-        let mut reader = stone::read(File::open(&self.path)?)?;
+                // If we don't have any files to unpack OR download was cached
+                // & all assets exist, we can skip unpacking
+                if indices.is_empty() || (self.was_cached && check_assets_exist(&indices, &self.installation)) {
+                    self.check_delta(&payloads)?;
+
+                    return Ok(UnpackedAsset { payloads });
+                }
+
+                let content = payloads
+                    .iter()
+                    .find_map(PayloadKind::content)
+                    .ok_or(Error::MissingContent)?;
+
+                let content_file = File::options()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&content_path)?;
+
+                reader.unpack_content(
+                    content,
+                    &mut ProgressWriter::new(&content_file, content.header.plain_size, &on_progress),
+                )?;
+
+                (payloads, content_file)
+            }
+        };
 
-        let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
         let indices = payloads
             .iter()
             .filter_map(PayloadKind::index)
             .flat_map(|p| &p.body)
             .collect::<Vec<_>>();
-
-        // If we don't have any files to unpack OR download was cached
-        // & all assets exist, we can skip unpacking
-        if indices.is_empty() || (self.was_cached && check_assets_exist(&indices, &self.installation)) {
-            return Ok(UnpackedAsset { payloads });
-        }
-
-        let content = payloads
-            .iter()
-            .find_map(PayloadKind::content)
-            .ok_or(Error::MissingContent)?;
-
-        let content_file = File::options()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&content_path)?;
-
-        reader.unpack_content(
-            content,
-            &mut ProgressWriter::new(&content_file, content.header.plain_size, &on_progress),
-        )?;
+        let num_indices = indices.len() as u64;
 
         indices
             .into_iter()
-            .map(|idx| {
+            .enumerate()
+            .map(|(i, idx)| {
+                if is_streamed {
+                    on_progress(Progress {
+                        delta: 1,
+                        completed: i as u64 + 1,
+                        total: num_indices,
+                    });
+                }
+
                 let path = asset_path(&self.installation, &format!("{:02x}", idx.digest));
 
                 // If file is already being unpacked by another worker, skip
@@ -252,8 +609,22 @@ impl Download {
 
         remove_file(&content_path)?;
 
+        self.check_delta(&payloads)?;
+
         Ok(UnpackedAsset { payloads })
     }
+
+    /// A delta only holds changed assets, so ensure the rest of the
+    /// package is still provided by the asset store
+    fn check_delta(&self, payloads: &[PayloadKind]) -> Result<(), Error> {
+        let layouts = payloads.iter().filter_map(PayloadKind::layout).flat_map(|p| &p.body);
+
+        if self.is_delta && !layout_assets_exist(layouts, &self.installation) {
+            return Err(Error::IncompleteDelta(self.id.clone()));
+        }
+
+        Ok(())
+    }
 }
 
 /// Returns true if all assets already exist in the installation
@@ -264,6 +635,11 @@ fn check_assets_exist(indices: &[&payload::Index], installation: &Installation)
     })
 }
 
+/// Returns a fully qualified filesystem path to unpack the content of the package `id` into
+fn content_path(installation: &Installation, id: &package::Id) -> PathBuf {
+    installation.cache_path("content").join(id)
+}
+
 /// Returns a fully qualified filesystem path to download the given hash ID into
 pub fn download_path(installation: &Installation, hash: &str) -> Result<PathBuf, Error> {
     if hash.len() < 5 {
@@ -294,6 +670,16 @@ pub fn asset_path(installation: &Installation, hash: &str) -> PathBuf {
     directory.join(hash)
 }
 
+impl repository::mirror::Failure for Error {
+    fn is_mirror_failure(&self) -> bool {
+        match self {
+            Error::Request(error) => error.is_mirror_failure(),
+            Error::HashMismatch { .. } => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Missing download hash")]
@@ -302,8 +688,18 @@ pub enum Error {
     MissingUri,
     #[error("Missing content payload")]
     MissingContent,
+    #[error("Delta for {0} is missing assets of the package it applies to")]
+    IncompleteDelta(package::Id),
+    #[error("Download hash mismatch, expected {expected} but got {actual}")]
+    HashMismatch { expected: String, actual: String },
     #[error("Malformed download hash: {0}")]
     MalformedHash(String),
+    #[error("{0} is not signed")]
+    Unsigned(package::Name),
+    #[error("{0} is not signed by a trusted key")]
+    UntrustedSignature(package::Name),
+    #[error("signature")]
+    Signature(#[from] signing::Error),
     #[error("stone format")]
     Format(#[from] stone::read::Error),
     #[error("invalid url")]
@@ -312,4 +708,55 @@ pub enum Error {
     Request(#[from] request::Error),
     #[error("io")]
     Io(#[from] io::Error),
+    #[error(transparent)]
+    NoLocation(#[from] repository::mirror::NoLocation),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::test::{ranged, serve, Response};
+
+    const STONE: &[u8] = b"the complete stone";
+
+    fn hash(bytes: &[u8]) -> String {
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    #[tokio::test]
+    async fn download_resumes() {
+        let honours_range = serve(ranged(STONE)).await;
+        let ignores_range = serve(|_| Response::new(200, STONE)).await;
+
+        let dir = std::env::temp_dir().join(format!("moss-download-{}", std::process::id()));
+        fs::create_dir_all(&dir).await.unwrap();
+
+        for (name, url, partial) in [
+            // Picks up where the interrupted download stopped
+            ("resumed", &honours_range, &STONE[..4]),
+            // Already complete, so the server has nothing left to send
+            ("complete", &honours_range, STONE),
+            // The server sends everything again, replacing the partial download
+            ("ignored", &ignores_range, &STONE[..4]),
+            // Left behind by another revision, it's restarted once it fails validation
+            ("stale", &honours_range, &b"stale"[..]),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, partial).await.unwrap();
+
+            download(url.clone(), &path, &hash(STONE), Some(STONE.len() as u64), None, |_| {})
+                .await
+                .unwrap();
+
+            assert_eq!(fs::read(&path).await.unwrap(), STONE, "{name}");
+        }
+
+        // A fresh download which doesn't match is an error and isn't kept
+        let path = dir.join("mismatch");
+        let result = download(honours_range, &path, &hash(b"other"), None, None, |_| {}).await;
+        assert!(matches!(result, Err(Error::HashMismatch { .. })));
+        assert!(!path.exists());
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
 }
@@ -32,17 +32,47 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool) -> Result<Timing,
     // Resolve input packages
     let input = resolve_input(pkgs, client)?;
 
-    // Add all inputs
-    let mut tx = client.registry.transaction()?;
+    // Get installed packages to check against
+    let installed = client.registry.list_installed(Flags::default()).collect::<Vec<_>>();
+    let is_installed = |p: &Package| installed.iter().any(|i| i.meta.name == p.meta.name);
 
-    tx.add(input.clone())?;
+    // Only use previous state in stateful mode
+    let previous_selections = match client.installation.active_state {
+        Some(id) if !client.is_ephemeral() => client.state_db.get(id)?.selections,
+        _ => vec![],
+    };
 
     // Resolve transaction to metadata
-    let resolved = client.resolve_packages(tx.finalize())?;
+    let (resolved, conflicts, replaced) = if client.is_ephemeral() {
+        let mut tx = client.registry.transaction()?;
+        tx.add(input.clone())?;
 
-    // Get installed packages to check against
-    let installed = client.registry.list_installed(Flags::default()).collect::<Vec<_>>();
-    let is_installed = |p: &Package| installed.iter().any(|i| i.meta.name == p.meta.name);
+        (client.resolve_packages(tx.finalize())?, vec![], vec![])
+    } else {
+        // Inputs already installed under the same name are left as they are
+        let incoming = input
+            .iter()
+            .filter(|id| client.registry.by_id(id).next().is_some_and(|p| !is_installed(&p)))
+            .cloned()
+            .collect();
+
+        // Installed packages clashing with incoming packages, these
+        // can be swapped out for the incoming package
+        let replacement = client.registry.transaction_with_replacements(
+            previous_selections.iter().map(|s| s.package.clone()).collect(),
+            incoming,
+        )?;
+
+        (
+            client.resolve_packages(replacement.transaction.finalize())?,
+            replacement.conflicts,
+            replacement.replaced,
+        )
+    };
+    let replaced = installed
+        .iter()
+        .filter(|i| replaced.contains(&i.id))
+        .collect::<Vec<_>>();
 
     // Get missing packages that are:
     //
@@ -80,6 +110,19 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool) -> Result<Timing,
     autoprint_columns(&missing);
     println!();
 
+    if !conflicts.is_empty() {
+        println!("The following conflicts were found:");
+        println!();
+        for conflict in &conflicts {
+            println!("  {conflict}");
+        }
+        println!();
+        println!("The following installed package(s) will be removed:");
+        println!();
+        autoprint_columns(&replaced);
+        println!();
+    }
+
     // Must we prompt?
     let result = if yes {
         true
@@ -101,23 +144,24 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool) -> Result<Timing,
     timing.fetch = instant.elapsed();
     instant = Instant::now();
 
-    // Calculate the new state of packages (old_state + missing)
-    let new_state_pkgs = {
-        // Only use previous state in stateful mode
-        let previous_selections = match client.installation.active_state {
-            Some(id) if !client.is_ephemeral() => client.state_db.get(id)?.selections,
-            _ => vec![],
-        };
-        let missing_selections = missing.iter().map(|p| Selection {
-            package: p.id.clone(),
-            // Package is explicit if it was one of the input
-            // packages provided by the user
-            explicit: input.iter().any(|id| *id == p.id),
-            reason: None,
-        });
-
-        missing_selections.chain(previous_selections).collect::<Vec<_>>()
-    };
+    // Calculate the new state of packages, keeping the selection
+    // of all previously selected packages which remain
+    let new_state_pkgs = resolved
+        .iter()
+        .map(|p| {
+            previous_selections
+                .iter()
+                .find(|s| s.package == p.id)
+                .cloned()
+                .unwrap_or_else(|| Selection {
+                    package: p.id.clone(),
+                    // Package is explicit if it was one of the input
+                    // packages provided by the user
+                    explicit: input.iter().any(|id| *id == p.id),
+                    reason: None,
+                })
+        })
+        .collect::<Vec<_>>();
 
     // Perfect, apply state.
     client.new_state(&new_state_pkgs, "Install")?;
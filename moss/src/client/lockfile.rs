@@ -0,0 +1,318 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Export the exact selections of a state and reproduce them on another root
+
+use std::{collections::BTreeSet, io, path::Path};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tui::{
+    dialoguer::{theme::ColorfulTheme, Confirm},
+    pretty::autoprint_columns,
+};
+
+use crate::{
+    client::{self, Client},
+    db, package, runtime,
+    state::{self, Selection},
+    Package, Registry, State,
+};
+
+/// Every selection of a state, pinned to exact package IDs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// State the selections were exported from
+    pub state: i32,
+    pub packages: Vec<Entry>,
+}
+
+/// A single selection of a [`Lockfile`]
+///
+/// `id` selects the package, the remaining metadata must match the
+/// package it resolves to for the selection to be reproduced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Entry {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub source_release: u64,
+    pub build_release: u64,
+    pub explicit: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Entry {
+    /// Returns true if `meta` is the package this entry was exported from
+    fn matches(&self, meta: &package::Meta) -> bool {
+        meta.name.to_string() == self.name
+            && meta.version_identifier == self.version
+            && meta.source_release == self.source_release
+            && meta.build_release == self.build_release
+    }
+}
+
+impl Lockfile {
+    /// Read a YAML (or JSON) lockfile
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Serialize as YAML
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// Build a [`Lockfile`] from the selections of state `id`
+pub fn export(client: &Client, id: state::Id) -> Result<Lockfile, Error> {
+    let state = client.state_db.get(id)?;
+
+    let mut packages = state
+        .selections
+        .into_iter()
+        .map(|selection| {
+            let meta = client.install_db.get(&selection.package)?;
+
+            Ok(Entry {
+                id: selection.package.to_string(),
+                name: meta.name.to_string(),
+                version: meta.version_identifier,
+                source_release: meta.source_release,
+                build_release: meta.build_release,
+                explicit: selection.explicit,
+                reason: selection.reason,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Lockfile {
+        state: state.id.into(),
+        packages,
+    })
+}
+
+/// Create a new state holding exactly the selections of `lockfile`
+///
+/// Returns `None` if the client is ephemeral or the active state already matches.
+pub fn apply(client: &Client, lockfile: &Lockfile, yes: bool) -> Result<Option<State>, Error> {
+    let selections = lockfile
+        .packages
+        .iter()
+        .map(|entry| Selection {
+            package: package::Id::from(entry.id.clone()),
+            explicit: entry.explicit,
+            reason: entry.reason.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    if !client.is_ephemeral() {
+        if let Some(active) = client.installation.active_state {
+            let active = client.state_db.get(active)?;
+
+            if selection_set(&active.selections) == selection_set(&selections) {
+                println!("The active state already matches the lockfile");
+                return Ok(None);
+            }
+        }
+    }
+
+    let missing = missing_packages(&client.registry, lockfile, client.is_ephemeral())?;
+
+    println!(
+        "The lockfile of state {} selects {} package(s)",
+        lockfile.state,
+        selections.len()
+    );
+    println!();
+
+    if !missing.is_empty() {
+        println!("The following package(s) will be fetched:");
+        println!();
+        autoprint_columns(&missing);
+        println!();
+    }
+
+    let result = if yes {
+        true
+    } else {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(" Do you wish to continue? ")
+            .default(false)
+            .interact()?
+    };
+    if !result {
+        return Err(Error::Cancelled);
+    }
+
+    runtime::block_on(client.cache_packages(&missing))?;
+
+    Ok(client.new_state(&selections, "Apply lockfile")?)
+}
+
+/// Packages of `lockfile` which must be fetched, ensuring every entry resolves to
+/// a package matching its recorded metadata. Stateful clients only need to fetch
+/// what isn't installed yet.
+fn missing_packages(registry: &Registry, lockfile: &Lockfile, ephemeral: bool) -> Result<Vec<Package>, Error> {
+    lockfile
+        .packages
+        .iter()
+        .filter_map(|entry| {
+            let id = package::Id::from(entry.id.clone());
+            let packages = registry.by_id(&id).collect::<Vec<_>>();
+
+            if packages.iter().any(|package| !entry.matches(&package.meta)) {
+                return Some(Err(Error::Mismatch(entry.name.clone(), id)));
+            }
+
+            if !ephemeral && packages.iter().any(|package| package.flags.installed) {
+                return None;
+            }
+
+            Some(
+                packages
+                    .into_iter()
+                    .find(|package| package.flags.available)
+                    .ok_or_else(|| Error::Unavailable(entry.name.clone(), id)),
+            )
+        })
+        .collect()
+}
+
+/// Selections compared irrespective of order
+fn selection_set(selections: &[Selection]) -> BTreeSet<(&package::Id, bool)> {
+    selections
+        .iter()
+        .map(|selection| (&selection.package, selection.explicit))
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("{0} ({1}) isn't available from any active repository")]
+    Unavailable(String, package::Id),
+
+    #[error("{0} ({1}) doesn't match the version recorded in the lockfile")]
+    Mismatch(String, package::Id),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("db")]
+    DB(#[from] db::Error),
+
+    #[error("lockfile")]
+    Format(#[from] serde_yaml::Error),
+
+    #[error("string processing")]
+    Dialog(#[from] tui::dialoguer::Error),
+
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::plugin::{self, Plugin};
+
+    fn package(name: &str, version: &str, flags: package::Flags) -> Package {
+        Package {
+            id: package::Id::from(format!("{name}-{version}")),
+            meta: package::Meta {
+                name: package::Name::from(name.to_string()),
+                version_identifier: version.to_string(),
+                source_release: 1,
+                build_release: 1,
+                ..Default::default()
+            },
+            flags,
+        }
+    }
+
+    fn entry(id: &str, name: &str, version: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+            source_release: 1,
+            build_release: 1,
+            explicit: true,
+            reason: None,
+        }
+    }
+
+    fn lockfile(packages: Vec<Entry>) -> Lockfile {
+        Lockfile { state: 1, packages }
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        let lockfile = Lockfile {
+            state: 4,
+            packages: vec![
+                entry("bash-5.2", "bash", "5.2"),
+                Entry {
+                    explicit: false,
+                    reason: Some("dependency of bash".to_string()),
+                    ..entry("ncurses-6.4", "ncurses", "6.4")
+                },
+            ],
+        };
+
+        let yaml = lockfile.to_yaml().unwrap();
+        assert!(yaml.contains("source-release: 1"));
+        assert_eq!(serde_yaml::from_str::<Lockfile>(&yaml).unwrap(), lockfile);
+    }
+
+    #[test]
+    fn missing_packages_are_verified() {
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            0,
+            vec![
+                package("bash", "5.2", package::Flags::new().with_available()),
+                package("ncurses", "6.4", package::Flags::new().with_installed()),
+            ],
+        )));
+
+        let missing = |lockfile, ephemeral| {
+            missing_packages(&registry, &lockfile, ephemeral)
+                .map(|packages| packages.into_iter().map(|package| package.id).collect::<Vec<_>>())
+        };
+
+        // Installed packages are only fetched for ephemeral clients
+        let both = lockfile(vec![
+            entry("bash-5.2", "bash", "5.2"),
+            entry("ncurses-6.4", "ncurses", "6.4"),
+        ]);
+        assert_eq!(
+            missing(both.clone(), false).unwrap(),
+            [package::Id::from("bash-5.2".to_string())]
+        );
+        assert!(matches!(
+            missing(both, true),
+            Err(Error::Unavailable(name, _)) if name == "ncurses"
+        ));
+
+        // Unknown IDs can't be reproduced
+        assert!(matches!(
+            missing(lockfile(vec![entry("zsh-5.9", "zsh", "5.9")]), false),
+            Err(Error::Unavailable(name, _)) if name == "zsh"
+        ));
+
+        // Nor can IDs resolving to a package other than the one recorded
+        assert!(matches!(
+            missing(lockfile(vec![entry("bash-5.2", "bash", "5.1")]), false),
+            Err(Error::Mismatch(name, _)) if name == "bash"
+        ));
+    }
+}
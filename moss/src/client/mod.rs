@@ -10,8 +10,12 @@
 
 use std::{
     borrow::Borrow,
+    collections::BTreeSet,
     fmt, io,
-    os::{fd::RawFd, unix::fs::symlink},
+    os::{
+        fd::RawFd,
+        unix::fs::{symlink, PermissionsExt},
+    },
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -23,28 +27,34 @@ use nix::{
     fcntl::{self, OFlag},
     libc::{syscall, SYS_renameat2, AT_FDCWD, RENAME_EXCHANGE},
     sys::stat::{fchmodat, mkdirat, Mode},
-    unistd::{close, linkat, mkdir, symlinkat},
+    unistd::{close, mkdir, symlinkat},
 };
 use postblit::TriggerScope;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use stone::{payload::layout, read::PayloadKind};
 use thiserror::Error;
 use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
-use vfs::tree::{builder::TreeBuilder, BlitFile, Element};
+use vfs::tree::{self, builder::TreeBuilder, BlitFile, Element};
 
+use self::bundle::bundle;
 use self::install::install;
+use self::lockfile::Lockfile;
 use self::prune::prune;
 use self::verify::verify;
 use crate::{
-    db, environment, installation, package,
+    db, environment, hold, installation, package,
     registry::plugin::{self, Plugin},
-    repository, runtime, signal,
+    repository, request, runtime, signal,
     state::{self, Selection},
-    Installation, Package, Registry, Signal, State,
+    system, Installation, Package, Registry, Signal, State,
 };
 
+pub mod blit;
 pub mod boot;
+pub mod bundle;
 pub mod cache;
 pub mod install;
+pub mod lockfile;
 mod postblit;
 pub mod prune;
 mod verify;
@@ -130,11 +140,41 @@ impl Client {
         matches!(self.scope, Scope::Ephemeral { .. })
     }
 
+    /// The configured [`blit::Mode`] new trees are blitted with
+    pub fn blit_mode(&self) -> blit::Mode {
+        blit::Config::load(&self.config).mode
+    }
+
+    /// Packages held back from sync on this installation
+    pub fn holds(&self) -> hold::Map {
+        hold::Map::load(&self.config)
+    }
+
+    /// The declarative [`system::Config`] of this installation
+    pub fn system_config(&self) -> system::Config {
+        system::Config::load(&self.config)
+    }
+
     /// Perform an installation via [`install::install`]
     pub fn install(&mut self, packages: &[&str], yes: bool) -> Result<install::Timing, install::Error> {
         install(self, packages, yes)
     }
 
+    /// Copy the stones of `packages` and their full dependency closure into `output`
+    pub fn bundle(&self, packages: &[&str], output: &Path) -> Result<Vec<(Package, String)>, bundle::Error> {
+        bundle(self, packages, output)
+    }
+
+    /// Export the selections of state `id` as a [`Lockfile`]
+    pub fn export_state(&self, id: state::Id) -> Result<Lockfile, lockfile::Error> {
+        lockfile::export(self, id)
+    }
+
+    /// Reproduce the exact selections of `lockfile` as a new state
+    pub fn apply_lockfile(&self, lockfile: &Lockfile, yes: bool) -> Result<Option<State>, lockfile::Error> {
+        lockfile::apply(self, lockfile, yes)
+    }
+
     /// Transition to an ephemeral client that doesn't record state changes
     /// and blits to a different root.
     ///
@@ -180,6 +220,20 @@ impl Client {
         Ok(())
     }
 
+    /// Search the file-list indexes of active repositories for available
+    /// packages shipping a path matching `pattern`
+    pub fn search_files(&self, pattern: &fnmatch::Pattern) -> Result<Vec<(Package, String)>, Error> {
+        Ok(self
+            .repositories
+            .search_files(pattern)?
+            .into_iter()
+            .filter_map(|found| {
+                let package = self.registry.by_id(&found.package).next()?;
+                Some((package, found.path))
+            })
+            .collect())
+    }
+
     pub fn verify(&self, yes: bool, verbose: bool) -> Result<(), Error> {
         if self.scope.is_ephemeral() {
             return Err(Error::EphemeralProhibitedOperation);
@@ -240,6 +294,15 @@ impl Client {
             return Err(Error::StateAlreadyActive(id));
         }
 
+        // The archived tree was recycled by an incremental blit, so it needs a full reblit
+        if !self.installation.root_path(new.id.to_string()).join("usr").exists() {
+            let fstree = self.blit_root(new.selections.iter().map(|selection| &selection.package))?;
+
+            self.apply_stateful_blit(fstree, &new, Some(old))?;
+
+            return Ok(old);
+        }
+
         let staging_dir = self.installation.staging_dir();
 
         // Ensure staging dir exists
@@ -286,10 +349,10 @@ impl Client {
 
         let old_state = self.installation.active_state;
 
-        let fstree = self.blit_root(selections.iter().map(|s| &s.package))?;
-
         match &self.scope {
             Scope::Stateful => {
+                let fstree = self.blit_root_incremental(selections.iter().map(|s| &s.package))?;
+
                 // Add to db
                 let state = self.state_db.add(selections, Some(&summary.to_string()), None)?;
 
@@ -298,6 +361,8 @@ impl Client {
                 Ok(Some(state))
             }
             Scope::Ephemeral { blit_root } => {
+                let fstree = self.blit_root(selections.iter().map(|s| &s.package))?;
+
                 self.apply_ephemeral_blit(fstree, blit_root)?;
 
                 Ok(None)
@@ -465,11 +530,16 @@ impl Client {
         let cached = stream::iter(packages)
             .map(|package| async {
                 let package: &Package = package.borrow();
+                let delta = self.applicable_delta(package);
+                let download_size = match delta {
+                    Some(delta) => Some(delta.size),
+                    None => package.meta.download_size,
+                };
 
                 // Setup the progress bar and set as downloading
                 let progress_bar = multi_progress.insert_before(
                     &total_progress,
-                    ProgressBar::new(package.meta.download_size.unwrap_or_default())
+                    ProgressBar::new(download_size.unwrap_or_default())
                         .with_message(format!(
                             "{} {}",
                             "Downloading".blue(),
@@ -486,11 +556,13 @@ impl Client {
                 progress_bar.enable_steady_tick(Duration::from_millis(150));
 
                 // Download and update progress
-                let download = cache::fetch(&package.meta, &self.installation, |progress| {
+                let origin = self.repositories.origin(&package.id);
+                let download = cache::fetch(&package.meta, delta, &self.installation, origin, |progress| {
                     progress_bar.inc(progress.delta);
                 })
                 .await?;
                 let is_cached = download.was_cached;
+                let is_delta = download.is_delta;
 
                 // Move rest of blocking code to threadpool
 
@@ -523,10 +595,18 @@ impl Client {
                     let cached_tag = is_cached
                         .then_some(format!("{}", " (cached)".dim()))
                         .unwrap_or_default();
+                    let delta_tag = is_delta.then_some(format!("{}", " (delta)".dim())).unwrap_or_default();
 
                     // Write installed line
-                    multi_progress
-                        .suspend(|| println!("{} {}{}", "Installed".green(), package_name.clone().bold(), cached_tag,));
+                    multi_progress.suspend(|| {
+                        println!(
+                            "{} {}{}{}",
+                            "Installed".green(),
+                            package_name.clone().bold(),
+                            delta_tag,
+                            cached_tag,
+                        )
+                    });
 
                     // Inc total progress by 1
                     total_progress.inc(1);
@@ -536,7 +616,7 @@ impl Client {
                 .await
             })
             // Use max network concurrency since we download files here
-            .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
+            .buffer_unordered(request::concurrency())
             .try_collect::<Vec<_>>()
             .await?;
 
@@ -578,6 +658,17 @@ impl Client {
         Ok(())
     }
 
+    /// Returns the delta advertised for `package` if all assets of
+    /// the package it applies to are already in the asset store
+    fn applicable_delta<'a>(&self, package: &'a Package) -> Option<&'a package::meta::Delta> {
+        let delta = package.meta.delta.as_ref()?;
+        let layouts = self.layout_db.query([&delta.from]).ok()?;
+
+        (!layouts.is_empty()
+            && cache::layout_assets_exist(layouts.iter().map(|(_, layout)| layout), &self.installation))
+        .then_some(delta)
+    }
+
     /// Build a [`vfs::Tree`] for the specified package IDs
     ///
     /// Returns a newly built vfs Tree to plan the filesystem operations for blitting
@@ -637,48 +728,237 @@ impl Client {
         // undirt.
         fs::remove_dir_all(&blit_target)?;
 
+        let linker = blit::Linker::new(cache_fd, self.blit_mode());
+
         if let Some(root) = tree.structured() {
             let _ = mkdir(&blit_target, Mode::from_bits_truncate(0o755));
             let root_dir = fcntl::open(&blit_target, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty())?;
 
             if let Element::Directory(_, _, children) = root {
-                for child in children {
-                    self.blit_element(root_dir, cache_fd, child, &progress)?;
-                }
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(environment::MAX_DISK_CONCURRENCY)
+                    .build()?;
+
+                pool.install(|| Self::blit_children(root_dir, &linker, children, &progress))?;
             }
 
             close(root_dir)?;
         }
 
+        if let Scope::Stateful = self.scope {
+            blit::record_mode(&blit_target, linker.mode())?;
+        }
+
         Ok(tree)
     }
 
+    /// Blit the packages to the staging tree by recycling the archived tree of
+    /// another state, only writing the entries which differ between both.
+    ///
+    /// The archive most similar to the new selection is picked among those which
+    /// [`Self::recyclable_state`] allows. As the archive is consumed, that state is
+    /// fully reblitted by [`Self::activate_state`] if it's ever activated again.
+    ///
+    /// Falls back to [`Self::blit_root`] if there's no archived tree to recycle.
+    fn blit_root_incremental<'a>(
+        &self,
+        packages: impl IntoIterator<Item = &'a package::Id>,
+    ) -> Result<vfs::tree::Tree<PendingFile>, Error> {
+        let packages = packages.into_iter().collect::<BTreeSet<_>>();
+
+        let mode = self.blit_mode();
+
+        let Some(base) = self.recyclable_state(&packages, mode)? else {
+            return self.blit_root(packages);
+        };
+
+        let progress = ProgressBar::new(1).with_style(
+            ProgressStyle::with_template("\n|{bar:20.red/blue}| {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("■≡=- "),
+        );
+        progress.set_message(format!("Blitting filesystem from state #{}", base.id));
+        progress.enable_steady_tick(Duration::from_millis(150));
+        progress.tick();
+
+        let old_tree = self.vfs(base.selections.iter().map(|s| &s.package))?;
+        let tree = self.vfs(packages)?;
+
+        let changes = old_tree.diff(&tree, PendingFile::blits_same);
+
+        progress.set_length(changes.len() as u64);
+        progress.set_position(0_u64);
+
+        let staging_dir = self.installation.staging_dir();
+        let archive_dir = self.installation.root_path(base.id.to_string());
+
+        // undirt.
+        fs::remove_dir_all(&staging_dir)?;
+        fs::create_dir_all(&staging_dir)?;
+
+        fs::rename(archive_dir.join("usr"), staging_dir.join("usr"))?;
+        fs::remove_dir_all(&archive_dir)?;
+
+        let cache_fd = fcntl::open(
+            &self.installation.assets_path("v2"),
+            OFlag::O_DIRECTORY | OFlag::O_RDONLY,
+            Mode::empty(),
+        )?;
+        let linker = blit::Linker::new(cache_fd, mode);
+
+        for change in changes {
+            match change {
+                tree::Change::Removed(old) => remove_blitted(&staging_dir, old)?,
+                tree::Change::Added(new) => Self::blit_at(&staging_dir, &linker, new)?,
+                tree::Change::Modified { old, new } => {
+                    // Keep directories in place so their contents survive
+                    if matches!(old.layout.entry, layout::Entry::Directory(_))
+                        && matches!(new.layout.entry, layout::Entry::Directory(_))
+                    {
+                        fs::set_permissions(
+                            staging_dir.join(new.path().trim_start_matches('/')),
+                            std::fs::Permissions::from_mode(new.layout.mode),
+                        )?;
+                    } else {
+                        remove_blitted(&staging_dir, old)?;
+                        Self::blit_at(&staging_dir, &linker, new)?;
+                    }
+                }
+            }
+
+            progress.inc(1);
+        }
+
+        close(cache_fd)?;
+
+        // Drop whatever else the recycled tree picked up, i.e. trigger output
+        // which is regenerated for the new state
+        let paths = tree.iter().map(BlitFile::path).collect::<BTreeSet<_>>();
+        remove_untracked(&staging_dir.join("usr"), "/usr", &paths)?;
+        blit::record_mode(&staging_dir, linker.mode())?;
+
+        progress.finish_and_clear();
+
+        Ok(tree)
+    }
+
+    /// Returns the state with an archived tree closest to `packages`, which can
+    /// be recycled for blitting them with `mode`
+    ///
+    /// Tagged states are never recycled, nor is the newest state before the active
+    /// one, so both remain ready to be activated again.
+    fn recyclable_state(&self, packages: &BTreeSet<&package::Id>, mode: blit::Mode) -> Result<Option<State>, Error> {
+        let tagged = self.state_db.list_tagged()?;
+        let states = self.state_db.all()?;
+
+        let rollback = self
+            .installation
+            .active_state
+            .and_then(|active| states.iter().map(|state| state.id).filter(|id| *id < active).max());
+
+        let state = states
+            .into_iter()
+            .filter(|state| Some(state.id) != self.installation.active_state)
+            .filter(|state| Some(state.id) != rollback && !tagged.contains(&state.id))
+            .filter(|state| blit::recorded_mode(&self.installation.root_path(state.id.to_string())) == mode)
+            .filter(|state| {
+                // Only trust trees which still record the state they were blitted for
+                let state_id = self
+                    .installation
+                    .root_path(state.id.to_string())
+                    .join("usr")
+                    .join(".stateID");
+
+                fs::read_to_string(state_id).is_ok_and(|id| id.trim() == state.id.to_string())
+            })
+            .min_by_key(|state| {
+                let selected = state.selections.iter().map(|s| &s.package).collect::<BTreeSet<_>>();
+                let difference = selected.symmetric_difference(packages).count();
+
+                // Prefer the newest state on a tie
+                (difference, std::cmp::Reverse(state.id))
+            });
+
+        Ok(state)
+    }
+
+    /// Write a single inode to its place below `root`
+    fn blit_at(root: &Path, linker: &blit::Linker, item: &PendingFile) -> Result<(), Error> {
+        let path = item.path();
+        let (Some(parent), Some(name)) = (vfs::path::parent(&path), vfs::path::file_name(&path)) else {
+            return Ok(());
+        };
+
+        let target = root.join(path.trim_start_matches('/'));
+
+        // Trigger output may have taken the place of the new entry
+        match target.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&target)?,
+            Ok(_) => fs::remove_file(&target)?,
+            Err(_) => {}
+        }
+
+        let parent = fcntl::open(
+            &root.join(parent.trim_start_matches('/')),
+            OFlag::O_RDONLY | OFlag::O_DIRECTORY,
+            Mode::empty(),
+        )?;
+        let result = Self::blit_element_item(parent, linker, name, item);
+        close(parent)?;
+
+        result
+    }
+
+    /// Write the children of a directory to the staging tree, blitting
+    /// its subdirectories concurrently as independent subtrees.
+    ///
+    /// All subdirectories are attempted before the first error in tree order
+    /// is returned, so failures don't depend on how the work was scheduled.
+    fn blit_children(
+        parent: RawFd,
+        linker: &blit::Linker,
+        children: Vec<Element<'_, PendingFile>>,
+        progress: &ProgressBar,
+    ) -> Result<(), Error> {
+        let (directories, files): (Vec<_>, Vec<_>) = children
+            .into_iter()
+            .partition(|child| matches!(child, Element::Directory(..)));
+
+        for file in files {
+            Self::blit_element(parent, linker, file, progress)?;
+        }
+
+        directories
+            .into_par_iter()
+            .map(|directory| Self::blit_element(parent, linker, directory, progress))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .collect()
+    }
+
     /// Recursively write a directory, or a single flat inode, to the staging tree.
     /// Care is taken to retain the directory file descriptor to avoid costly path
     /// resolution at runtime.
     fn blit_element(
-        &self,
         parent: RawFd,
-        cache: RawFd,
-        element: Element<PendingFile>,
+        linker: &blit::Linker,
+        element: Element<'_, PendingFile>,
         progress: &ProgressBar,
     ) -> Result<(), Error> {
         progress.inc(1);
         match element {
             Element::Directory(name, item, children) => {
                 // Construct within the parent
-                self.blit_element_item(parent, cache, name, item)?;
+                Self::blit_element_item(parent, linker, name, item)?;
 
                 // open the new dir
                 let newdir = fcntl::openat(parent, name, OFlag::O_RDONLY | OFlag::O_DIRECTORY, Mode::empty())?;
-                for child in children.into_iter() {
-                    self.blit_element(newdir, cache, child, progress)?;
-                }
+                let result = Self::blit_children(newdir, linker, children, progress);
                 close(newdir)?;
-                Ok(())
+                result
             }
             Element::Child(name, item) => {
-                self.blit_element_item(parent, cache, name, item)?;
+                Self::blit_element_item(parent, linker, name, item)?;
                 Ok(())
             }
         }
@@ -689,10 +969,10 @@ impl Client {
     /// # Arguments
     ///
     /// * `parent`  - raw file descriptor for parent directory in which the inode is being record to
-    /// * `cache`   - raw file descriptor for the system asset pool tree
+    /// * `linker`  - writes regular files from the system asset pool tree
     /// * `subpath` - the base name of the new inode
     /// * `item`    - New inode being recorded
-    fn blit_element_item(&self, parent: RawFd, cache: RawFd, subpath: &str, item: &PendingFile) -> Result<(), Error> {
+    fn blit_element_item(parent: RawFd, linker: &blit::Linker, subpath: &str, item: &PendingFile) -> Result<(), Error> {
         match &item.layout.entry {
             layout::Entry::Regular(id, _) => {
                 let hash = format!("{:02x}", id);
@@ -704,13 +984,7 @@ impl Client {
 
                 // Link relative from cache to target
                 let fp = directory.join(hash);
-                linkat(
-                    Some(cache),
-                    fp.to_str().unwrap(),
-                    Some(parent),
-                    subpath,
-                    nix::unistd::LinkatFlags::NoSymlinkFollow,
-                )?;
+                linker.link(fp.to_str().unwrap(), parent, subpath)?;
 
                 // Fix permissions
                 fchmodat(
@@ -738,17 +1012,43 @@ impl Client {
     }
 }
 
+/// Symlinks at the root of every installation into `/usr`, as (source, target)
+const ROOT_LINKS: [(&str, &str); 5] = [
+    ("usr/sbin", "sbin"),
+    ("usr/bin", "bin"),
+    ("usr/lib", "lib"),
+    ("usr/lib", "lib64"),
+    ("usr/lib32", "lib32"),
+];
+
+/// Resolve the absolute `path` lexically, dropping `.` and `..` components and following
+/// the root links into `/usr`, i.e. `/bin/../lib64/libc.so.6` => `/usr/lib/libc.so.6`
+pub fn canonical_path(path: &str) -> String {
+    let mut components = vec![];
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+
+        // Follow root links as they're reached, so any `..` after them applies within `/usr`
+        if let [top] = components.as_slice() {
+            if let Some((source, _)) = ROOT_LINKS.iter().find(|(_, target)| target == top) {
+                components = source.split('/').collect();
+            }
+        }
+    }
+
+    format!("/{}", components.join("/"))
+}
+
 /// Add root symlinks & os-release file
 fn create_root_links(root: &Path) -> Result<(), io::Error> {
-    let links = vec![
-        ("usr/sbin", "sbin"),
-        ("usr/bin", "bin"),
-        ("usr/lib", "lib"),
-        ("usr/lib", "lib64"),
-        ("usr/lib32", "lib32"),
-    ];
-
-    'linker: for (source, target) in links.into_iter() {
+    'linker: for (source, target) in ROOT_LINKS {
         let final_target = root.join(target);
         let staging_target = root.join(format!("{target}.next"));
 
@@ -766,6 +1066,44 @@ fn create_root_links(root: &Path) -> Result<(), io::Error> {
     Ok(())
 }
 
+/// Remove a blitted inode from its place below `root`
+fn remove_blitted(root: &Path, item: &PendingFile) -> Result<(), io::Error> {
+    let target = root.join(item.path().trim_start_matches('/'));
+
+    let result = match target.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&target),
+        Ok(_) => fs::remove_file(&target),
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Recursively remove all entries of `dir`, found at `path` within a tree,
+/// which aren't part of the tree's `paths`
+fn remove_untracked(dir: &Path, path: &str, paths: &BTreeSet<String>) -> Result<(), io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let entry_path = vfs::path::join(path, &entry.file_name().to_string_lossy());
+        let is_dir = entry.file_type()?.is_dir();
+
+        if !paths.contains(&entry_path) {
+            if is_dir {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        } else if is_dir {
+            remove_untracked(&entry.path(), &entry_path, paths)?;
+        }
+    }
+
+    Ok(())
+}
+
 fn record_state_id(root: &Path, state: state::Id) -> Result<(), Error> {
     let usr = root.join("usr");
     fs::create_dir_all(&usr)?;
@@ -825,6 +1163,14 @@ pub struct PendingFile {
     pub layout: layout::Layout,
 }
 
+impl PendingFile {
+    /// Returns true if blitting `other` produces an identical inode,
+    /// regardless of the package providing it
+    pub fn blits_same(&self, other: &Self) -> bool {
+        self.layout.entry == other.layout.entry && self.layout.mode == other.layout.mode
+    }
+}
+
 impl BlitFile for PendingFile {
     /// Match internal kind to minimalist vfs kind
     fn kind(&self) -> vfs::tree::Kind {
@@ -954,6 +1300,8 @@ pub enum Error {
     Filesystem(#[from] vfs::tree::Error),
     #[error("blit")]
     Blit(#[from] Errno),
+    #[error("blit thread pool")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
     #[error("postblit")]
     PostBlit(#[from] postblit::Error),
     #[error("boot")]
@@ -967,3 +1315,315 @@ pub enum Error {
     #[error("ignore signals during blit")]
     BlitSignalIgnore(#[from] signal::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, os::unix::fs::MetadataExt};
+
+    use super::*;
+
+    /// Assets of the blit fixture, as (hash, content)
+    const ASSETS: [(u128, &str); 2] = [
+        (0x1234_5678_9abc_def0_1234_5678_9abc_def0, "#!/bin/sh\n"),
+        (0x0fed_cba9_8765_4321_0fed_cba9_8765_4321, "readme\n"),
+    ];
+
+    /// Path of the asset with `hash` within the asset store at `cache`
+    fn asset_path(cache: &Path, hash: u128) -> PathBuf {
+        let hash = format!("{hash:02x}");
+        cache.join(&hash[..2]).join(&hash[2..4]).join(&hash[4..6]).join(hash)
+    }
+
+    /// Blit a small tree from a fresh asset store using `threads` workers,
+    /// returning the asset store, the blitted root and the mode files were written with
+    fn blit_fixture(name: &str, mode: blit::Mode, threads: usize) -> (PathBuf, PathBuf, blit::Mode) {
+        let dir = std::env::temp_dir().join(format!("moss-blit-{name}-{}", std::process::id()));
+        let cache = dir.join("assets");
+        let root = dir.join("root");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&root).unwrap();
+        for (hash, content) in ASSETS {
+            let path = asset_path(&cache, hash);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let mut builder = TreeBuilder::new();
+        for (mode, entry) in [
+            (0o755, layout::Entry::Directory("bin".into())),
+            (0o755, layout::Entry::Regular(ASSETS[0].0, "bin/tool".into())),
+            (0o777, layout::Entry::Symlink("tool".into(), "bin/alias".into())),
+            (0o755, layout::Entry::Directory("share".into())),
+            (0o750, layout::Entry::Directory("share/doc".into())),
+            (0o640, layout::Entry::Regular(ASSETS[1].0, "share/doc/readme".into())),
+        ] {
+            builder.push(PendingFile {
+                id: package::Id::from("fixture".to_string()),
+                layout: layout::Layout {
+                    uid: 0,
+                    gid: 0,
+                    mode,
+                    tag: 0,
+                    entry,
+                },
+            });
+        }
+        builder.bake();
+        let tree = builder.tree().unwrap();
+
+        let cache_fd = fcntl::open(&cache, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty()).unwrap();
+        let root_fd = fcntl::open(&root, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty()).unwrap();
+        let linker = blit::Linker::new(cache_fd, mode);
+
+        let Some(Element::Directory(_, _, children)) = tree.structured() else {
+            panic!("fixture tree has no root");
+        };
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| Client::blit_children(root_fd, &linker, children, &ProgressBar::hidden()))
+            .unwrap();
+
+        close(root_fd).unwrap();
+        close(cache_fd).unwrap();
+
+        (cache, root, linker.mode())
+    }
+
+    /// Assert the blitted fixture at `root` has the expected contents and modes
+    fn assert_blitted(root: &Path) {
+        let usr = root.join("usr");
+        let mode = |path: &Path| fs::symlink_metadata(path).unwrap().mode() & 0o7777;
+
+        assert_eq!(fs::read_to_string(usr.join("bin/tool")).unwrap(), ASSETS[0].1);
+        assert_eq!(fs::read_to_string(usr.join("share/doc/readme")).unwrap(), ASSETS[1].1);
+        assert_eq!(fs::read_link(usr.join("bin/alias")).unwrap(), Path::new("tool"));
+
+        assert_eq!(mode(&usr.join("bin/tool")), 0o755);
+        assert_eq!(mode(&usr.join("share/doc")), 0o750);
+        assert_eq!(mode(&usr.join("share/doc/readme")), 0o640);
+    }
+
+    #[test]
+    fn canonical_paths() {
+        assert_eq!(canonical_path("/usr/bin/bash"), "/usr/bin/bash");
+        assert_eq!(canonical_path("/bin/bash"), "/usr/bin/bash");
+        assert_eq!(canonical_path("/lib64//libc.so.6"), "/usr/lib/libc.so.6");
+        assert_eq!(canonical_path("/usr/./share/../bin/"), "/usr/bin");
+        assert_eq!(canonical_path("/bin/../lib32/libz.so"), "/usr/lib32/libz.so");
+        assert_eq!(canonical_path("/../../sbin"), "/usr/sbin");
+        assert_eq!(canonical_path("etc/os-release"), "/etc/os-release");
+    }
+
+    #[test]
+    fn blit_serial_and_parallel() {
+        for threads in [1, 4] {
+            let (cache, root, mode) = blit_fixture(&format!("hardlink-{threads}"), blit::Mode::Hardlink, threads);
+
+            assert_eq!(mode, blit::Mode::Hardlink);
+            assert_blitted(&root);
+
+            // Files share their inode with the asset
+            for (hash, path) in [(ASSETS[0].0, "usr/bin/tool"), (ASSETS[1].0, "usr/share/doc/readme")] {
+                assert_eq!(
+                    fs::metadata(root.join(path)).unwrap().ino(),
+                    fs::metadata(asset_path(&cache, hash)).unwrap().ino()
+                );
+            }
+
+            fs::remove_dir_all(cache.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn blit_reflink() {
+        let (cache, root, mode) = blit_fixture("reflink", blit::Mode::Reflink, 4);
+        let tool = root.join("usr/bin/tool");
+        let asset = asset_path(&cache, ASSETS[0].0);
+
+        assert_blitted(&root);
+
+        match mode {
+            // Writes to the clone never reach the asset
+            blit::Mode::Reflink => {
+                assert_ne!(fs::metadata(&tool).unwrap().ino(), fs::metadata(&asset).unwrap().ino());
+
+                fs::write(&tool, "modified").unwrap();
+                assert_eq!(fs::read_to_string(&asset).unwrap(), ASSETS[0].1);
+            }
+            // The file created for the failed clone was replaced by the hardlink
+            blit::Mode::Hardlink => {
+                assert_eq!(fs::metadata(&tool).unwrap().ino(), fs::metadata(&asset).unwrap().ino());
+                assert_eq!(fs::metadata(&asset).unwrap().nlink(), 2);
+            }
+        }
+
+        let entries = |path: &str| {
+            fs::read_dir(root.join(path))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<BTreeSet<_>>()
+        };
+        assert_eq!(entries("usr/bin"), ["alias", "tool"].map(str::to_string).into());
+        assert_eq!(entries("usr/share/doc"), ["readme".to_string()].into());
+
+        fs::remove_dir_all(cache.parent().unwrap()).unwrap();
+    }
+
+    /// Add package `id` with the (mode, entry) `layouts` to the layout db of `client`
+    fn add_package(client: &Client, id: &str, layouts: Vec<(u32, layout::Entry)>) -> package::Id {
+        let id = package::Id::from(id.to_string());
+        let layouts = layouts
+            .into_iter()
+            .map(|(mode, entry)| layout::Layout {
+                uid: 0,
+                gid: 0,
+                mode,
+                tag: 0,
+                entry,
+            })
+            .collect::<Vec<_>>();
+
+        client
+            .layout_db
+            .batch_add(layouts.iter().map(|layout| (&id, layout)))
+            .unwrap();
+
+        id
+    }
+
+    /// Every entry below `dir` by relative path, along with its mode and
+    /// its contents or link target
+    fn snapshot(dir: &Path) -> BTreeMap<PathBuf, (u32, Option<String>)> {
+        let mut entries = BTreeMap::new();
+        let mut pending = vec![dir.to_path_buf()];
+
+        while let Some(parent) = pending.pop() {
+            for entry in fs::read_dir(&parent).unwrap() {
+                let path = entry.unwrap().path();
+                let metadata = fs::symlink_metadata(&path).unwrap();
+
+                let contents = if metadata.is_dir() {
+                    pending.push(path.clone());
+                    None
+                } else if metadata.is_symlink() {
+                    Some(fs::read_link(&path).unwrap().display().to_string())
+                } else {
+                    Some(fs::read_to_string(&path).unwrap())
+                };
+
+                entries.insert(
+                    path.strip_prefix(dir).unwrap().to_path_buf(),
+                    (metadata.mode() & 0o7777, contents),
+                );
+            }
+        }
+
+        entries
+    }
+
+    #[test]
+    fn incremental_blit_matches_full_blit() {
+        let root = std::env::temp_dir().join(format!("moss-incremental-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let installation = Installation::open(&root, None).unwrap();
+        let mut client = Client::new("test", installation).unwrap();
+
+        for (hash, content) in ASSETS {
+            let path = asset_path(&client.installation.assets_path("v2"), hash);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let (script, readme) = (ASSETS[0].0, ASSETS[1].0);
+        let old = add_package(
+            &client,
+            "old",
+            vec![
+                (0o755, layout::Entry::Directory("bin".into())),
+                (0o755, layout::Entry::Regular(script, "bin/tool".into())),
+                (0o777, layout::Entry::Symlink("tool".into(), "bin/alias".into())),
+                (0o755, layout::Entry::Directory("share".into())),
+                (0o755, layout::Entry::Directory("share/swap".into())),
+                (0o644, layout::Entry::Regular(readme, "share/swap/readme".into())),
+                (0o755, layout::Entry::Directory("lib".into())),
+                (0o644, layout::Entry::Regular(readme, "lib/swap".into())),
+            ],
+        );
+        let new = add_package(
+            &client,
+            "new",
+            vec![
+                (0o755, layout::Entry::Directory("bin".into())),
+                (0o644, layout::Entry::Regular(readme, "bin/tool".into())),
+                (0o777, layout::Entry::Symlink("tool".into(), "bin/alias".into())),
+                (0o700, layout::Entry::Directory("share".into())),
+                (0o755, layout::Entry::Regular(script, "share/swap".into())),
+                (0o755, layout::Entry::Directory("lib".into())),
+                (0o755, layout::Entry::Directory("lib/swap".into())),
+                (0o644, layout::Entry::Regular(readme, "lib/swap/readme".into())),
+            ],
+        );
+
+        let [archived, rollback, active] = [(); 3].map(|_| {
+            client
+                .state_db
+                .add(&[Selection::explicit(old.clone())], None, None)
+                .unwrap()
+        });
+        client.installation.active_state = Some(active.id);
+
+        // Archive the trees of both inactive states
+        for state in [&archived, &rollback] {
+            client.blit_root([&old]).unwrap();
+
+            let archive = client.installation.root_path(state.id.to_string());
+            fs::create_dir_all(&archive).unwrap();
+            fs::rename(client.installation.staging_path("usr"), archive.join("usr")).unwrap();
+            record_state_id(&archive, state.id).unwrap();
+        }
+
+        // Trigger output picked up by the archived tree
+        let archive = client.installation.root_path(archived.id.to_string());
+        fs::write(archive.join("usr/share/trigger.cache"), "cache").unwrap();
+
+        // Neither the rollback nor tagged states are recycled
+        let packages = BTreeSet::from([&new]);
+        let recyclable = |client: &Client| {
+            client
+                .recyclable_state(&packages, blit::Mode::Hardlink)
+                .unwrap()
+                .map(|state| state.id)
+        };
+        assert_eq!(recyclable(&client), Some(archived.id));
+        client.state_db.set_tag(archived.id, Some("good"), None).unwrap();
+        assert_eq!(recyclable(&client), None);
+        client.state_db.set_tag(archived.id, None, None).unwrap();
+
+        client.blit_root_incremental([&new]).unwrap();
+        let incremental = snapshot(&client.installation.staging_path("usr"));
+
+        assert!(!archive.exists());
+        assert!(client
+            .installation
+            .root_path(rollback.id.to_string())
+            .join("usr")
+            .exists());
+        assert!(!incremental.contains_key(Path::new("share/trigger.cache")));
+        assert_eq!(
+            incremental.get(Path::new("share/swap")),
+            Some(&(0o755, Some(ASSETS[0].1.to_string())))
+        );
+        assert_eq!(incremental.get(Path::new("lib/swap")), Some(&(0o755, None)));
+
+        // Recycling yields exactly the tree of a full blit
+        client.blit_root([&new]).unwrap();
+        assert_eq!(incremental, snapshot(&client.installation.staging_path("usr")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
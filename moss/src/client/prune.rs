@@ -28,7 +28,8 @@ use crate::{client::cache, db, package, state, Installation, State};
 /// The prune strategy for removing old states
 #[derive(Debug, Clone, Copy)]
 pub enum Strategy {
-    /// Keep the most recent N states, remove the rest
+    /// Keep the most recent N states, remove the rest.
+    /// Tagged states are never removed, nor counted towards N
     KeepRecent { keep: u64, include_newer: bool },
     /// Removes a specific state
     Remove(state::Id),
@@ -63,9 +64,12 @@ pub fn prune(
     // Find each state we need to remove
     let removal_ids = match strategy {
         Strategy::KeepRecent { keep, include_newer } => {
+            let tagged = state_db.list_tagged()?;
+
             // Filter for all removal candidates
             let candidates = state_ids
                 .iter()
+                .filter(|(id, _)| !tagged.contains(id))
                 .filter(|(id, _)| {
                     if include_newer {
                         *id != current_state
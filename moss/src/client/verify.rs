@@ -15,7 +15,7 @@ use tui::{
 use vfs::tree::BlitFile;
 
 use crate::{
-    client::{self, cache},
+    client::{self, blit, cache},
     package, runtime, signal, state, Client, Signal,
 };
 
@@ -110,8 +110,10 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
 
     pb.set_length(states.len() as u64);
     pb.set_position(0);
+    let blit_mode = client.blit_mode();
+
     pb.suspend(|| {
-        println!("Verifying states");
+        println!("Verifying states (blit mode: {blit_mode})");
     });
 
     // Check the VFS of each state exists properly on the FS
@@ -120,13 +122,20 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
 
         let is_active = client.installation.active_state == Some(state.id);
 
-        let vfs = client.vfs(state.selections.iter().map(|s| &s.package))?;
-
-        let base = if is_active {
-            client.installation.root.join("usr")
+        let tree = if is_active {
+            client.installation.root.clone()
         } else {
-            client.installation.root_path(state.id.to_string()).join("usr")
+            client.installation.root_path(state.id.to_string())
         };
+        let base = tree.join("usr");
+
+        // Archives recycled by an incremental blit are reblitted on activation
+        if !is_active && !base.exists() {
+            pb.inc(1);
+            continue;
+        }
+
+        let vfs = client.vfs(state.selections.iter().map(|s| &s.package))?;
 
         let files = vfs.iter().collect::<Vec<_>>();
 
@@ -150,10 +159,15 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
             }
         }
 
+        // Trees keep the mode they were blitted with until they're reblitted
+        let mode = blit::recorded_mode(&tree);
+
         pb.inc(1);
         if verbose {
             let mark = if num_issues > 0 { "×".yellow() } else { "»".green() };
-            pb.suspend(|| println!(" {mark} state #{}", state.id));
+            pb.suspend(|| println!(" {mark} state #{} ({mode})", state.id));
+        } else if mode != blit_mode {
+            pb.suspend(|| println!(" {} state #{} was blitted in {mode} mode", "»".dim(), state.id));
         }
     }
 
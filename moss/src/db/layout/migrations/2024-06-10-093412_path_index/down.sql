@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS layout_package_id;
+DROP INDEX IF EXISTS layout_path;
+ALTER TABLE layout DROP COLUMN path;
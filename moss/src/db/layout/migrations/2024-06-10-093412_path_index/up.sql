@@ -0,0 +1,13 @@
+-- Your SQL goes here
+ALTER TABLE layout ADD COLUMN path TEXT NOT NULL DEFAULT '';
+
+-- Absolute target path, as joined onto `/usr` when blitting
+UPDATE layout
+SET path = CASE
+    WHEN entry_type IN ('regular', 'symlink') THEN entry_value2
+    ELSE entry_value1
+END;
+UPDATE layout SET path = '/usr/' || path WHERE path NOT LIKE '/%';
+
+CREATE INDEX IF NOT EXISTS layout_path ON layout (path);
+CREATE INDEX IF NOT EXISTS layout_package_id ON layout (package_id);
@@ -59,6 +59,18 @@ impl Database {
         })
     }
 
+    /// Retrieve all entries targeting the absolute `path`
+    pub fn owners(&self, path: &str) -> Result<Vec<(package::Id, payload::Layout)>, Error> {
+        self.conn.exec(|conn| {
+            model::layout::table
+                .select(model::Layout::as_select())
+                .filter(model::layout::path.eq(path))
+                .load_iter(conn)?
+                .map(map_layout)
+                .collect()
+        })
+    }
+
     pub fn all(&self) -> Result<Vec<(package::Id, payload::Layout)>, Error> {
         self.conn.exec(|conn| {
             model::layout::table
@@ -101,6 +113,7 @@ impl Database {
                     ids.push(package_id.as_ref());
 
                     let (entry_type, entry_value1, entry_value2) = encode_entry(layout.entry.clone());
+                    let path = target_path(&layout.entry);
 
                     model::NewLayout {
                         package_id: package_id.to_string(),
@@ -111,6 +124,7 @@ impl Database {
                         entry_type,
                         entry_value1,
                         entry_value2,
+                        path,
                     }
                 })
                 .collect::<Vec<_>>();
@@ -119,7 +133,7 @@ impl Database {
             ids.dedup();
             batch_remove_impl(&ids, tx)?;
 
-            for chunk in values.chunks(MAX_VARIABLE_NUMBER / 8) {
+            for chunk in values.chunks(MAX_VARIABLE_NUMBER / 9) {
                 diesel::insert_into(model::layout::table).values(chunk).execute(tx)?;
             }
 
@@ -189,6 +203,11 @@ fn decode_entry(
     }
 }
 
+/// Absolute path of the entry once blitted under `/usr`
+pub(crate) fn target_path(entry: &payload::layout::Entry) -> String {
+    vfs::path::join("/usr", entry.target())
+}
+
 fn encode_entry(entry: payload::layout::Entry) -> (&'static str, Option<String>, Option<String>) {
     use payload::layout::Entry;
 
@@ -236,6 +255,7 @@ mod model {
         pub entry_type: &'a str,
         pub entry_value1: Option<String>,
         pub entry_value2: Option<String>,
+        pub path: String,
     }
 }
 
@@ -269,4 +289,29 @@ mod test {
 
         assert_eq!(count, all.len());
     }
+
+    #[test]
+    fn query_owners() {
+        let database = Database::new(":memory:").unwrap();
+
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let layouts = payloads
+            .iter()
+            .filter_map(PayloadKind::layout)
+            .flat_map(|p| &p.body)
+            .collect::<Vec<_>>();
+        let id = package::Id::from("test".to_string());
+
+        database.batch_add(layouts.iter().map(|l| (&id, *l))).unwrap();
+
+        let owners = database.owners("/usr/share/bash-completion/completions/bind").unwrap();
+
+        assert!(!owners.is_empty());
+        assert!(owners.iter().all(|(owner, _)| *owner == id));
+        assert!(database.owners("/usr/bin/does-not-exist").unwrap().is_empty());
+    }
 }
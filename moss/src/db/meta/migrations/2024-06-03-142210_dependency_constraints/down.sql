@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE meta_dependencies_old (
+    package TEXT NOT NULL,
+    dependency TEXT NOT NULL,
+    PRIMARY KEY (package, dependency),
+    FOREIGN KEY (package) REFERENCES meta(package) ON DELETE CASCADE
+);
+
+INSERT OR IGNORE INTO meta_dependencies_old (package, dependency)
+SELECT package, dependency FROM meta_dependencies;
+
+DROP TABLE meta_dependencies;
+ALTER TABLE meta_dependencies_old RENAME TO meta_dependencies;
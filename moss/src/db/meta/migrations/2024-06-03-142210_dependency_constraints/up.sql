@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- Constrained dependencies on the same provider (i.e. `foo >= 1` and `foo < 2`)
+-- need their own rows, so the constraint is part of the key. Unconstrained
+-- dependencies have an empty constraint
+CREATE TABLE meta_dependencies_new (
+    package TEXT NOT NULL,
+    dependency TEXT NOT NULL,
+    version_constraint TEXT NOT NULL DEFAULT '',
+    PRIMARY KEY (package, dependency, version_constraint),
+    FOREIGN KEY (package) REFERENCES meta(package) ON DELETE CASCADE
+);
+
+INSERT INTO meta_dependencies_new (package, dependency)
+SELECT package, dependency FROM meta_dependencies;
+
+DROP TABLE meta_dependencies;
+ALTER TABLE meta_dependencies_new RENAME TO meta_dependencies;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE meta DROP COLUMN delta_size;
+ALTER TABLE meta DROP COLUMN delta_hash;
+ALTER TABLE meta DROP COLUMN delta_uri;
+ALTER TABLE meta DROP COLUMN delta_from;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE meta ADD COLUMN delta_from TEXT NULL;
+ALTER TABLE meta ADD COLUMN delta_uri TEXT NULL;
+ALTER TABLE meta ADD COLUMN delta_hash TEXT NULL;
+ALTER TABLE meta ADD COLUMN delta_size BIGINT NULL;
@@ -63,7 +63,7 @@ impl Database {
             let dependencies = model::Dependency::belonging_to(&meta)
                 .select(model::Dependency::as_select())
                 .load_iter(conn)?
-                .map(|d| Ok(d?.dependency))
+                .map(|d| d?.into_dependency())
                 .collect::<Result<_, Error>>()?;
             let providers = model::Provider::belonging_to(&meta)
                 .select(model::Provider::as_select())
@@ -75,6 +75,7 @@ impl Database {
                 .load_iter(conn)?
                 .map(|p| Ok(p?.conflict))
                 .collect::<Result<_, Error>>()?;
+            let delta = meta.delta();
 
             Ok(Meta {
                 name: meta.name,
@@ -93,6 +94,7 @@ impl Database {
                 uri: meta.uri,
                 hash: meta.hash,
                 download_size: meta.download_size.map(|size| size as u64),
+                delta,
             })
         })
     }
@@ -112,10 +114,31 @@ impl Database {
         })
     }
 
+    /// Return all distinct providers containing `keyword`
+    pub fn providers_matching(&self, keyword: &str) -> Result<Vec<Provider>, Error> {
+        // Match `keyword` literally, as provider names often contain `_`
+        let keyword = keyword.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+
+        self.conn.exec(|conn| {
+            model::meta_providers::table
+                .select(model::meta_providers::provider)
+                .distinct()
+                .filter(
+                    model::meta_providers::provider
+                        .like(format!("%{keyword}%"))
+                        .escape('\\'),
+                )
+                .load_iter::<String, _>(conn)?
+                .map(|result| Ok(result?.parse()?))
+                .collect()
+        })
+    }
+
     pub fn query(&self, filter: Option<Filter>) -> Result<Vec<(package::Id, Meta)>, Error> {
         self.conn.exec(|conn| {
             let map_row = |result| {
                 let meta: model::Meta = result?;
+                let delta = meta.delta();
 
                 Ok((
                     meta.package.into(),
@@ -136,6 +159,7 @@ impl Database {
                         uri: meta.uri,
                         hash: meta.hash,
                         download_size: meta.download_size.map(|size| size as u64),
+                        delta,
                     },
                 ))
             };
@@ -149,7 +173,7 @@ impl Database {
                 Some(Filter::Dependency(dependency)) => model::meta::table
                     .select(model::Meta::as_select())
                     .inner_join(model::meta_dependencies::table)
-                    .filter(model::meta_dependencies::dependency.eq(dependency.to_string()))
+                    .filter(model::meta_dependencies::dependency.eq(dependency.provider().to_string()))
                     .load_iter::<model::Meta, _>(conn)?,
                 Some(Filter::Name(name)) => model::meta::table
                     .select(model::Meta::as_select())
@@ -197,8 +221,8 @@ impl Database {
                     .load_iter::<model::Dependency, _>(conn)?
                     .try_for_each::<_, Result<_, Error>>(|result| {
                         let row = result?;
-                        if let Some(meta) = entries.get_mut(&row.package.into()) {
-                            meta.dependencies.insert(row.dependency);
+                        if let Some(meta) = entries.get_mut(&row.package.clone().into()) {
+                            meta.dependencies.insert(row.into_dependency()?);
                         }
                         Ok(())
                     })?;
@@ -264,6 +288,10 @@ impl Database {
                     uri: meta.uri.as_deref(),
                     hash: meta.hash.as_deref(),
                     download_size: meta.download_size.map(|size| size as i64),
+                    delta_from: meta.delta.as_ref().map(|delta| delta.from.as_ref()),
+                    delta_uri: meta.delta.as_ref().map(|delta| delta.uri.as_str()),
+                    delta_hash: meta.delta.as_ref().map(|delta| delta.hash.as_str()),
+                    delta_size: meta.delta.as_ref().map(|delta| delta.size as i64),
                 })
                 .collect::<Vec<_>>();
             let licenses = packages
@@ -283,7 +311,12 @@ impl Database {
                     meta.dependencies.iter().map(|dependency| {
                         (
                             model::meta_dependencies::package.eq(<package::Id as AsRef<str>>::as_ref(package)),
-                            model::meta_dependencies::dependency.eq(dependency.to_string()),
+                            model::meta_dependencies::dependency.eq(dependency.provider().to_string()),
+                            model::meta_dependencies::version_constraint.eq(dependency
+                                .constraint
+                                .as_ref()
+                                .map(ToString::to_string)
+                                .unwrap_or_default()),
                         )
                     })
                 })
@@ -313,7 +346,7 @@ impl Database {
 
             batch_remove_impl(&ids, tx)?;
 
-            for chunk in entries.chunks(MAX_VARIABLE_NUMBER / 13) {
+            for chunk in entries.chunks(MAX_VARIABLE_NUMBER / 17) {
                 diesel::insert_into(model::meta::table).values(chunk).execute(tx)?;
             }
             for chunk in licenses.chunks(MAX_VARIABLE_NUMBER / 2) {
@@ -321,7 +354,7 @@ impl Database {
                     .values(chunk)
                     .execute(tx)?;
             }
-            for chunk in dependencies.chunks(MAX_VARIABLE_NUMBER / 2) {
+            for chunk in dependencies.chunks(MAX_VARIABLE_NUMBER / 3) {
                 diesel::insert_or_ignore_into(model::meta_dependencies::table)
                     .values(chunk)
                     .execute(tx)?;
@@ -393,6 +426,22 @@ mod model {
         pub uri: Option<String>,
         pub hash: Option<String>,
         pub download_size: Option<i64>,
+        pub delta_from: Option<String>,
+        pub delta_uri: Option<String>,
+        pub delta_hash: Option<String>,
+        pub delta_size: Option<i64>,
+    }
+
+    impl Meta {
+        /// The advertised delta, if all of its fields are set
+        pub fn delta(&self) -> Option<package::meta::Delta> {
+            Some(package::meta::Delta {
+                from: self.delta_from.clone()?.into(),
+                uri: self.delta_uri.clone()?,
+                hash: self.delta_hash.clone()?,
+                size: self.delta_size? as u64,
+            })
+        }
     }
 
     #[derive(Queryable, Selectable, Identifiable)]
@@ -415,13 +464,27 @@ mod model {
 
     #[derive(Queryable, Selectable, Identifiable, Associations)]
     #[diesel(table_name = meta_dependencies)]
-    #[diesel(primary_key(package, dependency))]
+    #[diesel(primary_key(package, dependency, version_constraint))]
     #[diesel(belongs_to(Meta, foreign_key = package))]
     #[diesel(belongs_to(PackageId, foreign_key = package))]
     pub struct Dependency {
         pub package: String,
         #[diesel(deserialize_as = String)]
         pub dependency: crate::Dependency,
+        /// Version constraint, empty if unconstrained
+        pub version_constraint: String,
+    }
+
+    impl Dependency {
+        /// Reunite the dependency with its stored version constraint
+        pub fn into_dependency(self) -> Result<crate::Dependency, super::Error> {
+            let constraint = Some(self.version_constraint).filter(|constraint| !constraint.is_empty());
+
+            Ok(crate::Dependency {
+                constraint: constraint.as_deref().map(str::parse).transpose()?,
+                ..self.dependency
+            })
+        }
     }
 
     #[derive(Queryable, Selectable, Identifiable, Associations)]
@@ -462,6 +525,10 @@ mod model {
         pub uri: Option<&'a str>,
         pub hash: Option<&'a str>,
         pub download_size: Option<i64>,
+        pub delta_from: Option<&'a str>,
+        pub delta_uri: Option<&'a str>,
+        pub delta_hash: Option<&'a str>,
+        pub delta_size: Option<i64>,
     }
 }
 
@@ -553,4 +620,66 @@ mod test {
         // correctly.
         assert_eq!(retrieved_conflicts, vec![&pineapple_provider]);
     }
+
+    #[test]
+    fn dependency_range_is_retained() {
+        let db = Database::new(":memory:").unwrap();
+
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta_payload = payloads.iter().find_map(PayloadKind::meta).unwrap();
+        let mut meta = Meta::from_stone_payload(&meta_payload.body).unwrap();
+
+        let range = ["name(zlib) >= 1", "name(zlib) < 2", "name(zlib)"]
+            .into_iter()
+            .map(|dependency| dependency.parse::<crate::Dependency>().unwrap())
+            .collect::<Vec<_>>();
+        meta.dependencies.extend(range.iter().cloned());
+
+        let id = package::Id::from("test".to_string());
+        db.add(id.clone(), meta).unwrap();
+
+        let fetched = db.get(&id).unwrap();
+        for dependency in &range {
+            assert!(fetched.dependencies.contains(dependency), "{dependency} missing");
+        }
+    }
+
+    #[test]
+    fn providers_match_literally() {
+        let db = Database::new(":memory:").unwrap();
+
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta_payload = payloads.iter().find_map(PayloadKind::meta).unwrap();
+        let mut meta = Meta::from_stone_payload(&meta_payload.body).unwrap();
+
+        meta.providers = ["libfoo_bar.so", "libfooXbar.so", "libfoo%bar.so"]
+            .into_iter()
+            .map(|name| Provider {
+                kind: Kind::SharedLibrary,
+                name: name.to_string(),
+            })
+            .collect();
+        db.add(package::Id::from("test".to_string()), meta).unwrap();
+
+        let matching = |keyword| {
+            db.providers_matching(keyword)
+                .unwrap()
+                .into_iter()
+                .map(|provider| provider.name)
+                .collect::<Vec<_>>()
+        };
+
+        // Wildcards in the keyword aren't treated as such
+        assert_eq!(matching("foo_bar"), ["libfoo_bar.so"]);
+        assert_eq!(matching("foo%bar"), ["libfoo%bar.so"]);
+        assert_eq!(matching("foo\\"), Vec::<String>::new());
+    }
 }
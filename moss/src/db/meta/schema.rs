@@ -15,6 +15,10 @@ diesel::table! {
         uri -> Nullable<Text>,
         hash -> Nullable<Text>,
         download_size -> Nullable<BigInt>,
+        delta_from -> Nullable<Text>,
+        delta_uri -> Nullable<Text>,
+        delta_hash -> Nullable<Text>,
+        delta_size -> Nullable<BigInt>,
     }
 }
 
@@ -26,9 +30,10 @@ diesel::table! {
 }
 
 diesel::table! {
-    meta_dependencies (package, dependency) {
+    meta_dependencies (package, dependency, version_constraint) {
         package -> Text,
         dependency -> Text,
+        version_constraint -> Text,
     }
 }
 
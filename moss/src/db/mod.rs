@@ -66,6 +66,8 @@ pub enum Error {
     RowNotFound,
     #[error("failed to decode layout entry")]
     LayoutEntryDecode,
+    #[error("invalid dependency")]
+    Dependency(#[from] crate::dependency::ParseError),
     #[error("invalid timestamp: {0}")]
     InvalidTimestamp(i64),
     #[error("diesel")]
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS state_tag;
+ALTER TABLE state DROP COLUMN tag;
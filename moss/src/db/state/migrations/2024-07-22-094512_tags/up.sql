@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE state ADD COLUMN tag TEXT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS state_tag ON state (tag);
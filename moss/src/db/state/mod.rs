@@ -75,6 +75,7 @@ impl Database {
                         id,
                         summary: state.summary,
                         description: state.description,
+                        tag: state.tag,
                         selections,
                         created: state.created.0,
                         kind: state.kind,
@@ -107,6 +108,7 @@ impl Database {
                 id: state.id.into(),
                 summary: state.summary,
                 description: state.description,
+                tag: state.tag,
                 selections,
                 created: state.created.0,
                 kind: state.kind,
@@ -154,6 +156,50 @@ impl Database {
             .and_then(|id| self.get(id))
     }
 
+    /// Returns the state named `tag`, if any
+    pub fn find_tag(&self, tag: &str) -> Result<Option<Id>, Error> {
+        self.conn.exec(|conn| {
+            Ok(model::state::table
+                .select(model::state::id)
+                .filter(model::state::tag.eq(tag))
+                .first::<i32>(conn)
+                .optional()?
+                .map(Id::from))
+        })
+    }
+
+    /// Returns the IDs of all tagged states
+    pub fn list_tagged(&self) -> Result<Vec<Id>, Error> {
+        self.conn.exec(|conn| {
+            Ok(model::state::table
+                .select(model::state::id)
+                .filter(model::state::tag.is_not_null())
+                .load::<i32>(conn)?
+                .into_iter()
+                .map(Id::from)
+                .collect())
+        })
+    }
+
+    /// Name state `id` as `tag`, or clear its tag if `None`, optionally replacing its description
+    pub fn set_tag(&self, id: Id, tag: Option<&str>, description: Option<&str>) -> Result<(), Error> {
+        self.conn.exclusive_tx(|tx| {
+            let state = model::state::table.find(i32::from(id));
+
+            if diesel::update(state).set(model::state::tag.eq(tag)).execute(tx)? == 0 {
+                return Err(Error::RowNotFound);
+            }
+
+            if let Some(description) = description {
+                diesel::update(state)
+                    .set(model::state::description.eq(description))
+                    .execute(tx)?;
+            }
+
+            Ok(())
+        })
+    }
+
     pub fn remove(&self, state: &state::Id) -> Result<(), Error> {
         self.batch_remove(Some(state))
     }
@@ -194,6 +240,7 @@ mod model {
         pub created: Timestamp,
         pub summary: Option<String>,
         pub description: Option<String>,
+        pub tag: Option<String>,
         #[diesel(column_name = "type_", deserialize_as = String)]
         pub kind: Kind,
     }
@@ -270,4 +317,33 @@ mod test {
 
         assert_eq!(state.selections, selections);
     }
+
+    #[test]
+    fn tag_find_clear() {
+        let database = Database::new(":memory:").unwrap();
+
+        let selections = vec![Selection::explicit(package::Id::from("pkg a".to_string()))];
+        let first = database.add(&selections, Some("test"), None).unwrap();
+        let second = database.add(&selections, Some("test"), None).unwrap();
+
+        database
+            .set_tag(first.id, Some("known-good"), Some("before upgrade"))
+            .unwrap();
+
+        let state = database.get(first.id).unwrap();
+        assert_eq!(state.tag.as_deref(), Some("known-good"));
+        assert_eq!(state.description.as_deref(), Some("before upgrade"));
+        assert_eq!(database.find_tag("known-good").unwrap(), Some(first.id));
+        assert_eq!(database.list_tagged().unwrap(), vec![first.id]);
+
+        // Tags are unique
+        assert!(database.set_tag(second.id, Some("known-good"), None).is_err());
+
+        database.set_tag(first.id, None, None).unwrap();
+        assert_eq!(database.find_tag("known-good").unwrap(), None);
+        assert_eq!(
+            database.get(first.id).unwrap().description.as_deref(),
+            Some("before upgrade")
+        );
+    }
 }
@@ -8,6 +8,7 @@ diesel::table! {
         created -> BigInt,
         summary -> Nullable<Text>,
         description -> Nullable<Text>,
+        tag -> Nullable<Text>,
     }
 }
 
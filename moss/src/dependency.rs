@@ -16,16 +16,18 @@
 //! As one might expect, a [`Provider`] is the inverse of a dependency. It is used to record
 //! the capabilities of a package such that others may depend on it through resolution.
 //!
-//! We acknowledge the current dependency format, while powerful, does not allow expressing
-//! relationship constraints. This was a deliberate decision due to the rolling nature of
-//! Serpent OS, however more expressive dependency types will be introduced in the next major
-//! stone format.
-use std::str::FromStr;
+//! A dependency may optionally carry a [`Constraint`] on the package that provides it, such
+//! as `name(zlib) >= 1.3` or `soname(libz.so.1(x86_64)) = release 12`. Unconstrained dependencies
+//! are encoded exactly as they always have been, so existing v1 stones remain valid.
+use std::{cmp::Ordering, fmt, str::FromStr};
 
 use derive_more::Display;
+use itertools::{EitherOrBoth, Itertools};
 use stone::payload;
 use thiserror::Error;
 
+use crate::package;
+
 /// Every dependency
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString)]
 #[strum(serialize_all = "lowercase")]
@@ -96,15 +98,18 @@ impl From<Kind> for payload::meta::Dependency {
 }
 
 /// A Dependency in moss is simplistic in that it only contains
-/// a target and a Kind, ie. `pkgconfig(zlib)`
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Display)]
-#[display(fmt = "{kind}({name})")]
+/// a target and a Kind, ie. `pkgconfig(zlib)`, with an optional
+/// version [`Constraint`], ie. `pkgconfig(zlib) >= 1.3`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Dependency {
     /// Specific type of dependency
     pub kind: Kind,
 
     /// Bare target (i.e. `libz.so.1(x86_64)`)
     pub name: String,
+
+    /// Optional constraint on the providing package
+    pub constraint: Option<Constraint>,
 }
 
 impl Dependency {
@@ -119,17 +124,50 @@ impl Dependency {
     /// ```
     ///     use moss::Dependency;
     ///     let dep = Dependency::from_name("pkgconfig(zlib)").unwrap();
+    ///     let versioned = Dependency::from_name("zlib >= 1.3").unwrap();
     /// ```
     pub fn from_name(name: &str) -> Result<Self, ParseError> {
-        if name.contains('(') {
-            Dependency::from_str(name)
+        let (target, constraint) = split_constraint(name)?;
+
+        if target.contains('(') {
+            let (kind, name) = parse(target)?;
+            Ok(Dependency { kind, name, constraint })
         } else {
             Ok(Dependency {
                 kind: Kind::PackageName,
-                name: name.to_owned(),
+                name: target.to_owned(),
+                constraint,
             })
         }
     }
+
+    /// The [`Provider`] which satisfies this dependency, ignoring any constraint
+    pub fn provider(&self) -> Provider {
+        Provider {
+            kind: self.kind,
+            name: self.name.clone(),
+        }
+    }
+
+    /// Returns true if the package described by `meta` meets the
+    /// constraint of this dependency, if any
+    pub fn satisfied_by(&self, meta: &package::Meta) -> bool {
+        self.constraint
+            .as_ref()
+            .map_or(true, |constraint| constraint.satisfied_by(meta))
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.kind, self.name)?;
+
+        if let Some(constraint) = &self.constraint {
+            write!(f, " {constraint}")?;
+        }
+
+        Ok(())
+    }
 }
 
 /// Partial ordering comparator for dependencies
@@ -151,9 +189,10 @@ impl FromStr for Dependency {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (kind, name) = parse(s)?;
+        let (target, constraint) = split_constraint(s)?;
+        let (kind, name) = parse(target)?;
 
-        Ok(Self { kind, name })
+        Ok(Self { kind, name, constraint })
     }
 }
 
@@ -227,6 +266,150 @@ impl TryFrom<String> for Provider {
     }
 }
 
+/// Comparison operator of a [`Constraint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString)]
+pub enum Operator {
+    #[strum(serialize = "<")]
+    Less,
+    #[strum(serialize = "<=")]
+    LessOrEqual,
+    #[strum(serialize = "=")]
+    Equal,
+    #[strum(serialize = ">=")]
+    GreaterOrEqual,
+    #[strum(serialize = ">")]
+    Greater,
+}
+
+/// What a [`Constraint`] is compared against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// Human readable version identifier, i.e. `1.3`
+    Version(String),
+    /// Source release number, i.e. `release 12`
+    Release(u64),
+}
+
+/// A version constraint on the package providing a [`Dependency`],
+/// written as `>= 1.3` or `= release 12`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Constraint {
+    pub operator: Operator,
+    pub target: Target,
+}
+
+impl Constraint {
+    /// Returns true if the package described by `meta` meets this constraint
+    pub fn satisfied_by(&self, meta: &package::Meta) -> bool {
+        let ordering = match &self.target {
+            Target::Version(version) => compare_versions(&meta.version_identifier, version),
+            Target::Release(release) => meta.source_release.cmp(release),
+        };
+
+        match self.operator {
+            Operator::Less => ordering.is_lt(),
+            Operator::LessOrEqual => ordering.is_le(),
+            Operator::Equal => ordering.is_eq(),
+            Operator::GreaterOrEqual => ordering.is_ge(),
+            Operator::Greater => ordering.is_gt(),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            Target::Version(version) => write!(f, "{} {version}", self.operator),
+            Target::Release(release) => write!(f, "{} release {release}", self.operator),
+        }
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+
+        let operator = parts
+            .next()
+            .and_then(|op| op.parse::<Operator>().ok())
+            .ok_or(ParseError(s.to_string()))?;
+        let target = match (parts.next(), parts.next()) {
+            (Some("release"), Some(release)) => {
+                Target::Release(release.parse().map_err(|_| ParseError(s.to_string()))?)
+            }
+            (Some(version), None) => Target::Version(version.to_string()),
+            _ => return Err(ParseError(s.to_string())),
+        };
+
+        if parts.next().is_some() {
+            return Err(ParseError(s.to_string()));
+        }
+
+        Ok(Self { operator, target })
+    }
+}
+
+/// Split a trailing [`Constraint`] from a dependency string, i.e. `name(zlib) >= 1.3`
+pub(crate) fn split_constraint(s: &str) -> Result<(&str, Option<Constraint>), ParseError> {
+    match s.trim().split_once(char::is_whitespace) {
+        Some((target, constraint)) => Ok((target, Some(constraint.parse()?))),
+        None => Ok((s.trim(), None)),
+    }
+}
+
+/// Compare two version identifiers, segment by segment. Numeric segments
+/// compare numerically and take precedence over alphabetic segments, i.e.
+/// `1.10 > 1.9` and `1.3.1 > 1.3rc1`
+///
+/// Missing numeric segments count as `0`, so `1.3.0 = 1.3`, while a trailing
+/// alphabetic segment marks a pre-release, so `1.3rc1 < 1.3`
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    for pair in version_segments(a).zip_longest(version_segments(b)) {
+        let ordering = match pair {
+            EitherOrBoth::Both(a, b) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Greater,
+                (Err(_), Ok(_)) => Ordering::Less,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+            EitherOrBoth::Left(a) => compare_to_missing(a),
+            EitherOrBoth::Right(b) => compare_to_missing(b).reverse(),
+        };
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Compare a trailing `segment` against the absent segment of a shorter version
+fn compare_to_missing(segment: &str) -> Ordering {
+    match segment.parse::<u64>() {
+        Ok(number) => number.cmp(&0),
+        Err(_) => Ordering::Less,
+    }
+}
+
+/// Split a version into alternating numeric and alphabetic segments,
+/// dropping any separators, i.e. `1.3rc1` => `1`, `3`, `rc`, `1`
+fn version_segments(version: &str) -> impl Iterator<Item = &str> {
+    version
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .flat_map(|mut rest| {
+            std::iter::from_fn(move || {
+                let numeric = rest.chars().next()?.is_ascii_digit();
+                let end = rest.find(|c: char| c.is_ascii_digit() != numeric).unwrap_or(rest.len());
+                let (segment, tail) = rest.split_at(end);
+                rest = tail;
+                Some(segment)
+            })
+        })
+}
+
 /// Parse the [`Kind`] of dependency or provider from the string
 fn parse(s: &str) -> Result<(Kind, String), ParseError> {
     let (kind, rest) = s.split_once('(').ok_or(ParseError(s.to_string()))?;
@@ -246,3 +429,49 @@ fn parse(s: &str) -> Result<(Kind, String), ParseError> {
 #[derive(Debug, Error)]
 #[error("Invalid dependency type: {0}")]
 pub struct ParseError(String);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_constraint() {
+        let dep = Dependency::from_name("zlib >= 1.3").unwrap();
+        assert_eq!(dep.kind, Kind::PackageName);
+        assert_eq!(dep.name, "zlib");
+        assert_eq!(
+            dep.constraint,
+            Some(Constraint {
+                operator: Operator::GreaterOrEqual,
+                target: Target::Version("1.3".into()),
+            })
+        );
+
+        let dep = Dependency::from_str("soname(libz.so.1(x86_64)) = release 12").unwrap();
+        assert_eq!(dep.name, "libz.so.1(x86_64)");
+        assert_eq!(dep.to_string(), "soname(libz.so.1(x86_64)) = release 12");
+
+        assert!(Dependency::from_name("zlib ~ 1.3").is_err());
+        assert!(Dependency::from_name("zlib = release twelve").is_err());
+    }
+
+    #[test]
+    fn version_ordering() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.3", "1.3"), Ordering::Equal);
+        assert_eq!(compare_versions("1.3", "1.3.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.3.1", "1.3rc1"), Ordering::Greater);
+        assert_eq!(compare_versions("2024.01", "2023.12"), Ordering::Greater);
+    }
+
+    #[test]
+    fn version_trailing_segments() {
+        assert_eq!(compare_versions("1.3.0", "1.3"), Ordering::Equal);
+        assert_eq!(compare_versions("1.3", "1.3.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.3.0.1", "1.3"), Ordering::Greater);
+        assert_eq!(compare_versions("1.3rc1", "1.3"), Ordering::Less);
+        assert_eq!(compare_versions("1.3", "1.3beta"), Ordering::Greater);
+        assert_eq!(compare_versions("1.3rc1", "1.3rc2"), Ordering::Less);
+        assert_eq!(compare_versions("1.3.0", "1.3rc1"), Ordering::Greater);
+    }
+}
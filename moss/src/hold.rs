@@ -0,0 +1,74 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Holding packages back from sync
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{package, repository};
+
+/// How sync treats a held package
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hold {
+    /// Sync the package from this repository only, instead
+    /// of holding it at the installed version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<repository::Id>,
+}
+
+impl fmt::Display for Hold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repository {
+            Some(repository) => write!(f, "pinned to {repository}"),
+            None => "held".fmt(f),
+        }
+    }
+}
+
+/// Holds by package name, loaded from `hold.d` configs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Map(BTreeMap<String, Hold>);
+
+impl Map {
+    pub fn with(items: impl IntoIterator<Item = (String, Hold)>) -> Self {
+        Self(items.into_iter().collect())
+    }
+
+    /// Load and merge all hold configs of `manager`, later configs taking precedence
+    pub fn load(manager: &config::Manager) -> Self {
+        manager
+            .load::<Self>()
+            .into_iter()
+            .reduce(Self::merge)
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, name: &package::Name) -> Option<&Hold> {
+        self.0.get(AsRef::<String>::as_ref(name))
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Hold> {
+        self.0.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Hold)> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn merge(self, other: Self) -> Self {
+        Self(self.0.into_iter().chain(other.0).collect())
+    }
+}
+
+impl config::Config for Map {
+    fn domain() -> String {
+        "hold".into()
+    }
+}
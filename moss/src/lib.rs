@@ -15,6 +15,7 @@ pub mod client;
 pub mod db;
 pub mod dependency;
 pub mod environment;
+pub mod hold;
 pub mod installation;
 pub mod package;
 pub mod registry;
@@ -22,4 +23,6 @@ pub mod repository;
 pub mod request;
 pub mod runtime;
 pub mod signal;
+pub mod signing;
 pub mod state;
+pub mod system;
@@ -15,7 +15,7 @@ use crate::{dependency, Dependency, Provider};
 pub struct Id(pub(super) String);
 
 /// The name of a [`super::Package`]
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, AsRef, From, Into, Display)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, AsRef, From, Into, Display)]
 pub struct Name(String);
 
 impl Name {
@@ -25,7 +25,7 @@ impl Name {
 }
 
 /// The metadata of a [`super::Package`]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Meta {
     /// Package name
     pub name: Name,
@@ -59,10 +59,61 @@ pub struct Meta {
     pub hash: Option<String>,
     /// How big is this package in the repo..?
     pub download_size: Option<u64>,
+    /// If relevant: delta from an older release of this package
+    pub delta: Option<Delta>,
+}
+
+/// A delta stone, holding only the assets which changed since an
+/// older release of the package. It can be fetched in place of the
+/// full stone when that release's assets are already available
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    /// Hash of the package the delta applies to
+    pub from: super::Id,
+    /// Uri to fetch the delta from
+    pub uri: String,
+    /// Hash of the delta stone
+    pub hash: String,
+    /// Size of the delta stone
+    pub size: u64,
+}
+
+impl Delta {
+    /// Decode an entry of the deltas index, returning the hash
+    /// of the package it reconstructs along with the delta
+    pub fn from_stone_payload(payload: &[payload::Meta]) -> Result<(String, Self), MissingMetaFieldError> {
+        let package = find_meta_string(payload, payload::meta::Tag::PackageHash)?;
+
+        Ok((
+            package,
+            Delta {
+                from: find_meta_string(payload, payload::meta::Tag::DeltaFrom)?.into(),
+                uri: find_meta_string(payload, payload::meta::Tag::DeltaURI)?,
+                hash: find_meta_string(payload, payload::meta::Tag::DeltaHash)?,
+                size: find_meta_u64(payload, payload::meta::Tag::DeltaSize)?,
+            },
+        ))
+    }
+
+    /// Encode an entry of the deltas index for the package with hash `package`
+    pub fn to_stone_payload(self, package: String) -> Vec<payload::Meta> {
+        use payload::meta::{Kind, Tag};
+
+        vec![
+            (Tag::PackageHash, Kind::String(package)),
+            (Tag::DeltaFrom, Kind::String(self.from.into())),
+            (Tag::DeltaURI, Kind::String(self.uri)),
+            (Tag::DeltaHash, Kind::String(self.hash)),
+            (Tag::DeltaSize, Kind::Uint64(self.size)),
+        ]
+        .into_iter()
+        .map(|(tag, kind)| payload::Meta { tag, kind })
+        .collect()
+    }
 }
 
 impl Meta {
-    pub fn from_stone_payload(payload: &[stone::payload::Meta]) -> Result<Self, MissingMetaFieldError> {
+    pub fn from_stone_payload(payload: &[stone::payload::Meta]) -> Result<Self, Error> {
         let name = find_meta_string(payload, payload::meta::Tag::Name)?;
         let version_identifier = find_meta_string(payload, payload::meta::Tag::Version)?;
         let source_release = find_meta_u64(payload, payload::meta::Tag::Release)?;
@@ -80,7 +131,7 @@ impl Meta {
             .iter()
             .filter_map(|meta| meta_string(meta, payload::meta::Tag::License))
             .collect();
-        let dependencies = payload.iter().filter_map(meta_dependency).collect();
+        let dependencies = payload.iter().filter_map(meta_dependency).collect::<Result<_, _>>()?;
         let providers = payload
             .iter()
             .filter_map(meta_provider)
@@ -109,6 +160,8 @@ impl Meta {
             uri,
             hash,
             download_size,
+            // Deltas are published in their own index
+            delta: None,
         })
     }
 
@@ -135,11 +188,7 @@ impl Meta {
                 .into_iter()
                 .map(|license| (Tag::License, Kind::String(license))),
         )
-        .chain(
-            self.dependencies
-                .into_iter()
-                .map(|dep| (Tag::Depends, Kind::Dependency(dep.kind.into(), dep.name))),
-        )
+        .chain(self.dependencies.into_iter().map(dependency_payload))
         .chain(
             self.providers
                 .into_iter()
@@ -157,6 +206,72 @@ impl Meta {
         .collect()
     }
 
+    /// Strip the version constraints from all dependencies. Clients which predate
+    /// them can't read the versioned dependency tag, so the stone index lists
+    /// them by name only and publishes the constraints in their own index
+    pub fn without_constraints(self) -> Self {
+        Self {
+            dependencies: self
+                .dependencies
+                .into_iter()
+                .map(|dependency| Dependency {
+                    constraint: None,
+                    ..dependency
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Replace the dependencies matching `constrained` by kind & name with
+    /// their constrained form, as read from the constraints index
+    pub fn with_constraints(self, constrained: Vec<Dependency>) -> Self {
+        let dependencies = self
+            .dependencies
+            .into_iter()
+            .filter(|dependency| {
+                !constrained
+                    .iter()
+                    .any(|other| other.kind == dependency.kind && other.name == dependency.name)
+            })
+            .chain(constrained)
+            .collect();
+
+        Self { dependencies, ..self }
+    }
+
+    /// Encode the entry of the constraints index for this package, holding
+    /// its versioned dependencies. Returns `None` if it has none
+    pub fn constraints_payload(&self) -> Option<Vec<payload::Meta>> {
+        use payload::meta::{Kind, Tag};
+
+        let hash = self.hash.clone()?;
+        let constrained = self
+            .dependencies
+            .iter()
+            .filter(|dependency| dependency.constraint.is_some())
+            .cloned()
+            .map(dependency_payload)
+            .collect::<Vec<_>>();
+
+        (!constrained.is_empty()).then(|| {
+            Some((Tag::PackageHash, Kind::String(hash)))
+                .into_iter()
+                .chain(constrained)
+                .map(|(tag, kind)| payload::Meta { tag, kind })
+                .collect()
+        })
+    }
+
+    /// Decode an entry of the constraints index, returning the hash
+    /// of the package along with its versioned dependencies
+    pub fn constraints_from_stone_payload(payload: &[payload::Meta]) -> Result<(String, Vec<Dependency>), Error> {
+        let package = find_meta_string(payload, payload::meta::Tag::PackageHash)?;
+        let dependencies = payload.iter().filter_map(meta_dependency).collect::<Result<_, _>>()?;
+
+        Ok((package, dependencies))
+    }
+
     /// Return a reusable ID
     pub fn id(&self) -> Id {
         Id(format!(
@@ -203,14 +318,37 @@ fn meta_string(meta: &payload::Meta, tag: payload::meta::Tag) -> Option<String>
     }
 }
 
-fn meta_dependency(meta: &payload::Meta) -> Option<Dependency> {
-    if let payload::meta::Kind::Dependency(kind, name) = meta.kind.clone() {
-        Some(Dependency {
+fn dependency_payload(dependency: Dependency) -> (payload::meta::Tag, payload::meta::Kind) {
+    use payload::meta::{Kind, Tag};
+
+    match dependency.constraint {
+        // Constrained dependencies get their own tag so unconstrained
+        // dependencies are encoded exactly as before
+        Some(constraint) => (
+            Tag::VersionedDepends,
+            Kind::Dependency(dependency.kind.into(), format!("{} {constraint}", dependency.name)),
+        ),
+        None => (Tag::Depends, Kind::Dependency(dependency.kind.into(), dependency.name)),
+    }
+}
+
+fn meta_dependency(meta: &payload::Meta) -> Option<Result<Dependency, Error>> {
+    match (meta.tag, meta.kind.clone()) {
+        (payload::meta::Tag::VersionedDepends, payload::meta::Kind::Dependency(kind, name)) => Some(
+            dependency::split_constraint(&name)
+                .map(|(name, constraint)| Dependency {
+                    kind: dependency::Kind::from(kind),
+                    name: name.to_string(),
+                    constraint,
+                })
+                .map_err(|error| Error::MalformedDependency(name.clone(), error)),
+        ),
+        (_, payload::meta::Kind::Dependency(kind, name)) => Some(Ok(Dependency {
             kind: dependency::Kind::from(kind),
             name,
-        })
-    } else {
-        None
+            constraint: None,
+        })),
+        _ => None,
     }
 }
 
@@ -237,3 +375,12 @@ fn meta_conflict(meta: &payload::Meta) -> Option<Provider> {
 #[derive(Debug, Error)]
 #[error("Missing metadata field: {0:?}")]
 pub struct MissingMetaFieldError(pub payload::meta::Tag);
+
+/// Failure to build a [`Meta`] from a stone meta payload
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    MissingField(#[from] MissingMetaFieldError),
+    #[error("Malformed versioned dependency: {0}")]
+    MalformedDependency(String, #[source] dependency::ParseError),
+}
@@ -5,6 +5,8 @@
 use derive_more::{AsRef, Display, From, Into};
 use itertools::Itertools;
 
+use crate::Dependency;
+
 pub use self::meta::{Meta, MissingMetaFieldError, Name};
 
 pub mod meta;
@@ -34,6 +36,15 @@ pub struct Package {
     pub flags: Flags,
 }
 
+impl Package {
+    /// Returns the first dependency of this package which `other` satisfies
+    pub fn dependency_on(&self, other: &Package) -> Option<&Dependency> {
+        self.meta.dependencies.iter().find(|dependency| {
+            other.meta.providers.contains(&dependency.provider()) && dependency.satisfied_by(&other.meta)
+        })
+    }
+}
+
 impl PartialOrd for Package {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
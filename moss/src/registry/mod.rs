@@ -8,14 +8,18 @@
 use itertools::Itertools;
 
 use crate::package::{self, Package};
-use crate::Provider;
+use crate::{repository, Provider};
 
 pub use self::plugin::Plugin;
 pub use self::transaction::Transaction;
 
 pub mod plugin;
+pub mod solver;
 pub mod transaction;
 
+/// Upper bound on suggestions returned by [`Registry::similar_providers`]
+const MAX_SIMILAR_PROVIDERS: usize = 10;
+
 /// A registry is composed of multiple "query plugins" that
 /// provide [`Package`] information
 #[derive(Debug, Default)]
@@ -67,6 +71,21 @@ impl Registry {
         self.query(move |plugin| plugin.query_name(package_name, flags))
     }
 
+    /// Return a sorted stream of [`Package`] by name, from the repository `repository` only
+    pub fn by_name_from<'a>(
+        &'a self,
+        package_name: &'a package::Name,
+        repository: &'a repository::Id,
+        flags: package::Flags,
+    ) -> impl Iterator<Item = Package> + 'a {
+        self.query(move |plugin| {
+            matches!(plugin, Plugin::Repository(plugin) if plugin.id() == repository)
+                .then(|| plugin.query_name(package_name, flags))
+                .into_iter()
+                .flatten()
+        })
+    }
+
     /// Return a sorted stream of [`Package`] by id
     pub fn by_id<'a>(&'a self, id: &'a package::Id) -> impl Iterator<Item = Package> + 'a {
         self.query(move |plugin| plugin.package(id))
@@ -93,6 +112,52 @@ impl Registry {
         self.list(flags.with_available())
     }
 
+    /// Return providers with a name similar to `provider`, used to
+    /// suggest alternatives when nothing provides it
+    pub fn similar_providers(&self, provider: &Provider) -> Vec<Provider> {
+        // Match on the leading portion of the name, i.e. `libnss3` for
+        // `libnss3.so(x86_64)`, ignoring anything too short to be useful
+        let stem = provider.name.split(['.', '(', '-']).next().unwrap_or_default();
+        if stem.len() < 3 {
+            return vec![];
+        }
+
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.query_providers_matching(stem))
+            .filter(|similar| similar != provider)
+            .sorted()
+            .dedup()
+            .take(MAX_SIMILAR_PROVIDERS)
+            .collect()
+    }
+
+    /// Return the IDs of all repositories backing this registry, by priority
+    pub fn repositories(&self) -> impl Iterator<Item = &repository::Id> + '_ {
+        self.plugins
+            .iter()
+            .sorted_by(|a, b| a.priority().cmp(&b.priority()).reverse())
+            .filter_map(|plugin| match plugin {
+                Plugin::Repository(repository) => Some(repository.id()),
+                _ => None,
+            })
+    }
+
+    /// Return a registry of the repository plugins only, which resolves
+    /// against available packages regardless of what is installed
+    pub fn available(&self) -> Registry {
+        Registry {
+            plugins: self
+                .plugins
+                .iter()
+                .filter_map(|plugin| match plugin {
+                    Plugin::Repository(repository) => Some(Plugin::Repository(repository.clone())),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
     /// Return a new transaction for this registry
     pub fn transaction(&self) -> Result<Transaction<'_>, transaction::Error> {
         transaction::new(self)
@@ -105,6 +170,16 @@ impl Registry {
     ) -> Result<Transaction<'_>, transaction::Error> {
         transaction::new_with_installed(self, incoming)
     }
+
+    /// Return a new transaction for this registry adding `incoming` to the `installed` package set,
+    /// swapping out installed packages which conflict with the incoming ones
+    pub fn transaction_with_replacements(
+        &self,
+        installed: Vec<package::Id>,
+        incoming: Vec<package::Id>,
+    ) -> Result<transaction::Replacement<'_>, transaction::Error> {
+        transaction::new_with_replacements(self, installed, incoming)
+    }
 }
 
 #[cfg(test)]
@@ -136,6 +211,7 @@ mod test {
                 uri: Default::default(),
                 hash: Default::default(),
                 download_size: Default::default(),
+                delta: Default::default(),
             },
             flags: package::Flags::default(),
         };
@@ -191,6 +267,7 @@ mod test {
                 uri: Default::default(),
                 hash: Default::default(),
                 download_size: Default::default(),
+                delta: Default::default(),
             },
             flags,
         };
@@ -226,4 +303,159 @@ mod test {
         assert!(matches(installed_source, &["d"]));
         assert!(matches(available_source, &["e"]));
     }
+
+    #[test]
+    fn test_conflicts() {
+        let mut registry = Registry::default();
+
+        let name = |name: &str| Provider::from_name(name).unwrap();
+        let package = |id: &str, dependencies: &[&str], conflicts: &[&str]| Package {
+            id: package::Id::from(id.to_string()),
+            meta: package::Meta {
+                name: package::Name::from(id.to_string()),
+                dependencies: dependencies
+                    .iter()
+                    .map(|d| crate::Dependency::from_name(d).unwrap())
+                    .collect(),
+                providers: BTreeSet::from([name(id)]),
+                conflicts: conflicts.iter().map(|c| name(c)).collect(),
+                ..Default::default()
+            },
+            flags: package::Flags::new().with_available(),
+        };
+
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("pizza", &[], &["pineapple"]),
+                package("pineapple", &[], &[]),
+                package("hawaiian", &["pineapple"], &[]),
+            ],
+        )));
+
+        let id = |id: &str| package::Id::from(id.to_string());
+
+        let mut tx = registry.transaction().unwrap();
+        tx.add(vec![id("pizza"), id("hawaiian")]).unwrap_err();
+
+        let mut tx = registry.transaction().unwrap();
+        tx.add(vec![id("hawaiian")]).unwrap();
+        let Err(transaction::Error::Solver(solver::Error::Unsatisfiable(unsatisfiable))) = tx.add(vec![id("pizza")])
+        else {
+            panic!("expected unsatisfiable");
+        };
+
+        assert_eq!(unsatisfiable.core, vec![id("pizza")]);
+        assert!(matches!(
+            unsatisfiable.failure.rejected.as_slice(),
+            [(_, solver::Rejection::Conflict(transaction::Conflict { package, conflicts_with, .. }))]
+                if *package == id("pizza") && *conflicts_with == id("pineapple")
+        ));
+    }
+
+    #[test]
+    fn test_orphans() {
+        let mut registry = Registry::default();
+
+        let name = |name: &str| Provider::from_name(name).unwrap();
+        let package = |id: &str, dependencies: &[&str]| Package {
+            id: package::Id::from(id.to_string()),
+            meta: package::Meta {
+                name: package::Name::from(id.to_string()),
+                dependencies: dependencies
+                    .iter()
+                    .map(|d| crate::Dependency::from_name(d).unwrap())
+                    .collect(),
+                providers: BTreeSet::from([name(id)]),
+                ..Default::default()
+            },
+            flags: package::Flags::new().with_installed(),
+        };
+
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("pizza", &["dough", "cheese"]),
+                package("calzone", &["dough", "ham"]),
+                package("dough", &["flour"]),
+                package("cheese", &[]),
+                package("ham", &[]),
+                package("flour", &[]),
+            ],
+        )));
+
+        let id = |id: &str| package::Id::from(id.to_string());
+
+        let installed = registry
+            .list_installed(package::Flags::default())
+            .map(|p| p.id)
+            .collect();
+        let mut tx = registry.transaction_with_installed(installed).unwrap();
+        tx.remove(vec![id("calzone")]);
+
+        let orphans = tx.orphans(&[id("pizza")]).into_iter().collect::<BTreeSet<_>>();
+
+        assert_eq!(orphans, BTreeSet::from([id("ham")]));
+    }
+
+    #[test]
+    fn test_replacements() {
+        let mut registry = Registry::default();
+
+        let provider = |name: &str| Provider::from_name(name).unwrap();
+        let package = |id: &str, providers: &[&str], dependencies: &[&str], conflicts: &[&str], flags| Package {
+            id: package::Id::from(id.to_string()),
+            meta: package::Meta {
+                name: package::Name::from(id.to_string()),
+                dependencies: dependencies
+                    .iter()
+                    .map(|d| crate::Dependency::from_name(d).unwrap())
+                    .collect(),
+                providers: providers.iter().chain(Some(&id)).map(|p| provider(p)).collect(),
+                conflicts: conflicts.iter().map(|c| provider(c)).collect(),
+                ..Default::default()
+            },
+            flags,
+        };
+        let installed = package::Flags::new().with_installed();
+        let available = package::Flags::new().with_available();
+
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("bash", &["binary(sh)"], &[], &[], installed),
+                package("scripts", &[], &["binary(sh)"], &[], installed),
+                // Replaces bash, along with the `sh` it provides
+                package("dash", &["binary(sh)"], &[], &["bash"], available),
+                // Replaces bash, leaving `scripts` without a shell
+                package("busybox", &[], &[], &["bash"], available),
+                package("toybox", &[], &[], &["busybox"], available),
+            ],
+        )));
+
+        let id = |id: &str| package::Id::from(id.to_string());
+        let system = || vec![id("bash"), id("scripts")];
+
+        let replacement = registry
+            .transaction_with_replacements(system(), vec![id("dash")])
+            .unwrap();
+        assert_eq!(replacement.replaced, vec![id("bash")]);
+        assert_eq!(
+            replacement.transaction.finalize().cloned().collect::<BTreeSet<_>>(),
+            BTreeSet::from([id("scripts"), id("dash")])
+        );
+
+        let Err(transaction::Error::BrokenDependents { replaced, dependents }) =
+            registry.transaction_with_replacements(system(), vec![id("busybox")])
+        else {
+            panic!("expected broken dependents");
+        };
+        assert_eq!(replaced, vec![id("bash")]);
+        assert_eq!(dependents, vec![id("scripts")]);
+
+        // Incoming packages are checked against each other too
+        assert!(registry
+            .transaction_with_replacements(system(), vec![id("dash"), id("busybox"), id("toybox")])
+            .is_err());
+    }
 }
@@ -114,6 +114,21 @@ impl Active {
         }
     }
 
+    /// Providers of installed packages containing `keyword`
+    pub fn query_providers_matching(&self, keyword: &str) -> Vec<Provider> {
+        if self.state.is_none() {
+            return vec![];
+        }
+
+        match self.db.providers_matching(keyword) {
+            Ok(providers) => providers,
+            Err(error) => {
+                warn!("failed to query installed providers: {error}");
+                vec![]
+            }
+        }
+    }
+
     pub fn priority(&self) -> u64 {
         u64::MAX
     }
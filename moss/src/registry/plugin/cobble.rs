@@ -11,8 +11,8 @@ use thiserror::Error;
 
 use stone::read::PayloadKind;
 
-use crate::package::{self, meta, Meta, MissingMetaFieldError, Package};
-use crate::Provider;
+use crate::package::{self, meta, Meta, Package};
+use crate::{signing, Provider};
 
 // TODO:
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -22,9 +22,11 @@ pub struct Cobble {
 }
 
 impl Cobble {
-    /// Add a package to the cobble set
-    pub fn add_package(&mut self, path: impl Into<PathBuf>) -> Result<meta::Id, Error> {
+    /// Add a package to the cobble set, once its signature satisfies `signing`
+    pub fn add_package(&mut self, path: impl Into<PathBuf>, signing: &signing::Config) -> Result<meta::Id, Error> {
         let path = path.into();
+        signing.check(&path)?;
+
         let mut file = File::open(&path)?;
         let mut reader = stone::read(&mut file)?;
         let mut payloads = reader.payloads()?;
@@ -86,6 +88,15 @@ impl Cobble {
         self.query(flags, |meta| meta.name == *package_name)
     }
 
+    pub fn query_providers_matching(&self, keyword: &str) -> Vec<Provider> {
+        self.packages
+            .values()
+            .flat_map(|state| &state.meta.providers)
+            .filter(|provider| provider.to_string().contains(keyword))
+            .cloned()
+            .collect()
+    }
+
     pub fn priority(&self) -> u64 {
         u64::MAX
     }
@@ -120,5 +131,8 @@ pub enum Error {
     Io(#[from] io::Error),
 
     #[error("metadata")]
-    Metadata(#[from] MissingMetaFieldError),
+    Metadata(#[from] meta::Error),
+
+    #[error("signature")]
+    Signing(#[from] signing::Error),
 }
@@ -116,6 +116,18 @@ impl Plugin {
         })
     }
 
+    /// Returns all providers containing `keyword`
+    pub fn query_providers_matching(&self, keyword: &str) -> Vec<Provider> {
+        match self {
+            Plugin::Active(plugin) => plugin.query_providers_matching(keyword),
+            Plugin::Cobble(plugin) => plugin.query_providers_matching(keyword),
+            Plugin::Repository(plugin) => plugin.query_providers_matching(keyword),
+
+            #[cfg(test)]
+            Plugin::Test(plugin) => plugin.query_providers_matching(keyword),
+        }
+    }
+
     /// Plugin priority
     ///
     /// Higher priority = better chance of selection
@@ -183,6 +195,15 @@ pub mod test {
                 .collect()
         }
 
+        pub fn query_providers_matching(&self, keyword: &str) -> Vec<Provider> {
+            self.packages
+                .iter()
+                .flat_map(|p| &p.meta.providers)
+                .filter(|provider| provider.to_string().contains(keyword))
+                .cloned()
+                .collect()
+        }
+
         pub fn query_name(&self, package_name: &package::Name, flags: package::Flags) -> Vec<Package> {
             self.packages
                 .iter()
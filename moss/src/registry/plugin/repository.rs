@@ -10,7 +10,7 @@ use crate::{
     repository, Provider,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Repository {
     active: repository::Cached,
 }
@@ -24,6 +24,11 @@ impl Repository {
         self.active.repository.priority.into()
     }
 
+    /// Identifier of the backing repository
+    pub fn id(&self) -> &repository::Id {
+        &self.active.id
+    }
+
     pub fn package(&self, id: &package::Id) -> Option<Package> {
         let result = self.active.db.get(id);
 
@@ -37,6 +42,13 @@ impl Repository {
                         .uri
                         .and_then(|relative| self.active.repository.uri.join(&relative).ok())
                         .map(|url| url.to_string()),
+                    delta: meta.delta.and_then(|delta| {
+                        let url = self.active.repository.uri.join(&delta.uri).ok()?;
+                        Some(package::meta::Delta {
+                            uri: url.to_string(),
+                            ..delta
+                        })
+                    }),
                     ..meta
                 },
                 flags: package::Flags::new().with_available(),
@@ -90,6 +102,16 @@ impl Repository {
         self.query(flags, Some(db::meta::Filter::Name(package_name.clone())))
     }
 
+    pub fn query_providers_matching(&self, keyword: &str) -> Vec<Provider> {
+        match self.active.db.providers_matching(keyword) {
+            Ok(providers) => providers,
+            Err(error) => {
+                warn!("failed to query repository providers: {error}");
+                vec![]
+            }
+        }
+    }
+
     pub fn query_provider_id_only(&self, provider: &Provider, flags: package::Flags) -> Vec<package::Id> {
         if flags.available || flags == package::Flags::default() {
             // TODO: Error handling
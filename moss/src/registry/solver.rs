@@ -0,0 +1,802 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Backtracking dependency resolution
+//!
+//! The [`Solver`] searches for a complete set of packages meeting every requirement
+//! of a [`super::Transaction`]:
+//!
+//!  - every dependency is provided by a selected package, honouring version constraints
+//!  - at most one package is selected per name
+//!  - no two selected packages conflict
+//!
+//! Candidates are tried in order of preference (pinned, installed, then registry priority)
+//! and the solver backtracks whenever a choice leads to a dead end, jumping straight
+//! past choices which played no part in it. If no solution exists the requested packages
+//! are reduced to a minimal unsatisfiable core, the smallest subset which still can't be
+//! installed together.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::registry::transaction::{self, Conflict};
+use crate::{package, repository, Dependency, Package, Provider, Registry};
+
+/// Upper bound on requirements visited by a single search, guarding
+/// against pathological backtracking
+const MAX_STEPS: usize = 250_000;
+
+/// Which packages are eligible for selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Installed packages only, taken as-is without enforcing conflicts
+    Installed,
+    /// Any installed or available package
+    Global,
+}
+
+/// Something the solution must satisfy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Requirement {
+    /// An explicitly requested package
+    Package(package::Id),
+    /// A dependency of a selected package
+    Dependency {
+        dependent: package::Id,
+        dependency: Dependency,
+    },
+}
+
+/// Why a candidate was passed over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// Conflicts with a selected package
+    Conflict(Conflict),
+    /// A different package of the same name is already selected
+    NameTaken(package::Id),
+    /// Doesn't meet the version constraint of the dependency
+    Constraint,
+}
+
+/// A requirement which no candidate could meet
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// Dependencies leading from an explicitly requested package down to `requirement`
+    pub chain: Vec<(package::Id, Dependency)>,
+    pub requirement: Requirement,
+    /// All candidates considered and why they were rejected
+    pub rejected: Vec<(package::Id, Rejection)>,
+    /// Scope candidates were drawn from
+    pub scope: Scope,
+}
+
+impl Failure {
+    /// Packages whose selection led to this failure
+    fn culprits(&self) -> impl Iterator<Item = &package::Id> {
+        let dependent = match &self.requirement {
+            Requirement::Package(_) => None,
+            Requirement::Dependency { dependent, .. } => Some(dependent),
+        };
+
+        dependent
+            .into_iter()
+            .chain(self.rejected.iter().flat_map(|(id, rejection)| {
+                let ids: Vec<&package::Id> = match rejection {
+                    Rejection::Conflict(conflict) => vec![&conflict.package, &conflict.conflicts_with],
+                    Rejection::NameTaken(other) => vec![other],
+                    Rejection::Constraint => vec![],
+                };
+                ids.into_iter().filter(move |culprit| *culprit != id)
+            }))
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (dependent, dependency) in &self.chain {
+            write!(f, "{dependent} -> {dependency} -> ")?;
+        }
+
+        match &self.requirement {
+            Requirement::Package(id) => write!(f, "{id}")?,
+            Requirement::Dependency { dependent, dependency } => write!(f, "{dependent} -> {dependency}")?,
+        }
+
+        if self.rejected.is_empty() {
+            return match self.scope {
+                Scope::Installed => write!(f, " -> nothing installed provides this"),
+                Scope::Global => write!(f, " -> no active repository provides this"),
+            };
+        }
+
+        let reasons = self
+            .rejected
+            .iter()
+            .map(|(id, rejection)| match rejection {
+                Rejection::Conflict(conflict) => conflict.to_string(),
+                Rejection::NameTaken(other) => format!("{id} clashes with selected {other}"),
+                Rejection::Constraint => format!("{id} does not meet the version constraint"),
+            })
+            .join(", ");
+
+        write!(f, " -> {reasons}")
+    }
+}
+
+/// No solution exists for the requested packages
+#[derive(Debug, Clone)]
+pub struct Unsatisfiable {
+    /// Minimal subset of the requested packages which can't be installed together
+    pub core: Vec<package::Id>,
+    /// Why resolution of `core` failed
+    pub failure: Failure,
+    /// Existing providers named similarly to an unprovided dependency
+    pub similar: Vec<Provider>,
+    /// Repositories consulted for candidates, by priority
+    pub repositories: Vec<repository::Id>,
+}
+
+impl fmt::Display for Unsatisfiable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot satisfy {}", self.core.iter().join(", "))?;
+        write!(f, "\n  {}", self.failure)?;
+
+        if !self.similar.is_empty() {
+            write!(f, "\n  similarly named providers: {}", self.similar.iter().join(", "))?;
+        }
+        if self.failure.scope == Scope::Global {
+            if self.repositories.is_empty() {
+                write!(f, "\n  no repositories are active")?;
+            } else {
+                write!(f, "\n  repositories consulted: {}", self.repositories.iter().join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A complete and consistent selection of packages
+#[derive(Debug)]
+pub struct Solution {
+    /// Selected packages, in order of selection
+    pub packages: Vec<Package>,
+    /// Dependency edges as (dependent, dependency)
+    pub edges: Vec<(package::Id, package::Id)>,
+}
+
+/// Search state. Everything but the lookup maps is append-only, so a
+/// failed branch is discarded by rolling back to a [`Checkpoint`]
+#[derive(Debug, Clone, Default)]
+struct State {
+    /// Selected package for each name
+    names: BTreeMap<package::Name, package::Id>,
+    /// Selected packages and the dependency which brought them in
+    selected: BTreeMap<package::Id, Option<(package::Id, Dependency)>>,
+    /// Selected packages in order of selection
+    order: Vec<package::Id>,
+    edges: Vec<(package::Id, package::Id)>,
+    /// Requirements in order of discovery, those before `next` are met
+    pending: Vec<Requirement>,
+    next: usize,
+}
+
+/// Lengths of the append-only parts of a [`State`] at a branching point
+struct Checkpoint {
+    order: usize,
+    edges: usize,
+    pending: usize,
+    next: usize,
+}
+
+impl State {
+    fn pop(&mut self) -> Option<Requirement> {
+        let requirement = self.pending.get(self.next).cloned()?;
+        self.next += 1;
+        Some(requirement)
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            order: self.order.len(),
+            edges: self.edges.len(),
+            pending: self.pending.len(),
+            next: self.next,
+        }
+    }
+
+    /// Undo every selection made since `checkpoint`
+    fn rollback(&mut self, checkpoint: &Checkpoint, packages: &BTreeMap<package::Id, Package>) {
+        for id in self.order.drain(checkpoint.order..) {
+            self.selected.remove(&id);
+            self.names.remove(&packages[&id].meta.name);
+        }
+        self.edges.truncate(checkpoint.edges);
+        self.pending.truncate(checkpoint.pending);
+        self.next = checkpoint.next;
+    }
+}
+
+enum Search {
+    Failed(Box<Failure>),
+    Exhausted,
+}
+
+/// Outcome of meeting pending requirements until a choice has to be made
+enum Step {
+    /// Every requirement is met
+    Solved,
+    /// The requirement has several viable candidates, in order of preference
+    Branch(Requirement, Vec<package::Id>),
+    Failed(Box<Failure>),
+}
+
+/// A requirement with several viable candidates, which are tried in turn
+struct Branch {
+    checkpoint: Checkpoint,
+    requirement: Requirement,
+    /// Candidates yet to be tried
+    alternatives: std::vec::IntoIter<package::Id>,
+    /// Failure of the first candidate tried
+    first_failure: Option<Box<Failure>>,
+}
+
+pub struct Solver<'a> {
+    registry: &'a Registry,
+    scope: Scope,
+    /// Packages preferred over all other candidates
+    pinned: &'a [package::Id],
+    /// Cached package lookups
+    packages: BTreeMap<package::Id, Package>,
+    /// Cached, preference ordered candidates for each dependency
+    candidates: BTreeMap<Dependency, Vec<package::Id>>,
+    steps: usize,
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(registry: &'a Registry, scope: Scope, pinned: &'a [package::Id]) -> Self {
+        Self {
+            registry,
+            scope,
+            pinned,
+            packages: BTreeMap::new(),
+            candidates: BTreeMap::new(),
+            steps: 0,
+        }
+    }
+
+    /// Solve for the `requested` packages on top of the already `fixed` selection,
+    /// whose dependencies are assumed to be met
+    pub fn solve<'b>(
+        &mut self,
+        fixed: impl IntoIterator<Item = &'b Package>,
+        requested: &[package::Id],
+    ) -> Result<Solution, Error> {
+        let requested = requested.iter().unique().cloned().collect::<Vec<_>>();
+
+        for id in &requested {
+            if self.package(id).is_none() {
+                return Err(Error::NoCandidate(id.to_string()));
+            }
+        }
+
+        let mut initial = State::default();
+        for package in fixed {
+            self.packages.insert(package.id.clone(), package.clone());
+            initial.names.insert(package.meta.name.clone(), package.id.clone());
+            initial.selected.insert(package.id.clone(), None);
+            initial.order.push(package.id.clone());
+        }
+
+        match self.run(&initial, &requested) {
+            Ok(state) => Ok(self.solution(state)),
+            Err(Search::Exhausted) => Err(Error::Exhausted),
+            Err(Search::Failed(failure)) => {
+                let mut unsatisfiable = self.minimize(&initial, requested, *failure);
+                self.explain(&mut unsatisfiable);
+                Err(Error::Unsatisfiable(Box::new(unsatisfiable)))
+            }
+        }
+    }
+
+    /// Search from `initial` with a fresh step budget
+    fn run(&mut self, initial: &State, requested: &[package::Id]) -> Result<State, Search> {
+        let mut state = initial.clone();
+        state
+            .pending
+            .extend(requested.iter().cloned().map(Requirement::Package));
+
+        self.steps = 0;
+        self.search(&mut state)?;
+
+        Ok(state)
+    }
+
+    /// Shrink the requested packages down to a minimal unsatisfiable core by
+    /// dropping each one in turn and keeping it out if resolution still fails
+    fn minimize(&mut self, initial: &State, requested: Vec<package::Id>, failure: Failure) -> Unsatisfiable {
+        let mut core = requested.clone();
+        let mut failure = failure;
+
+        for id in &requested {
+            if core.len() == 1 {
+                break;
+            }
+
+            let reduced = core.iter().filter(|c| *c != id).cloned().collect::<Vec<_>>();
+
+            match self.run(initial, &reduced) {
+                Err(Search::Failed(reduced_failure)) => {
+                    core = reduced;
+                    failure = *reduced_failure;
+                }
+                Ok(_) => {}
+                Err(Search::Exhausted) => break,
+            }
+        }
+
+        Unsatisfiable {
+            core,
+            failure,
+            similar: vec![],
+            repositories: vec![],
+        }
+    }
+
+    /// Add context to help track down the cause of `unsatisfiable`
+    fn explain(&self, unsatisfiable: &mut Unsatisfiable) {
+        let failure = &unsatisfiable.failure;
+
+        if let (Requirement::Dependency { dependency, .. }, true) = (&failure.requirement, failure.rejected.is_empty())
+        {
+            unsatisfiable.similar = self.registry.similar_providers(&dependency.provider());
+        }
+        if self.scope == Scope::Global {
+            unsatisfiable.repositories = self.registry.repositories().cloned().collect();
+        }
+    }
+
+    fn search(&mut self, state: &mut State) -> Result<(), Search> {
+        // Choices which still have untried alternatives, innermost last
+        let mut branches: Vec<Branch> = vec![];
+
+        loop {
+            let mut failure = match self.propagate(state)? {
+                Step::Solved => return Ok(()),
+                Step::Branch(requirement, viable) => {
+                    let mut alternatives = viable.into_iter();
+                    let id = alternatives.next().expect("multiple viable candidates");
+
+                    branches.push(Branch {
+                        checkpoint: state.checkpoint(),
+                        requirement: requirement.clone(),
+                        alternatives,
+                        first_failure: None,
+                    });
+                    self.select(state, &requirement, id);
+                    continue;
+                }
+                Step::Failed(failure) => failure,
+            };
+
+            // Backtrack to the innermost choice with an alternative which may avoid the failure
+            loop {
+                let Some(branch) = branches.last_mut() else {
+                    return Err(Search::Failed(failure));
+                };
+                state.rollback(&branch.checkpoint, &self.packages);
+
+                // Nothing chosen in this branch caused the failure,
+                // so no alternative will avoid it either
+                if failure.culprits().all(|id| state.selected.contains_key(id)) {
+                    branches.pop();
+                    continue;
+                }
+
+                branch.first_failure.get_or_insert(failure);
+
+                match branch.alternatives.next() {
+                    Some(id) => {
+                        let requirement = branch.requirement.clone();
+                        self.select(state, &requirement, id);
+                        break;
+                    }
+                    None => {
+                        let branch = branches.pop().expect("innermost branch");
+                        failure = branch.first_failure.expect("failure of first candidate");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Meet pending requirements in order until one has several viable candidates
+    fn propagate(&mut self, state: &mut State) -> Result<Step, Search> {
+        while let Some(requirement) = state.pop() {
+            self.steps += 1;
+            if self.steps > MAX_STEPS {
+                return Err(Search::Exhausted);
+            }
+
+            let candidates = self.candidates(&requirement);
+
+            // Already met by a selected package
+            if let Some(id) = candidates
+                .iter()
+                .find(|id| state.selected.contains_key(*id) && self.meets(id, &requirement))
+            {
+                if let Requirement::Dependency { dependent, .. } = &requirement {
+                    state.edges.push((dependent.clone(), id.clone()));
+                }
+                continue;
+            }
+
+            let (viable, rejected): (Vec<_>, Vec<_>) = candidates
+                .into_iter()
+                .map(|id| match self.check(state, &requirement, &id) {
+                    Ok(()) => Ok(id),
+                    Err(rejection) => Err((id, rejection)),
+                })
+                .partition_result();
+
+            match viable.as_slice() {
+                [] => {
+                    return Ok(Step::Failed(Box::new(failure(
+                        state,
+                        self.scope,
+                        requirement,
+                        rejected,
+                    ))))
+                }
+                [id] => self.select(state, &requirement, id.clone()),
+                _ => return Ok(Step::Branch(requirement, viable)),
+            }
+        }
+
+        Ok(Step::Solved)
+    }
+
+    /// Select the candidate `id` for the requirement and queue up its dependencies
+    fn select(&self, state: &mut State, requirement: &Requirement, id: package::Id) {
+        let package = &self.packages[&id];
+
+        let reason = match requirement {
+            Requirement::Package(_) => None,
+            Requirement::Dependency { dependent, dependency } => {
+                state.edges.push((dependent.clone(), id.clone()));
+                Some((dependent.clone(), dependency.clone()))
+            }
+        };
+
+        state.names.insert(package.meta.name.clone(), id.clone());
+        state.selected.insert(id.clone(), reason);
+        state.order.push(id.clone());
+        state.pending.extend(
+            package
+                .meta
+                .dependencies
+                .iter()
+                .map(|dependency| Requirement::Dependency {
+                    dependent: id.clone(),
+                    dependency: dependency.clone(),
+                }),
+        );
+    }
+
+    /// Ensure the candidate can be added to the current selection
+    fn check(&self, state: &State, requirement: &Requirement, id: &package::Id) -> Result<(), Rejection> {
+        let package = &self.packages[id];
+
+        if !self.meets(id, requirement) {
+            return Err(Rejection::Constraint);
+        }
+
+        if let Some(other) = state.names.get(&package.meta.name).filter(|other| *other != id) {
+            return Err(Rejection::NameTaken(other.clone()));
+        }
+
+        if self.scope == Scope::Global {
+            if let Some(conflict) = state
+                .selected
+                .keys()
+                .find_map(|other| transaction::conflict(package, &self.packages[other]))
+            {
+                return Err(Rejection::Conflict(conflict));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the package meets the version constraint of the requirement
+    fn meets(&self, id: &package::Id, requirement: &Requirement) -> bool {
+        match requirement {
+            Requirement::Package(requested) => requested == id,
+            Requirement::Dependency { dependency, .. } => dependency.satisfied_by(&self.packages[id].meta),
+        }
+    }
+
+    /// Candidates for the requirement, in order of preference
+    fn candidates(&mut self, requirement: &Requirement) -> Vec<package::Id> {
+        let dependency = match requirement {
+            Requirement::Package(id) => return vec![id.clone()],
+            Requirement::Dependency { dependency, .. } => dependency,
+        };
+
+        if let Some(candidates) = self.candidates.get(dependency) {
+            return candidates.clone();
+        }
+
+        let provider = dependency.provider();
+        let installed = self
+            .registry
+            .by_provider(&provider, package::Flags::new().with_installed())
+            .collect::<Vec<_>>();
+        let available = match self.scope {
+            Scope::Global => self
+                .registry
+                .by_provider(&provider, package::Flags::new().with_available())
+                .collect(),
+            Scope::Installed => vec![],
+        };
+
+        // Pinned first, then installed, otherwise keep registry order
+        let packages = installed
+            .into_iter()
+            .chain(available)
+            .unique_by(|package| package.id.clone())
+            .sorted_by_key(|package| (!self.pinned.contains(&package.id), !package.flags.installed))
+            .collect::<Vec<_>>();
+
+        let candidates = packages.iter().map(|package| package.id.clone()).collect::<Vec<_>>();
+
+        for package in packages {
+            self.packages.entry(package.id.clone()).or_insert(package);
+        }
+        self.candidates.insert(dependency.clone(), candidates.clone());
+
+        candidates
+    }
+
+    /// Lookup (and cache) the package for `id`
+    fn package(&mut self, id: &package::Id) -> Option<&Package> {
+        if !self.packages.contains_key(id) {
+            let package = self.registry.by_id(id).next()?;
+            self.packages.insert(id.clone(), package);
+        }
+
+        self.packages.get(id)
+    }
+
+    fn solution(&self, state: State) -> Solution {
+        Solution {
+            packages: state.order.iter().map(|id| self.packages[id].clone()).collect(),
+            edges: state.edges,
+        }
+    }
+}
+
+/// Build the [`Failure`] for a requirement, tracing the
+/// dependency chain back to an explicitly requested package
+fn failure(state: &State, scope: Scope, requirement: Requirement, rejected: Vec<(package::Id, Rejection)>) -> Failure {
+    let mut chain = vec![];
+    let mut current = match &requirement {
+        Requirement::Package(_) => None,
+        Requirement::Dependency { dependent, .. } => Some(dependent),
+    };
+
+    while let Some(Some((dependent, dependency))) = current.and_then(|id| state.selected.get(id)) {
+        chain.push((dependent.clone(), dependency.clone()));
+        current = Some(dependent);
+    }
+
+    chain.reverse();
+
+    Failure {
+        chain,
+        requirement,
+        rejected,
+        scope,
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No such name: {0}")]
+    NoCandidate(String),
+
+    #[error("{0}")]
+    Unsatisfiable(Box<Unsatisfiable>),
+
+    #[error("gave up after exploring {MAX_STEPS} requirements")]
+    Exhausted,
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::registry::{plugin, Plugin};
+
+    fn package(id: &str, providers: &[&str], dependencies: &[&str], conflicts: &[&str]) -> Package {
+        let name = id.split_once('-').map_or(id, |(name, _)| name);
+
+        Package {
+            id: package::Id::from(id.to_string()),
+            meta: package::Meta {
+                name: package::Name::from(name.to_string()),
+                dependencies: dependencies.iter().map(|d| Dependency::from_name(d).unwrap()).collect(),
+                providers: providers
+                    .iter()
+                    .chain(Some(&name))
+                    .map(|p| Provider::from_name(p).unwrap())
+                    .collect(),
+                conflicts: conflicts.iter().map(|c| Provider::from_name(c).unwrap()).collect(),
+                ..Default::default()
+            },
+            flags: package::Flags::new().with_available(),
+        }
+    }
+
+    fn id(id: &str) -> package::Id {
+        package::Id::from(id.to_string())
+    }
+
+    #[test]
+    fn backtracks_alternative_providers() {
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("app", &[], &["soname(libfoo.so)"], &[]),
+                // Preferred provider conflicts with `tool`
+                package("foo-a", &["soname(libfoo.so)"], &[], &["tool"]),
+                package("foo-b", &["soname(libfoo.so)"], &[], &[]),
+                package("tool", &[], &[], &[]),
+            ],
+        )));
+
+        let solution = Solver::new(&registry, Scope::Global, &[])
+            .solve([], &[id("app"), id("tool")])
+            .unwrap();
+        let selected = solution.packages.into_iter().map(|p| p.id).collect::<BTreeSet<_>>();
+
+        assert_eq!(selected, BTreeSet::from([id("app"), id("tool"), id("foo-b")]));
+    }
+
+    #[test]
+    fn minimal_core() {
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("pizza", &[], &[], &["pineapple"]),
+                package("pineapple", &[], &[], &[]),
+                package("hawaiian", &[], &["pineapple"], &[]),
+                package("margherita", &[], &[], &[]),
+            ],
+        )));
+
+        let Err(Error::Unsatisfiable(unsatisfiable)) =
+            Solver::new(&registry, Scope::Global, &[]).solve([], &[id("margherita"), id("pizza"), id("hawaiian")])
+        else {
+            panic!("expected unsatisfiable");
+        };
+
+        assert_eq!(unsatisfiable.core, vec![id("pizza"), id("hawaiian")]);
+        assert!(matches!(
+            unsatisfiable.failure.rejected.as_slice(),
+            [(_, Rejection::Conflict(_))]
+        ));
+    }
+
+    #[test]
+    fn explains_missing_provider() {
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("firefox", &[], &["nss"], &[]),
+                package("nss", &[], &["soname(libnss3.so(x86_64))"], &[]),
+                package("compat", &["soname(libnss3.so.1(x86_64))"], &[], &[]),
+            ],
+        )));
+
+        let Err(Error::Unsatisfiable(unsatisfiable)) =
+            Solver::new(&registry, Scope::Global, &[]).solve([], &[id("firefox")])
+        else {
+            panic!("expected unsatisfiable");
+        };
+
+        assert_eq!(
+            unsatisfiable.failure.to_string(),
+            "firefox -> name(nss) -> nss -> soname(libnss3.so(x86_64)) -> no active repository provides this"
+        );
+        assert_eq!(
+            unsatisfiable.similar,
+            vec![Provider::from_name("soname(libnss3.so.1(x86_64))").unwrap()]
+        );
+    }
+
+    #[test]
+    fn prefers_installed_at_scale() {
+        const COUNT: usize = 800;
+
+        // Every library is installed, with a newer build available, and depends
+        // on its predecessor and on one roughly halfway down the stack
+        let libraries = (0..COUNT)
+            .map(|i| {
+                [i.checked_sub(1), Some(i / 2).filter(|d| *d != i && *d + 1 != i)]
+                    .into_iter()
+                    .flatten()
+                    .map(|d| format!("lib{d}"))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut packages = vec![package("app", &[], &[&format!("lib{}", COUNT - 1)], &[])];
+        for (i, dependencies) in libraries.iter().enumerate() {
+            let dependencies = dependencies.iter().map(String::as_str).collect::<Vec<_>>();
+
+            packages.push(Package {
+                flags: package::Flags::new().with_installed(),
+                ..package(&format!("lib{i}-1"), &[], &dependencies, &[])
+            });
+            packages.push(package(&format!("lib{i}-2"), &[], &dependencies, &[]));
+        }
+
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(1, packages)));
+
+        let solution = Solver::new(&registry, Scope::Global, &[])
+            .solve([], &[id("app")])
+            .unwrap();
+
+        assert_eq!(solution.packages.len(), COUNT + 1);
+        assert!(solution
+            .packages
+            .iter()
+            .filter(|p| p.id != id("app"))
+            .all(|p| p.flags.installed));
+    }
+
+    #[test]
+    fn backtracks_to_other_builds() {
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("app", &[], &["lib", "tool"], &[]),
+                package("tool", &[], &["lib >= 2"], &[]),
+                // The installed build is preferred, but too old for `tool`
+                Package {
+                    flags: package::Flags::new().with_installed(),
+                    meta: package::Meta {
+                        version_identifier: "1".to_string(),
+                        ..package("lib-1", &[], &[], &[]).meta
+                    },
+                    ..package("lib-1", &[], &[], &[])
+                },
+                Package {
+                    meta: package::Meta {
+                        version_identifier: "2".to_string(),
+                        ..package("lib-2", &[], &[], &[]).meta
+                    },
+                    ..package("lib-2", &[], &[], &[])
+                },
+            ],
+        )));
+
+        let solution = Solver::new(&registry, Scope::Global, &[])
+            .solve([], &[id("app")])
+            .unwrap();
+        let selected = solution.packages.into_iter().map(|p| p.id).collect::<BTreeSet<_>>();
+
+        assert_eq!(selected, BTreeSet::from([id("app"), id("tool"), id("lib-2")]));
+    }
+}
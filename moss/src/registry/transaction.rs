@@ -2,30 +2,15 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
+use std::collections::BTreeMap;
+
 use dag::Dag;
+use derive_more::Display;
+use itertools::Itertools;
 use thiserror::Error;
 
-use crate::{package, Provider, Registry};
-
-enum ProviderFilter {
-    /// Must be installed
-    InstalledOnly(Provider),
-
-    /// Filter the lookup to current selection scope
-    Selections(Provider),
-
-    /// Use a pinned package if one exists
-    /// for this provider
-    Pinned(Provider),
-
-    // Look beyond installed/selections
-    All(Provider),
-}
-
-enum Lookup {
-    InstalledOnly,
-    Global,
-}
+use super::solver::{self, Scope, Solver};
+use crate::{package, Package, Provider, Registry};
 
 /// A Transaction is used to modify one system state to another
 #[derive(Clone, Debug)]
@@ -37,9 +22,48 @@ pub struct Transaction<'a> {
     packages: Dag<package::Id>,
 
     /// packages which are always considered first
-    /// during [`ProviderFilter::Pinned`] but
-    /// aren't part of `packages` DAG
+    /// by the [`Solver`] but aren't part of
+    /// `packages` DAG
     pinned_providers: Vec<package::Id>,
+
+    /// Resolved packages within the DAG, the
+    /// fixed selection for subsequent solves
+    resolved: BTreeMap<package::Id, Package>,
+}
+
+/// A conflict between two packages, where `package` declares
+/// a conflict on `provider` which `conflicts_with` provides
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[display(fmt = "{package} conflicts with {conflicts_with} ({provider})")]
+pub struct Conflict {
+    pub package: package::Id,
+    pub provider: Provider,
+    pub conflicts_with: package::Id,
+}
+
+/// Return the [`Conflict`] between two packages, if any, checking
+/// declared conflicts in both directions.
+///
+/// Packages of the same name never conflict, as one replaces the other.
+pub fn conflict(a: &Package, b: &Package) -> Option<Conflict> {
+    if a.meta.name == b.meta.name {
+        return None;
+    }
+
+    let declared = |package: &Package, other: &Package| {
+        package
+            .meta
+            .conflicts
+            .iter()
+            .find(|provider| other.meta.providers.contains(provider))
+            .map(|provider| Conflict {
+                package: package.id.clone(),
+                provider: provider.clone(),
+                conflicts_with: other.id.clone(),
+            })
+    };
+
+    declared(a, b).or_else(|| declared(b, a))
 }
 
 /// Construct a new Transaction wrapped around the underlying Registry
@@ -50,20 +74,90 @@ pub(super) fn new(registry: &Registry) -> Result<Transaction<'_>, Error> {
         registry,
         packages: Dag::default(),
         pinned_providers: vec![],
+        resolved: BTreeMap::new(),
     })
 }
 
 /// Populate the transaction on initialisation
 pub(super) fn new_with_installed(registry: &Registry, incoming: Vec<package::Id>) -> Result<Transaction<'_>, Error> {
     let mut tx = new(registry)?;
-    tx.update(incoming, Lookup::InstalledOnly)?;
+    tx.update(incoming, Scope::Installed)?;
     Ok(tx)
 }
 
+/// A transaction adding incoming packages to the installed ones, see [`new_with_replacements`]
+#[derive(Debug)]
+pub struct Replacement<'a> {
+    /// The installed packages which were kept, plus the incoming packages
+    pub transaction: Transaction<'a>,
+    /// Conflicts between incoming and installed packages
+    pub conflicts: Vec<Conflict>,
+    /// Installed packages swapped out for the incoming packages they conflict with
+    pub replaced: Vec<package::Id>,
+}
+
+/// Populate a transaction with the `installed` packages plus the `incoming` ones.
+///
+/// Installed packages which conflict with an incoming package, or one of its dependencies,
+/// are swapped out. The whole selection is then solved again, so conflicts and dependencies
+/// are enforced across all of it rather than the incoming packages alone.
+pub(super) fn new_with_replacements(
+    registry: &Registry,
+    installed: Vec<package::Id>,
+    incoming: Vec<package::Id>,
+) -> Result<Replacement<'_>, Error> {
+    // Incoming packages and their dependencies, on their own
+    let mut tx = new(registry)?;
+    tx.add(incoming.clone())?;
+
+    let installed_packages = installed
+        .iter()
+        .filter_map(|id| registry.by_id(id).next())
+        .collect::<Vec<_>>();
+    let conflicts = tx
+        .resolved
+        .values()
+        .filter(|package| !installed.contains(&package.id))
+        .flat_map(|package| {
+            installed_packages
+                .iter()
+                .filter_map(move |other| conflict(package, other))
+        })
+        .collect::<Vec<_>>();
+    let replaced = installed
+        .iter()
+        .filter(|id| conflicts.iter().any(|c| c.package == **id || c.conflicts_with == **id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let kept = installed.iter().filter(|id| !replaced.contains(*id)).cloned();
+
+    let mut transaction = new(registry)?;
+    if let Err(error) = transaction.add(kept.chain(incoming).collect()) {
+        if replaced.is_empty() {
+            return Err(error);
+        }
+
+        // Blame the installed packages which relied on a replaced package
+        let dependents = new_with_installed(registry, installed)?.dependents(&replaced);
+        if dependents.is_empty() {
+            return Err(error);
+        }
+
+        return Err(Error::BrokenDependents { replaced, dependents });
+    }
+
+    Ok(Replacement {
+        transaction,
+        conflicts,
+        replaced,
+    })
+}
+
 impl<'a> Transaction<'a> {
     /// Add a package to this transaction
     pub fn add(&mut self, incoming: Vec<package::Id>) -> Result<(), Error> {
-        self.update(incoming, Lookup::Global)
+        self.update(incoming, Scope::Global)
     }
 
     /// Pins to the provided packages if a provider lookup matches one of these
@@ -81,103 +175,85 @@ impl<'a> Transaction<'a> {
         subgraph.iter_nodes().for_each(|package| {
             // Remove that package
             self.packages.remove_node(package);
+            self.resolved.remove(package);
         });
     }
 
+    /// Return the packages which depend, directly or transitively, on any of `packages`
+    pub fn dependents(&self, packages: &[package::Id]) -> Vec<package::Id> {
+        let transposed = self.packages.transpose();
+
+        transposed
+            .subgraph(packages)
+            .topo()
+            .filter(|package| !packages.contains(package))
+            .cloned()
+            .collect()
+    }
+
+    /// Return the packages which aren't required, directly or
+    /// transitively, by any of the `explicit` packages
+    pub fn orphans(&self, explicit: &[package::Id]) -> Vec<package::Id> {
+        let required = self.packages.subgraph(explicit);
+
+        self.packages
+            .topo()
+            .filter(|package| !required.node_exists(package))
+            .cloned()
+            .collect()
+    }
+
     /// Return the package IDs in the fully baked configuration
     pub fn finalize(&self) -> impl Iterator<Item = &package::Id> + '_ {
         self.packages.topo()
     }
 
+    /// Return the dependency graph, with edges from dependent to dependency
+    pub fn graph(&self) -> &Dag<package::Id> {
+        &self.packages
+    }
+
+    /// Return the resolved [`Package`] for an ID within this transaction
+    pub fn package(&self, id: &package::Id) -> Option<&Package> {
+        self.resolved.get(id)
+    }
+
     /// Update internal package graph with all incoming packages & their deps
-    fn update(&mut self, incoming: Vec<package::Id>, lookup: Lookup) -> Result<(), Error> {
-        let mut items = incoming;
-
-        loop {
-            if items.is_empty() {
-                break;
-            }
-            let mut next = vec![];
-            for check_id in items.iter() {
-                // Ensure node is added and get it's index
-                let check_node = self.packages.add_node_or_get_index(check_id.clone());
-
-                // Grab this package in question
-                let package = self
-                    .registry
-                    .by_id(check_id)
-                    .next()
-                    .ok_or(Error::NoCandidate(check_id.clone().into()))?;
-                for dependency in package.meta.dependencies.iter() {
-                    let provider = Provider {
-                        kind: dependency.kind,
-                        name: dependency.name.clone(),
-                    };
-
-                    // Now get it resolved
-                    let search = match lookup {
-                        Lookup::Global => self.resolve_installation_provider(provider)?,
-                        Lookup::InstalledOnly => self.resolve_provider(ProviderFilter::InstalledOnly(provider))?,
-                    };
-
-                    // Add dependency node
-                    let need_search = !self.packages.node_exists(&search);
-                    let dep_node = self.packages.add_node_or_get_index(search.clone());
-
-                    // No dag node for it previously
-                    if need_search {
-                        next.push(search.clone());
-                    }
-
-                    // Connect w/ edges (rejects cyclical & duplicate edges)
-                    self.packages.add_edge(check_node, dep_node);
-                }
-            }
-            items = next;
+    fn update(&mut self, incoming: Vec<package::Id>, scope: Scope) -> Result<(), Error> {
+        let solution =
+            Solver::new(self.registry, scope, &self.pinned_providers).solve(self.resolved.values(), &incoming)?;
+
+        for package in solution.packages {
+            self.packages.add_node_or_get_index(package.id.clone());
+            self.resolved.insert(package.id.clone(), package);
         }
 
-        Ok(())
-    }
+        for (dependent, dependency) in solution.edges {
+            let dependent = self.packages.add_node_or_get_index(dependent);
+            let dependency = self.packages.add_node_or_get_index(dependency);
 
-    /// Attempt to resolve the filterered provider
-    fn resolve_provider(&self, filter: ProviderFilter) -> Result<package::Id, Error> {
-        match filter {
-            ProviderFilter::All(provider) => self
-                .registry
-                .by_provider_id_only(&provider, package::Flags::new().with_available())
-                .next()
-                .ok_or(Error::NoCandidate(provider.to_string())),
-            ProviderFilter::InstalledOnly(provider) => self
-                .registry
-                .by_provider_id_only(&provider, package::Flags::new().with_installed())
-                .next()
-                .ok_or(Error::NoCandidate(provider.to_string())),
-            ProviderFilter::Selections(provider) => self
-                .registry
-                .by_provider_id_only(&provider, package::Flags::default())
-                .find(|id| self.packages.node_exists(id))
-                .ok_or(Error::NoCandidate(provider.to_string())),
-            ProviderFilter::Pinned(provider) => self
-                .registry
-                .by_provider_id_only(&provider, package::Flags::default())
-                .find(|id| self.pinned_providers.contains(id))
-                .ok_or(Error::NoCandidate(provider.to_string())),
+            // Connect w/ edges (rejects cyclical & duplicate edges)
+            self.packages.add_edge(dependent, dependency);
         }
-    }
 
-    // Try all strategies to resolve a provider for installation
-    fn resolve_installation_provider(&self, provider: Provider) -> Result<package::Id, Error> {
-        self.resolve_provider(ProviderFilter::Pinned(provider.clone()))
-            .or_else(|_| self.resolve_provider(ProviderFilter::Selections(provider.clone())))
-            .or_else(|_| self.resolve_provider(ProviderFilter::InstalledOnly(provider.clone())))
-            .or_else(|_| self.resolve_provider(ProviderFilter::All(provider)))
+        Ok(())
     }
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("No such name: {0}")]
-    NoCandidate(String),
+    #[error("solver")]
+    Solver(#[from] solver::Error),
+
+    #[error(
+        "replacing {} would break {} which depend on it, remove them first",
+        .replaced.iter().join(", "),
+        .dependents.iter().join(", ")
+    )]
+    BrokenDependents {
+        replaced: Vec<package::Id>,
+        dependents: Vec<package::Id>,
+    },
 
     #[error("Not yet implemented")]
     NotImplemented,
@@ -9,15 +9,18 @@ use std::time::Duration;
 
 use fs_err::{self as fs, File};
 use futures::{stream, StreamExt, TryStreamExt};
+use itertools::Itertools;
+use log::warn;
 use thiserror::Error;
+use url::Url;
 use xxhash_rust::xxh3::xxh3_64;
 
 use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
 
-use crate::db::meta;
-use crate::repository::{self, Repository};
-use crate::{environment, runtime};
-use crate::{package, Installation};
+use crate::db::{self, meta};
+use crate::repository::{self, mirror, Repository};
+use crate::{environment, request, runtime, signing};
+use crate::{package, Dependency, Installation};
 
 enum Source {
     System(config::Manager),
@@ -87,6 +90,8 @@ impl Manager {
             .map(|(id, repository)| {
                 let db = open_meta_db(source.identifier(), &repository, &installation)?;
 
+                repository.authenticate();
+
                 Ok((id.clone(), repository::Cached { id, repository, db }))
             })
             .collect::<Result<_, Error>>()?;
@@ -114,6 +119,8 @@ impl Manager {
 
         let db = open_meta_db(self.source.identifier(), &repository, &self.installation)?;
 
+        repository.authenticate();
+
         self.repositories
             .insert(id.clone(), repository::Cached { id, repository, db });
 
@@ -121,17 +128,49 @@ impl Manager {
     }
 
     /// Refresh a [`Repository`] by Id
-    pub async fn refresh(&self, id: &repository::Id) -> Result<(), Error> {
-        if let Some(repo) = self.repositories.get(id).cloned() {
-            if repo.repository.active {
-                let file = fetch_index(self.source.identifier(), &repo, &self.installation).await?;
-                runtime::unblock(move || update_meta_db(&repo, &file)).await?;
-            }
+    ///
+    /// The index is only imported if it changed since it was last fetched
+    pub async fn refresh(&self, id: &repository::Id) -> Result<Refresh, Error> {
+        let Some(repo) = self.repositories.get(id).cloned() else {
+            return Err(Error::UnknownRepo(id.clone()));
+        };
 
-            Ok(())
-        } else {
-            Err(Error::UnknownRepo(id.clone()))
+        if !repo.repository.active {
+            return Ok(Refresh::Unchanged);
         }
+
+        let Some((file, url, validators)) = fetch_index(self.source.identifier(), &repo, &self.installation).await?
+        else {
+            return Ok(Refresh::Unchanged);
+        };
+
+        // The file-list, deltas & constraints indexes are optional, so failing to fetch them shouldn't fail the refresh
+        for name in [
+            repository::FILES_INDEX,
+            repository::DELTAS_INDEX,
+            repository::CONSTRAINTS_INDEX,
+        ] {
+            let fetched = fetch_optional_index(self.source.identifier(), &repo, &url, &self.installation, name).await;
+
+            if let Err(error) = fetched {
+                match std::error::Error::source(&error) {
+                    Some(reason) => warn!("{id}: skipping {name}, {error}: {reason}"),
+                    None => warn!("{id}: skipping {name}, {error}"),
+                }
+            }
+        }
+
+        runtime::unblock(move || {
+            // Forget the previous validators first, so an index which fails
+            // to import is fetched again rather than reported unchanged
+            write_validators(&file, &request::Validators::default())?;
+            update_meta_db(&repo, &file)?;
+            // Only trust the validators once the index they describe is imported
+            write_validators(&file, &validators)
+        })
+        .await?;
+
+        Ok(Refresh::Updated)
     }
 
     /// Refresh all [`Repository`]'s by fetching it's latest index
@@ -154,13 +193,14 @@ impl Manager {
                 );
                 pb.enable_steady_tick(Duration::from_millis(150));
 
-                self.refresh(id).await?;
-
-                pb.suspend(|| println!("{} {}", "Refreshed".green(), *id));
+                match self.refresh(id).await? {
+                    Refresh::Updated => pb.suspend(|| println!("{} {}", "Refreshed".green(), *id)),
+                    Refresh::Unchanged => pb.suspend(|| println!("{} {}", "Unchanged".dim(), *id)),
+                }
 
                 Ok(())
             })
-            .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
+            .buffer_unordered(request::concurrency())
             .try_collect()
             .await
     }
@@ -214,13 +254,83 @@ impl Manager {
 
                 Ok(()) as Result<_, Error>
             })
-            .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
+            .buffer_unordered(request::concurrency())
             .try_collect::<()>()
             .await?;
 
         Ok(uninitialized.len())
     }
 
+    /// Search the cached file-list indexes of all active repositories for paths
+    /// matching `pattern`. Patterns without a `/` are matched against file names.
+    ///
+    /// Repositories which don't publish a file-list index are skipped.
+    pub fn search_files(&self, pattern: &fnmatch::Pattern) -> Result<Vec<FileMatch>, Error> {
+        let mut matches = vec![];
+
+        for cached in self.repositories.values().filter(|c| c.repository.active) {
+            let path = cache_dir(self.source.identifier(), &cached.repository, &self.installation)
+                .join(repository::FILES_INDEX);
+
+            if !path.exists() {
+                continue;
+            }
+
+            let mut file = File::open(&path).map_err(Error::OpenIndex)?;
+            let mut reader = stone::read(&mut file)?;
+
+            // Each layout payload belongs to the package of the meta payload preceding it
+            let mut package = None;
+
+            for payload in reader.payloads()? {
+                match payload? {
+                    stone::read::PayloadKind::Meta(meta) => {
+                        package = meta
+                            .body
+                            .into_iter()
+                            .find_map(|record| match (record.tag, record.kind) {
+                                (stone::payload::meta::Tag::PackageHash, stone::payload::meta::Kind::String(hash)) => {
+                                    Some(package::Id::from(hash))
+                                }
+                                _ => None,
+                            });
+                    }
+                    stone::read::PayloadKind::Layout(layouts) => {
+                        let Some(package) = &package else {
+                            continue;
+                        };
+
+                        for layout in layouts.body {
+                            let path = db::layout::target_path(&layout.entry);
+                            let file_name = vfs::path::file_name(&path).unwrap_or_default();
+
+                            if pattern.match_path(&path).is_some() || pattern.match_path(file_name).is_some() {
+                                matches.push(FileMatch {
+                                    repository: cached.id.clone(),
+                                    package: package.clone(),
+                                    path,
+                                });
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// Returns the highest priority active repository providing `package`
+    pub fn origin(&self, package: &package::Id) -> Option<&Repository> {
+        self.repositories
+            .values()
+            .filter(|cached| cached.repository.active)
+            .sorted_by(|a, b| a.repository.priority.cmp(&b.repository.priority))
+            .find(|cached| cached.db.get(package).is_ok())
+            .map(|cached| &cached.repository)
+    }
+
     /// Returns the active repositories held by this manager
     pub(crate) fn active(&self) -> impl Iterator<Item = repository::Cached> + '_ {
         self.repositories.values().filter(|c| c.repository.active).cloned()
@@ -309,23 +419,248 @@ fn open_meta_db(identifier: &str, repo: &Repository, installation: &Installation
     Ok(db)
 }
 
-/// Fetches a stone index file from the repository URL
+/// Fetches a stone index file from the repository URL, or one of its mirrors,
 /// and saves it to the repo installation path
+///
+/// Returns the saved path, the URL it was fetched from and its cache validators,
+/// or `None` if the previously fetched index is still current
 async fn fetch_index(
     identifier: &str,
     state: &repository::Cached,
     installation: &Installation,
-) -> Result<PathBuf, Error> {
+) -> Result<Option<(PathBuf, Url, request::Validators)>, Error> {
     let out_dir = cache_dir(identifier, &state.repository, installation);
 
     tokio::fs::create_dir_all(&out_dir).await.map_err(Error::CreateDir)?;
 
-    let out_path = out_dir.join("stone.index");
+    fetch_index_to(state, out_dir.join("stone.index")).await
+}
+
+/// Fetches the stone index to `out_path`, see [`fetch_index`]
+async fn fetch_index_to(
+    state: &repository::Cached,
+    out_path: PathBuf,
+) -> Result<Option<(PathBuf, Url, request::Validators)>, Error> {
+    // The trusted keys may have changed since the cached index was fetched, so it's
+    // only revalidated with the server while it's still trusted. Otherwise it's
+    // fetched in full and verified again.
+    let validators = if out_path.exists() && is_trusted(state, &out_path).await {
+        read_validators(&out_path)
+    } else {
+        request::Validators::default()
+    };
 
     // Fetch index & write to `out_path`
-    repository::fetch_index(state.repository.uri.clone(), &out_path).await?;
+    let fetched = {
+        let out_path = &out_path;
+        let validators = &validators;
+        let failures = mirror::Failures::session();
+        let locations = mirror::locations(&state.repository, &state.repository.uri, failures);
+
+        mirror::failover(locations, failures, |url| async move {
+            repository::fetch_index_if_modified(url.clone(), out_path, validators)
+                .await
+                .map(|fetched| fetched.map(|validators| (url, validators)))
+        })
+        .await?
+    };
+
+    let Some((url, validators)) = fetched else {
+        return Ok(None);
+    };
+
+    verify_signature(state, &url, &out_path).await?;
+
+    Ok(Some((out_path, url, validators)))
+}
 
-    Ok(out_path)
+/// Path of the file holding a cache validator of the file at `path`
+fn validator_path(path: &Path, validator: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{validator}", path.display()))
+}
+
+/// Read the cache validators stored alongside the file at `path`
+fn read_validators(path: &Path) -> request::Validators {
+    let read = |validator| fs::read_to_string(validator_path(path, validator)).ok();
+
+    request::Validators {
+        etag: read("etag"),
+        last_modified: read("last-modified"),
+    }
+}
+
+/// Store the cache validators of the file at `path` alongside it
+fn write_validators(path: &Path, validators: &request::Validators) -> Result<(), Error> {
+    for (validator, value) in [("etag", &validators.etag), ("last-modified", &validators.last_modified)] {
+        let validator_path = validator_path(path, validator);
+
+        match value {
+            Some(value) => fs::write(validator_path, value).map_err(Error::WriteValidators)?,
+            None if validator_path.exists() => fs::remove_file(validator_path).map_err(Error::WriteValidators)?,
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches the optional index `name` published alongside the stone index at `index_url`,
+/// removing any stale copy if the repository doesn't provide one or it can't be fetched
+async fn fetch_optional_index(
+    identifier: &str,
+    state: &repository::Cached,
+    index_url: &Url,
+    installation: &Installation,
+    name: &'static str,
+) -> Result<(), Error> {
+    let out_path = cache_dir(identifier, &state.repository, installation).join(name);
+
+    let Ok(url) = index_url.join(name) else {
+        return Ok(());
+    };
+
+    match repository::fetch_index(url.clone(), &out_path).await {
+        Ok(()) => verify_signature(state, &url, &out_path).await,
+        Err(error) if error.is_not_found() => {
+            if out_path.exists() {
+                fs::remove_file(&out_path).map_err(|error| Error::RemoveOptionalIndex(name, error))?;
+            }
+            Ok(())
+        }
+        Err(error) => {
+            // Don't leave anything describing a previous index behind
+            let _ = fs::remove_file(&out_path);
+            Err(Error::FetchOptionalIndex(name, error))
+        }
+    }
+}
+
+/// Verify the file fetched from `url` to `path` against the detached signature
+/// published next to it, if the repository has trusted keys configured.
+///
+/// Fails closed, removing the fetched file unless it's signed by a trusted key.
+async fn verify_signature(state: &repository::Cached, url: &Url, path: &Path) -> Result<(), Error> {
+    if state.repository.trusted_keys.is_empty() {
+        return Ok(());
+    }
+
+    let result = check_signature(state, url, path).await;
+
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+
+    result
+}
+
+/// Returns true if the previously fetched file at `path` is signed by a key the repository
+/// currently trusts, according to the signature fetched alongside it
+async fn is_trusted(state: &repository::Cached, path: &Path) -> bool {
+    if state.repository.trusted_keys.is_empty() {
+        return true;
+    }
+
+    let (Ok(signature), Ok(data)) = (
+        tokio::fs::read_to_string(signature_path(path)).await,
+        tokio::fs::read(path).await,
+    ) else {
+        return false;
+    };
+
+    signature
+        .parse::<signing::Signature>()
+        .is_ok_and(|signature| signing::verify_any(&state.repository.trusted_keys, &data, &signature))
+}
+
+/// Path of the detached signature stored alongside the file at `path`
+fn signature_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), signing::SIGNATURE_EXTENSION))
+}
+
+async fn check_signature(state: &repository::Cached, url: &Url, path: &Path) -> Result<(), Error> {
+    let file_name = url.path_segments().and_then(|mut s| s.next_back()).unwrap_or_default();
+    let signature_url = url
+        .join(&format!("{file_name}.{}", signing::SIGNATURE_EXTENSION))
+        .map_err(|_| Error::MissingSignature(state.id.clone()))?;
+    let signature_path = signature_path(path);
+
+    match repository::fetch_index(signature_url, &signature_path).await {
+        Ok(()) => {}
+        Err(error) if error.is_not_found() => return Err(Error::MissingSignature(state.id.clone())),
+        Err(error) => return Err(Error::FetchSignature(error)),
+    }
+
+    let signature = tokio::fs::read_to_string(&signature_path)
+        .await
+        .map_err(Error::ReadSignature)?
+        .parse::<signing::Signature>()
+        .map_err(Error::MalformedSignature)?;
+    let data = tokio::fs::read(path).await.map_err(Error::ReadSignature)?;
+
+    if signing::verify_any(&state.repository.trusted_keys, &data, &signature) {
+        Ok(())
+    } else {
+        Err(Error::UntrustedSignature(state.id.clone()))
+    }
+}
+
+/// Reads the deltas index at `path`, keyed by the hash of the package each delta reconstructs.
+/// Deltas are only an optimisation, so an unreadable index is skipped
+fn read_deltas(path: &Path) -> BTreeMap<String, package::meta::Delta> {
+    if !path.exists() {
+        return BTreeMap::new();
+    }
+
+    let read = || -> Result<_, Error> {
+        let mut file = File::open(path).map_err(Error::OpenIndex)?;
+        let mut reader = stone::read(&mut file)?;
+
+        reader
+            .payloads()?
+            .filter_map(|payload| match payload {
+                Ok(stone::read::PayloadKind::Meta(meta)) => Some(
+                    package::meta::Delta::from_stone_payload(&meta.body)
+                        .map_err(|error| Error::MissingMetaField(error.0)),
+                ),
+                Ok(_) => None,
+                Err(error) => Some(Err(error.into())),
+            })
+            .collect()
+    };
+
+    read().unwrap_or_else(|error| {
+        warn!("skipping {}, {error}", path.display());
+        BTreeMap::new()
+    })
+}
+
+/// Reads the constraints index at `path`, keyed by the hash of the package
+/// with versioned dependencies. Without it, dependencies are unconstrained
+fn read_constraints(path: &Path) -> BTreeMap<String, Vec<Dependency>> {
+    if !path.exists() {
+        return BTreeMap::new();
+    }
+
+    let read = || -> Result<_, Error> {
+        let mut file = File::open(path).map_err(Error::OpenIndex)?;
+        let mut reader = stone::read(&mut file)?;
+
+        reader
+            .payloads()?
+            .filter_map(|payload| match payload {
+                Ok(stone::read::PayloadKind::Meta(meta)) => {
+                    Some(package::Meta::constraints_from_stone_payload(&meta.body).map_err(Error::MalformedMeta))
+                }
+                Ok(_) => None,
+                Err(error) => Some(Err(error.into())),
+            })
+            .collect()
+    };
+
+    read().unwrap_or_else(|error| {
+        warn!("skipping {}, {error}", path.display());
+        BTreeMap::new()
+    })
 }
 
 /// Updates a stones metadata into the meta db
@@ -339,6 +674,9 @@ fn update_meta_db(state: &repository::Cached, index_path: &Path) -> Result<(), E
 
     let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
 
+    let mut deltas = read_deltas(&index_path.with_file_name(repository::DELTAS_INDEX));
+    let mut constraints = read_constraints(&index_path.with_file_name(repository::CONSTRAINTS_INDEX));
+
     // Construct Meta for each payload
     let packages = payloads
         .into_iter()
@@ -357,9 +695,14 @@ fn update_meta_db(state: &repository::Cached, index_path: &Path) -> Result<(), E
                 .hash
                 .clone()
                 .ok_or(Error::MissingMetaField(stone::payload::meta::Tag::PackageHash))?;
+            let delta = deltas.remove(&hash);
+            let meta = match constraints.remove(&hash) {
+                Some(constrained) => meta.with_constraints(constrained),
+                None => meta,
+            };
             let id = package::Id::from(hash);
 
-            Ok((id, meta))
+            Ok((id, package::Meta { delta, ..meta }))
         })
         .collect::<Result<Vec<_>, Error>>()?;
 
@@ -375,12 +718,30 @@ pub enum Error {
     ExplicitUnsupported,
     #[error("Missing metadata field: {0:?}")]
     MissingMetaField(stone::payload::meta::Tag),
+    #[error("malformed metadata")]
+    MalformedMeta(#[source] package::meta::Error),
     #[error("create directory")]
     CreateDir(#[source] io::Error),
     #[error("remove directory")]
     RemoveDir(#[source] io::Error),
     #[error("fetch index file")]
     FetchIndex(#[from] repository::FetchError),
+    #[error("{0} index is not signed")]
+    MissingSignature(repository::Id),
+    #[error("{0} index is not signed by a trusted key")]
+    UntrustedSignature(repository::Id),
+    #[error("fetch index signature")]
+    FetchSignature(#[source] repository::FetchError),
+    #[error("read index signature")]
+    ReadSignature(#[source] io::Error),
+    #[error("malformed index signature")]
+    MalformedSignature(#[source] signing::Error),
+    #[error("fetch {0}")]
+    FetchOptionalIndex(&'static str, #[source] repository::FetchError),
+    #[error("remove {0}")]
+    RemoveOptionalIndex(&'static str, #[source] io::Error),
+    #[error("write index cache validators")]
+    WriteValidators(#[source] io::Error),
     #[error("open index file")]
     OpenIndex(#[source] io::Error),
     #[error("read index file")]
@@ -393,14 +754,102 @@ pub enum Error {
     UnknownRepo(repository::Id),
 }
 
-impl From<package::MissingMetaFieldError> for Error {
-    fn from(error: package::MissingMetaFieldError) -> Self {
-        Self::MissingMetaField(error.0)
+impl From<package::meta::Error> for Error {
+    fn from(error: package::meta::Error) -> Self {
+        match error {
+            package::meta::Error::MissingField(error) => Self::MissingMetaField(error.0),
+            error @ package::meta::Error::MalformedDependency(..) => Self::MalformedMeta(error),
+        }
     }
 }
 
+/// A path matched by [`Manager::search_files`]
+#[derive(Debug, Clone)]
+pub struct FileMatch {
+    pub repository: repository::Id,
+    pub package: package::Id,
+    /// Absolute path of the file
+    pub path: String,
+}
+
+/// Outcome of refreshing a [`Repository`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    /// A new index was fetched and imported
+    Updated,
+    /// The index hasn't changed since it was last fetched
+    Unchanged,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Removal {
     NotFound,
     ConfigDeleted(bool),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repository::Priority;
+
+    fn cached(uri: Url, trusted_keys: Vec<signing::PublicKey>) -> repository::Cached {
+        repository::Cached {
+            id: repository::Id::new("test".to_owned()),
+            repository: Repository {
+                description: String::default(),
+                uri,
+                mirrors: vec![],
+                priority: Priority::new(0),
+                active: true,
+                trusted_keys,
+                stone_signatures: Default::default(),
+                credentials: None,
+            },
+            db: meta::Database::new(":memory:").unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn cached_index_follows_trust_config() {
+        let dir = std::env::temp_dir().join(format!("moss-fetch-index-{}", std::process::id()));
+        let remote = dir.join("remote");
+        let local = dir.join("local");
+        fs::create_dir_all(&remote).unwrap();
+        fs::create_dir_all(&local).unwrap();
+
+        let remote_index = remote.join("stone.index");
+        let local_index = local.join("stone.index");
+        fs::write(&remote_index, b"index").unwrap();
+        let uri = Url::from_file_path(&remote_index).unwrap();
+
+        // Unsigned repository, a second fetch is answered as not modified
+        let state = cached(uri.clone(), vec![]);
+        let (_, _, validators) = fetch_index_to(&state, local_index.clone()).await.unwrap().unwrap();
+        write_validators(&local_index, &validators).unwrap();
+        assert!(fetch_index_to(&state, local_index.clone()).await.unwrap().is_none());
+
+        // Cleared validators, e.g. after a failed import, force a full fetch
+        write_validators(&local_index, &request::Validators::default()).unwrap();
+        let (_, _, validators) = fetch_index_to(&state, local_index.clone()).await.unwrap().unwrap();
+        write_validators(&local_index, &validators).unwrap();
+
+        // Trusting a key invalidates the cached unsigned index, it's fetched and verified again
+        let key = signing::SecretKey::generate().unwrap();
+        fs::write(signature_path(&remote_index), key.sign(b"index").to_string()).unwrap();
+        let state = cached(uri.clone(), vec![key.public_key()]);
+        let (_, _, validators) = fetch_index_to(&state, local_index.clone()).await.unwrap().unwrap();
+        write_validators(&local_index, &validators).unwrap();
+        assert!(fetch_index_to(&state, local_index.clone()).await.unwrap().is_none());
+
+        // Rotating the trusted key rejects the cached index signed by the previous key
+        let other = signing::SecretKey::generate().unwrap();
+        let state = cached(uri, vec![other.public_key()]);
+        assert!(matches!(
+            fetch_index_to(&state, local_index.clone()).await,
+            Err(Error::UntrustedSignature(_))
+        ));
+        assert!(!local_index.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Failover between the mirrors of a [`Repository`]
+//!
+//! Mirrors which fail a request are remembered for the rest of the
+//! session and tried after their healthy peers from then on.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Mutex, OnceLock},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use super::Repository;
+use crate::request;
+
+/// An alternative location publishing the same index as [`Repository::uri`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mirror {
+    pub uri: Url,
+    /// Mirrors with a higher weight are tried first
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub weight: u64,
+}
+
+fn is_zero(weight: &u64) -> bool {
+    *weight == 0
+}
+
+/// A resource of a [`Repository`] as published by one of its mirrors
+#[derive(Debug, Clone)]
+pub struct Location {
+    /// Index URI of the mirror
+    pub mirror: Url,
+    /// Where the mirror publishes the resource
+    pub url: Url,
+}
+
+/// Errors which another mirror may not run into
+pub trait Failure {
+    /// Returns true if the request should be retried against the next mirror
+    fn is_mirror_failure(&self) -> bool;
+}
+
+impl Failure for request::Error {
+    fn is_mirror_failure(&self) -> bool {
+        match self {
+            request::Error::Fetch(error) => {
+                error.is_connect()
+                    || error.is_timeout()
+                    || error.is_body()
+                    || error.is_request()
+                    || error.status().is_some_and(|status| status.is_server_error())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// There's no location left to send a request to
+#[derive(Debug, Error)]
+#[error("no mirror to fetch from")]
+pub struct NoLocation;
+
+/// Number of failed requests per mirror
+#[derive(Debug, Default)]
+pub struct Failures(Mutex<BTreeMap<Url, u64>>);
+
+impl Failures {
+    /// Failures recorded for the rest of this session
+    pub fn session() -> &'static Self {
+        static FAILURES: OnceLock<Failures> = OnceLock::new();
+        FAILURES.get_or_init(Failures::default)
+    }
+
+    fn record(&self, mirror: &Url) {
+        *self.0.lock().expect("mutex lock").entry(mirror.clone()).or_default() += 1;
+    }
+}
+
+/// Index URIs of the repository and its mirrors, in the order they should be tried.
+///
+/// The primary URI comes first followed by the mirrors by descending weight, but
+/// any with recorded `failures` are moved behind those which haven't failed.
+pub fn uris(repository: &Repository, failures: &Failures) -> Vec<Url> {
+    let mut mirrors = repository.mirrors.iter().collect::<Vec<_>>();
+    mirrors.sort_by(|a, b| a.weight.cmp(&b.weight).reverse());
+
+    let failures = failures.0.lock().expect("mutex lock");
+
+    let mut uris = [&repository.uri]
+        .into_iter()
+        .chain(mirrors.into_iter().map(|mirror| &mirror.uri))
+        .cloned()
+        .collect::<Vec<_>>();
+    uris.sort_by_key(|uri| failures.get(uri).copied().unwrap_or_default());
+
+    uris
+}
+
+/// Locations of `url`, a resource published relative to the primary [`Repository::uri`],
+/// across all mirrors of the repository in the order they should be tried
+pub fn locations(repository: &Repository, url: &Url, failures: &Failures) -> Vec<Location> {
+    let Some(relative) = repository.uri.make_relative(url) else {
+        return vec![Location {
+            mirror: repository.uri.clone(),
+            url: url.clone(),
+        }];
+    };
+
+    uris(repository, failures)
+        .into_iter()
+        .filter_map(|mirror| {
+            let url = mirror.join(&relative).ok()?;
+            Some(Location { mirror, url })
+        })
+        .collect()
+}
+
+/// Run `request` against each location in turn until it succeeds, or fails
+/// in a way another mirror can't help with. Failed mirrors are recorded in `failures`.
+pub async fn failover<T, E, F, Fut>(locations: Vec<Location>, failures: &Failures, mut request: F) -> Result<T, E>
+where
+    E: Failure + std::error::Error + From<NoLocation>,
+    F: FnMut(Url) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut locations = locations.into_iter().peekable();
+
+    loop {
+        let Some(location) = locations.next() else {
+            return Err(NoLocation.into());
+        };
+
+        match request(location.url.clone()).await {
+            Ok(value) => return Ok(value),
+            Err(error) if error.is_mirror_failure() => {
+                failures.record(&location.mirror);
+
+                if locations.peek().is_none() {
+                    return Err(error);
+                }
+
+                warn!("{} failed, trying next mirror: {error}", location.url);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::TryStreamExt;
+
+    use super::*;
+    use crate::repository::{FetchError, Priority};
+    use crate::request::test::{serve, Response};
+
+    fn repository(uri: Url, mirrors: Vec<Mirror>) -> Repository {
+        Repository {
+            description: String::default(),
+            uri,
+            mirrors,
+            priority: Priority::new(0),
+            active: true,
+            trusted_keys: vec![],
+            stone_signatures: Default::default(),
+            credentials: None,
+        }
+    }
+
+    async fn fetch(url: Url) -> Result<Vec<u8>, FetchError> {
+        Ok(request::get(url)
+            .await?
+            .map_ok(|bytes| bytes.to_vec())
+            .try_concat()
+            .await?)
+    }
+
+    #[test]
+    fn mirror_locations() {
+        let repository = repository(
+            "https://primary.example/x86_64/stone.index".parse().unwrap(),
+            vec![
+                Mirror {
+                    uri: "https://light.example/stone.index".parse().unwrap(),
+                    weight: 1,
+                },
+                Mirror {
+                    uri: "https://heavy.example/serpent/x86_64/stone.index".parse().unwrap(),
+                    weight: 10,
+                },
+            ],
+        );
+        let failures = Failures::default();
+
+        let url = repository.uri.join("b/bash/bash-1-1-1-x86_64.stone").unwrap();
+        let urls = |repository| {
+            locations(repository, &url, &failures)
+                .into_iter()
+                .map(|location| location.url.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            urls(&repository),
+            [
+                "https://primary.example/x86_64/b/bash/bash-1-1-1-x86_64.stone",
+                "https://heavy.example/serpent/x86_64/b/bash/bash-1-1-1-x86_64.stone",
+                "https://light.example/b/bash/bash-1-1-1-x86_64.stone",
+            ]
+        );
+
+        failures.record(&repository.uri);
+
+        assert_eq!(
+            urls(&repository)[0],
+            "https://heavy.example/serpent/x86_64/b/bash/bash-1-1-1-x86_64.stone"
+        );
+    }
+
+    #[tokio::test]
+    async fn failover_to_mirror() {
+        let base = serve(|request| match request.path.as_str() {
+            "/broken/stone.index" => Response::new(503, "unavailable"),
+            "/good/stone.index" => Response::new(200, "index"),
+            _ => Response::new(404, "not found"),
+        })
+        .await;
+
+        let repository = repository(
+            base.join("broken/stone.index").unwrap(),
+            vec![
+                Mirror {
+                    uri: base.join("missing/stone.index").unwrap(),
+                    weight: 0,
+                },
+                Mirror {
+                    uri: base.join("good/stone.index").unwrap(),
+                    weight: 1,
+                },
+            ],
+        );
+        let failures = Failures::default();
+
+        // The failing primary is skipped for the next mirror, and tried last from then on
+        let fetched = failover(locations(&repository, &repository.uri, &failures), &failures, fetch).await;
+        assert_eq!(fetched.unwrap(), b"index");
+        assert_eq!(
+            uris(&repository, &failures),
+            [
+                base.join("good/stone.index").unwrap(),
+                base.join("missing/stone.index").unwrap(),
+                base.join("broken/stone.index").unwrap(),
+            ]
+        );
+
+        // Missing resources aren't a mirror failure, so they're reported as is
+        let url = base.join("missing/stone.index").unwrap();
+        let fetched = failover(locations(&repository, &url, &failures), &failures, fetch).await;
+        assert!(fetched.unwrap_err().is_not_found());
+
+        // Nowhere to fetch from is an error rather than a panic
+        let fetched = failover(vec![], &failures, fetch).await;
+        assert!(matches!(fetched, Err(FetchError::NoLocation(_))));
+    }
+}
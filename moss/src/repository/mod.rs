@@ -5,9 +5,10 @@
 use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use bytes::Bytes;
 use derive_more::{Display, From, Into};
 use fs_err::tokio::File;
-use futures::StreamExt;
+use futures::{stream::BoxStream, StreamExt};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::io::{self, AsyncWriteExt};
@@ -15,11 +16,27 @@ use url::Url;
 
 use config::Config;
 
-use crate::{db::meta, request};
+use crate::{db::meta, request, signing};
 
 pub use self::manager::Manager;
+pub use self::mirror::Mirror;
 
 pub mod manager;
+pub mod mirror;
+
+/// Name of the optional file-list index published next to the stone index,
+/// listing the layout of every package in the repository
+pub const FILES_INDEX: &str = "stone.files";
+
+/// Name of the optional deltas index published next to the stone index, listing
+/// the delta stones available for packages. Kept apart from the stone index so
+/// clients which don't know the delta tags can still read it
+pub const DELTAS_INDEX: &str = "stone.deltas";
+
+/// Name of the optional constraints index published next to the stone index,
+/// listing the versioned dependencies of packages. The stone index lists them
+/// by name only, so clients which don't know the versioned tag can still read it
+pub const CONSTRAINTS_INDEX: &str = "stone.constraints";
 
 /// A unique [`Repository`] identifier
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Ord, PartialOrd, From, Display)]
@@ -42,9 +59,62 @@ impl Id {
 pub struct Repository {
     pub description: String,
     pub uri: Url,
+    /// Alternative locations publishing the same index,
+    /// used when requests to `uri` fail
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Mirror>,
     pub priority: Priority,
     #[serde(default = "default_as_true")]
     pub active: bool,
+    /// Keys trusted to sign the index. When set, the index
+    /// is rejected unless signed by one of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_keys: Vec<signing::PublicKey>,
+    /// How signatures carried by stones fetched from
+    /// this repository are checked against `trusted_keys`
+    #[serde(default, skip_serializing_if = "SignaturePolicy::is_ignore")]
+    pub stone_signatures: SignaturePolicy,
+    /// Credentials presented when fetching from
+    /// the repository or any of its mirrors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<request::Credentials>,
+}
+
+impl Repository {
+    /// Register the credentials of the repository for all subsequent requests to it
+    pub(crate) fn authenticate(&self) {
+        let Some(credentials) = &self.credentials else {
+            return;
+        };
+
+        for uri in [&self.uri]
+            .into_iter()
+            .chain(self.mirrors.iter().map(|mirror| &mirror.uri))
+        {
+            request::authenticate(uri, credentials.clone());
+        }
+    }
+}
+
+/// Trust policy applied to stones fetched from a [`Repository`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum SignaturePolicy {
+    /// Signatures aren't checked
+    #[default]
+    Ignore,
+    /// Signed stones must be signed by a trusted key,
+    /// unsigned stones are accepted
+    Verify,
+    /// Every stone must be signed by a trusted key
+    Require,
+}
+
+impl SignaturePolicy {
+    fn is_ignore(&self) -> bool {
+        *self == Self::Ignore
+    }
 }
 
 fn default_as_true() -> bool {
@@ -124,23 +194,119 @@ impl Config for Map {
 }
 
 async fn fetch_index(url: Url, out_path: impl Into<PathBuf>) -> Result<(), FetchError> {
-    let mut stream = request::get(url).await?;
+    let stream = request::get(url).await?;
+
+    write_stream(stream, out_path).await
+}
+
+/// Fetch the index at `url` unless it still matches the `validators` of the copy
+/// previously fetched to `out_path`
+///
+/// Returns the validators of the newly fetched index, or `None` if it wasn't modified
+async fn fetch_index_if_modified(
+    url: Url,
+    out_path: impl Into<PathBuf>,
+    validators: &request::Validators,
+) -> Result<Option<request::Validators>, FetchError> {
+    match request::get_if_modified(url, validators).await? {
+        request::Conditional::NotModified => Ok(None),
+        request::Conditional::Modified(validators, stream) => {
+            write_stream(stream, out_path).await?;
+            Ok(Some(validators))
+        }
+    }
+}
+
+/// Write `stream` to `out_path`, which is only replaced once the stream completes
+/// so a failed fetch never leaves a truncated file behind
+async fn write_stream(
+    mut stream: BoxStream<'static, Result<Bytes, request::Error>>,
+    out_path: impl Into<PathBuf>,
+) -> Result<(), FetchError> {
+    let out_path = out_path.into();
+    let partial_path = PathBuf::from(format!("{}.part", out_path.display()));
+
+    let written = async {
+        let mut out = File::create(&partial_path).await?;
 
-    let mut out = File::create(out_path).await?;
+        while let Some(chunk) = stream.next().await {
+            out.write_all(&chunk?).await?;
+        }
 
-    while let Some(chunk) = stream.next().await {
-        out.write_all(&chunk?).await?;
+        out.flush().await?;
+
+        Ok::<_, FetchError>(())
+    }
+    .await;
+
+    if let Err(error) = written {
+        let _ = tokio::fs::remove_file(&partial_path).await;
+        return Err(error);
     }
 
-    out.flush().await?;
+    tokio::fs::rename(&partial_path, &out_path).await?;
 
     Ok(())
 }
 
+impl FetchError {
+    /// Returns true if the requested resource doesn't exist
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            FetchError::Request(request::Error::Fetch(error)) => error.status() == Some(reqwest::StatusCode::NOT_FOUND),
+            FetchError::Request(request::Error::Read(error)) => error.kind() == io::ErrorKind::NotFound,
+            FetchError::Request(_) | FetchError::Io(_) | FetchError::NoLocation(_) => false,
+        }
+    }
+}
+
+impl mirror::Failure for FetchError {
+    fn is_mirror_failure(&self) -> bool {
+        match self {
+            FetchError::Request(error) => error.is_mirror_failure(),
+            FetchError::Io(_) | FetchError::NoLocation(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum FetchError {
     #[error("request")]
     Request(#[from] request::Error),
     #[error("io")]
     Io(#[from] io::Error),
+    #[error(transparent)]
+    NoLocation(#[from] mirror::NoLocation),
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn interrupted_write_keeps_previous_file() {
+        let dir = std::env::temp_dir().join(format!("moss-write-stream-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("stone.index");
+        tokio::fs::write(&path, "previous").await.unwrap();
+
+        let interrupted = stream::iter([
+            Ok(Bytes::from_static(b"trunc")),
+            Err(request::Error::Read(io::Error::other("connection reset"))),
+        ])
+        .boxed();
+
+        assert!(write_stream(interrupted, &path).await.is_err());
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "previous");
+        assert!(!dir.join("stone.index.part").exists());
+
+        let complete = stream::iter([Ok(Bytes::from_static(b"current"))]).boxed();
+
+        write_stream(complete, &path).await.unwrap();
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "current");
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
 }
@@ -2,57 +2,383 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{io, path::PathBuf, sync::OnceLock};
+use std::{
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
 
 use bytes::Bytes;
 use fs_err::tokio::File;
 use futures::{
     stream::{self, BoxStream},
-    Stream, StreamExt,
+    StreamExt,
 };
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tokio::io::AsyncReadExt;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    time::Instant,
+};
 use tokio_util::io::ReaderStream;
 use url::Url;
 
 use crate::environment;
 
+/// Network settings applied to all requests, loaded from `network.d` configs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Proxy for all http(s) requests, overriding any set in the environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<Url>,
+    /// Credentials presented to the proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_credentials: Option<Credentials>,
+    /// Comma separated list of hosts which bypass the proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_proxy: Option<String>,
+    /// PEM bundles of certificate authorities to trust
+    /// in addition to the built-in roots
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_certificates: Vec<PathBuf>,
+    /// Maximum number of resources downloaded at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Maximum combined download rate in bytes per second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bandwidth: Option<u64>,
+}
+
+impl Config {
+    /// Load and merge all network configs of `manager`, later configs taking precedence
+    pub fn load(manager: &config::Manager) -> Self {
+        manager
+            .load::<Self>()
+            .into_iter()
+            .fold(Self::default(), |merged, config| Self {
+                proxy: config.proxy.or(merged.proxy),
+                proxy_credentials: config.proxy_credentials.or(merged.proxy_credentials),
+                no_proxy: config.no_proxy.or(merged.no_proxy),
+                ca_certificates: merged
+                    .ca_certificates
+                    .into_iter()
+                    .chain(config.ca_certificates)
+                    .collect(),
+                max_concurrency: config.max_concurrency.or(merged.max_concurrency),
+                max_bandwidth: config.max_bandwidth.or(merged.max_bandwidth),
+            })
+    }
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "network".into()
+    }
+}
+
+/// Credentials presented to a server, with the secret read from
+/// a file only accessible by the user moss is running as
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Credentials {
+    /// HTTP basic authentication
+    Basic { username: String, password_file: PathBuf },
+    /// Bearer token authentication
+    Bearer { token_file: PathBuf },
+}
+
+impl Credentials {
+    /// Value of the `Authorization` header for these credentials
+    fn header(&self) -> Result<header::HeaderValue, Error> {
+        let value = match self {
+            Credentials::Basic {
+                username,
+                password_file,
+            } => {
+                use base64::{engine::general_purpose::STANDARD, Engine};
+
+                let password = read_secret(password_file)?;
+                format!("Basic {}", STANDARD.encode(format!("{username}:{password}")))
+            }
+            Credentials::Bearer { token_file } => format!("Bearer {}", read_secret(token_file)?),
+        };
+
+        let mut value = header::HeaderValue::try_from(value).map_err(|_| Error::MalformedCredentials)?;
+        value.set_sensitive(true);
+
+        Ok(value)
+    }
+}
+
+/// Read the secret held in `path`, refusing files which can be
+/// accessed by anyone but the user moss is running as
+fn read_secret(path: &Path) -> Result<String, Error> {
+    let read = |error| Error::ReadCredentials(path.to_owned(), error);
+
+    let metadata = fs_err::metadata(path).map_err(read)?;
+
+    if metadata.uid() != nix::unistd::geteuid().as_raw() || metadata.mode() & 0o077 != 0 {
+        return Err(Error::InsecureCredentials(path.to_owned()));
+    }
+
+    Ok(fs_err::read_to_string(path).map_err(read)?.trim().to_string())
+}
+
+/// Settings the shared client is built with
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Credentials of each URL prefix registered with [`authenticate`]
+static CREDENTIALS: Mutex<Vec<(Url, Credentials)>> = Mutex::new(Vec::new());
+
 /// Shared client for tcp socket reuse and connection limit
 static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
 
-fn get_client() -> &'static reqwest::Client {
-    CLIENT.get_or_init(|| {
-        reqwest::ClientBuilder::new()
-            .referer(false)
-            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
-            .build()
-            .expect("build reqwest client")
-    })
+/// Apply `config` to all subsequent requests
+///
+/// Must be called before the first request is made, as the
+/// shared client is only built once
+pub fn configure(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+/// Present `credentials` with all requests to `base` and the resources below it,
+/// i.e. a repository index and the packages published alongside it
+pub fn authenticate(base: &Url, credentials: Credentials) {
+    let Ok(base) = base.join(".") else {
+        return;
+    };
+
+    let mut registered = CREDENTIALS.lock().expect("mutex lock");
+    registered.retain(|(url, _)| *url != base);
+    registered.push((base, credentials));
+}
+
+/// Number of resources which should be downloaded at once
+pub fn concurrency() -> usize {
+    CONFIG
+        .get()
+        .and_then(|config| config.max_concurrency)
+        .unwrap_or(environment::MAX_NETWORK_CONCURRENCY)
+        .max(1)
+}
+
+fn get_client() -> Result<&'static reqwest::Client, Error> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let client = build_client(CONFIG.get_or_init(Config::default))?;
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+fn build_client(config: &Config) -> Result<reqwest::Client, Error> {
+    let mut builder = reqwest::ClientBuilder::new().referer(false).user_agent(concat!(
+        env!("CARGO_PKG_NAME"),
+        "/",
+        env!("CARGO_PKG_VERSION")
+    ));
+
+    if let Some(url) = &config.proxy {
+        let mut proxy = reqwest::Proxy::all(url.clone()).map_err(Error::BuildClient)?;
+
+        if let Some(credentials) = &config.proxy_credentials {
+            proxy = proxy.custom_http_auth(credentials.header()?);
+        }
+        if let Some(no_proxy) = &config.no_proxy {
+            proxy = proxy.no_proxy(reqwest::NoProxy::from_string(no_proxy));
+        }
+
+        builder = builder.proxy(proxy);
+    }
+
+    for path in &config.ca_certificates {
+        let pem = fs_err::read(path).map_err(|error| Error::ReadCertificates(path.clone(), error))?;
+
+        for certificate in reqwest::Certificate::from_pem_bundle(&pem).map_err(Error::BuildClient)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder.build().map_err(Error::BuildClient)
+}
+
+/// Start a GET request to `url`, authenticated with the
+/// most specific credentials registered for it
+fn request(url: Url) -> Result<reqwest::RequestBuilder, Error> {
+    let credentials = CREDENTIALS
+        .lock()
+        .expect("mutex lock")
+        .iter()
+        .filter(|(base, _)| url.as_str().starts_with(base.as_str()))
+        .max_by_key(|(base, _)| base.as_str().len())
+        .map(|(_, credentials)| credentials.clone());
+
+    let request = get_client()?.get(url);
+
+    match credentials {
+        Some(credentials) => Ok(request.header(header::AUTHORIZATION, credentials.header()?)),
+        None => Ok(request),
+    }
 }
 
 /// Fetch a resource at the provided [`Url`] and stream response body as bytes
 pub async fn get(url: Url) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
+    let (_, stream) = get_from(url, 0).await?;
+    Ok(stream)
+}
+
+/// Cache validators identifying the version of a previously fetched resource
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Response to [`get_if_modified`]
+pub enum Conditional {
+    /// The resource still matches the provided validators
+    NotModified,
+    /// The resource changed, streaming its body alongside its new validators
+    Modified(Validators, BoxStream<'static, Result<Bytes, Error>>),
+}
+
+/// Fetch a resource at the provided [`Url`] unless it still matches the
+/// `validators` of a previous fetch
+pub async fn get_if_modified(url: Url, validators: &Validators) -> Result<Conditional, Error> {
+    if let Some(path) = url_file(&url) {
+        let modified = File::open(&path).await?.metadata().await?.modified()?;
+        let current = Validators {
+            etag: None,
+            last_modified: Some(
+                chrono::DateTime::<chrono::Utc>::from(modified)
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            ),
+        };
+
+        if current == *validators {
+            return Ok(Conditional::NotModified);
+        }
+
+        return Ok(Conditional::Modified(current, read(path, 0).await?));
+    }
+
+    let mut request = self::request(url)?;
+
+    if let Some(etag) = &validators.etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let response = request.send().await?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Conditional::NotModified);
+    }
+
+    let response = response.error_for_status()?;
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let validators = Validators {
+        etag: header(header::ETAG),
+        last_modified: header(header::LAST_MODIFIED),
+    };
+
+    let stream = response
+        .bytes_stream()
+        .map(|result| result.map_err(Error::Fetch))
+        .boxed();
+
+    Ok(Conditional::Modified(validators, throttle(stream)))
+}
+
+/// Fetch a resource at the provided [`Url`], skipping the first `offset` bytes
+/// when the source supports it.
+///
+/// Returns the offset the streamed body actually starts at, which is `0` if the
+/// source ignored the requested range and sent the full resource instead.
+pub async fn get_from(url: Url, offset: u64) -> Result<(u64, BoxStream<'static, Result<Bytes, Error>>), Error> {
     match url_file(&url) {
-        Some(path) => read(path).await,
-        _ => Ok(fetch(url).await?.boxed()),
+        Some(path) => Ok((offset, read(path, offset).await?)),
+        _ => fetch(url, offset).await,
     }
 }
 
-/// Internal fetch helper (sanity control) for `get`
-async fn fetch(url: Url) -> Result<impl Stream<Item = Result<Bytes, Error>>, Error> {
-    let response = self::get_client().get(url).send().await?;
+/// Internal fetch helper (sanity control) for `get_from`
+async fn fetch(url: Url, offset: u64) -> Result<(u64, BoxStream<'static, Result<Bytes, Error>>), Error> {
+    let mut request = self::request(url)?;
+
+    if offset > 0 {
+        request = request.header(header::RANGE, format!("bytes={offset}-"));
+    }
 
-    response
+    let response = request.send().await?;
+
+    let offset = match response.status() {
+        StatusCode::PARTIAL_CONTENT => offset,
+        // Nothing left past the offset, the resource is already complete
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok((offset, stream::empty().boxed())),
+        _ => 0,
+    };
+
+    let stream = response
         .error_for_status()
         .map(reqwest::Response::bytes_stream)
         .map(|stream| stream.map(|result| result.map_err(Error::Fetch)))
-        .map_err(Error::Fetch)
+        .map_err(Error::Fetch)?;
+
+    Ok((offset, throttle(stream.boxed())))
+}
+
+/// Delay the chunks of `stream` so all response bodies combined
+/// stay within the configured bandwidth cap
+fn throttle(stream: BoxStream<'static, Result<Bytes, Error>>) -> BoxStream<'static, Result<Bytes, Error>> {
+    let Some(rate) = CONFIG.get().and_then(|config| config.max_bandwidth) else {
+        return stream;
+    };
+
+    stream
+        .then(move |result| async move {
+            if let Ok(bytes) = &result {
+                tokio::time::sleep_until(reserve_bandwidth(bytes.len() as u64, rate)).await;
+            }
+            result
+        })
+        .boxed()
+}
+
+/// Reserve the time needed to receive `len` bytes at `rate` bytes per second after
+/// all previous reservations, returning when the reservation starts
+fn reserve_bandwidth(len: u64, rate: u64) -> Instant {
+    static NEXT: Mutex<Option<Instant>> = Mutex::new(None);
+
+    let mut next = NEXT.lock().expect("mutex lock");
+    let now = Instant::now();
+    let start = next.filter(|next| *next > now).unwrap_or(now);
+
+    *next = Some(start + Duration::from_secs_f64(len as f64 / rate.max(1) as f64));
+
+    start
 }
 
 /// Asynchronously read a filesystem path akin to the fetch API
-async fn read(path: PathBuf) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
+async fn read(path: PathBuf, offset: u64) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
     let mut file = File::open(path).await?;
-    let size = file.metadata().await?.len() as usize;
+    let size = file.metadata().await?.len().saturating_sub(offset) as usize;
+
+    file.seek(io::SeekFrom::Start(offset)).await?;
 
     if size > environment::FILE_READ_CHUNK_THRESHOLD {
         let stream = ReaderStream::with_capacity(file, environment::FILE_READ_BUFFER_SIZE);
@@ -81,4 +407,255 @@ pub enum Error {
     Fetch(#[from] reqwest::Error),
     #[error("io")]
     Read(#[from] io::Error),
+    #[error("build client")]
+    BuildClient(#[source] reqwest::Error),
+    #[error("read CA certificates {0:?}")]
+    ReadCertificates(PathBuf, #[source] io::Error),
+    #[error("read credentials {0:?}")]
+    ReadCredentials(PathBuf, #[source] io::Error),
+    #[error("credentials {0:?} must be owned by and only accessible to the current user")]
+    InsecureCredentials(PathBuf),
+    #[error("malformed credentials")]
+    MalformedCredentials,
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::Arc;
+
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+
+    /// A request received by [`serve`]
+    pub(crate) struct Request {
+        pub path: String,
+        /// Start of the requested `Range`, if any
+        pub range: Option<u64>,
+        /// Header names are lowercase
+        pub headers: Vec<(String, String)>,
+    }
+
+    impl Request {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find_map(|(header, value)| (header == name).then_some(value.as_str()))
+        }
+    }
+
+    /// A response sent by [`serve`]
+    pub(crate) struct Response {
+        pub status: u16,
+        pub headers: Vec<(&'static str, String)>,
+        pub body: Vec<u8>,
+    }
+
+    impl Response {
+        pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+            Self {
+                status,
+                headers: vec![],
+                body: body.into(),
+            }
+        }
+    }
+
+    /// Serve HTTP on a local port for the rest of the test, answering
+    /// each request with `respond`. Returns the base URL of the server
+    pub(crate) async fn serve(respond: impl Fn(Request) -> Response + Send + Sync + 'static) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap()).parse().unwrap();
+        let respond = Arc::new(respond);
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let respond = respond.clone();
+
+                tokio::spawn(async move {
+                    let mut head = vec![];
+                    let mut byte = [0; 1];
+                    while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).await.unwrap_or(0) == 1 {
+                        head.push(byte[0]);
+                    }
+
+                    let head = String::from_utf8_lossy(&head);
+                    let path = head.split_whitespace().nth(1).unwrap_or_default().to_owned();
+                    let headers = head
+                        .lines()
+                        .skip(1)
+                        .filter_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            Some((name.trim().to_ascii_lowercase(), value.trim().to_owned()))
+                        })
+                        .collect::<Vec<_>>();
+                    let mut request = Request {
+                        path,
+                        range: None,
+                        headers,
+                    };
+                    request.range = request
+                        .header("range")
+                        .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok());
+
+                    let response = respond(request);
+
+                    let mut out = format!(
+                        "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        response.status,
+                        response.body.len()
+                    );
+                    for (name, value) in &response.headers {
+                        out.push_str(&format!("{name}: {value}\r\n"));
+                    }
+                    out.push_str("\r\n");
+
+                    let _ = stream.write_all(out.as_bytes()).await;
+                    let _ = stream.write_all(&response.body).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        url
+    }
+
+    /// Answer requests for `body`, honouring any requested range
+    pub(crate) fn ranged(body: &'static [u8]) -> impl Fn(Request) -> Response + Send + Sync + 'static {
+        move |request: Request| match request.range {
+            Some(start) if start >= body.len() as u64 => Response {
+                status: 416,
+                headers: vec![("Content-Range", format!("bytes */{}", body.len()))],
+                body: vec![],
+            },
+            Some(start) => Response {
+                status: 206,
+                headers: vec![(
+                    "Content-Range",
+                    format!("bytes {start}-{}/{}", body.len() - 1, body.len()),
+                )],
+                body: body[start as usize..].to_vec(),
+            },
+            None => Response::new(200, body),
+        }
+    }
+
+    async fn get_all(url: Url, offset: u64) -> (u64, Vec<u8>) {
+        let (offset, stream) = get_from(url, offset).await.unwrap();
+        let chunks = stream.collect::<Vec<_>>().await;
+        let body = chunks.into_iter().flat_map(|chunk| chunk.unwrap().to_vec()).collect();
+
+        (offset, body)
+    }
+
+    #[tokio::test]
+    async fn get_from_offset() {
+        let honours_range = serve(ranged(b"0123456789")).await;
+        let ignores_range = serve(|_| Response::new(200, "0123456789")).await;
+
+        // Partial content picks up from the offset
+        assert_eq!(get_all(honours_range.clone(), 4).await, (4, b"456789".to_vec()));
+        // Nothing is left past the end of a complete resource
+        assert_eq!(get_all(honours_range, 10).await, (10, vec![]));
+        // The whole resource is sent when the range isn't honoured, so it restarts from 0
+        assert_eq!(get_all(ignores_range, 4).await, (0, b"0123456789".to_vec()));
+
+        let path = std::env::temp_dir().join(format!("moss-get-from-{}", std::process::id()));
+        fs_err::write(&path, "0123456789").unwrap();
+        let url = Url::from_file_path(&path).unwrap();
+
+        assert_eq!(get_all(url, 4).await, (4, b"456789".to_vec()));
+
+        fs_err::remove_file(&path).unwrap();
+    }
+
+    /// Write `secret` to a file only accessible by the current user
+    fn write_secret(name: &str, secret: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("moss-{name}-{}", std::process::id()));
+        fs_err::write(&path, secret).unwrap();
+        fs_err::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn secret_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = write_secret("secret", "hunter2\n");
+        assert_eq!(read_secret(&path).unwrap(), "hunter2");
+
+        fs_err::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        assert!(matches!(read_secret(&path), Err(Error::InsecureCredentials(_))));
+
+        fs_err::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn no_proxy_bypasses_proxy() {
+        let proxy = serve(|_| Response::new(200, "proxied")).await;
+        let server = serve(|_| Response::new(200, "direct")).await;
+
+        let fetch = |no_proxy: &str| {
+            let client = build_client(&Config {
+                proxy: Some(proxy.clone()),
+                no_proxy: Some(no_proxy.to_string()),
+                ..Config::default()
+            })
+            .unwrap();
+            let request = client.get(server.clone());
+
+            async move { request.send().await.unwrap().text().await.unwrap() }
+        };
+
+        assert_eq!(fetch("example.org").await, "proxied");
+        assert_eq!(fetch("example.org, 127.0.0.1").await, "direct");
+    }
+
+    #[tokio::test]
+    async fn credentials_match_longest_prefix() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let server = {
+            let seen = seen.clone();
+            serve(move |request| {
+                let authorization = request.header("authorization").map(str::to_owned);
+                seen.lock().unwrap().push((request.path, authorization));
+                Response::new(200, "")
+            })
+            .await
+        };
+
+        let repo = write_secret("repo-token", "repo");
+        let private = write_secret("private-token", "private");
+        authenticate(
+            &server.join("repo/stone.index").unwrap(),
+            Credentials::Bearer {
+                token_file: repo.clone(),
+            },
+        );
+        authenticate(
+            &server.join("repo/private/stone.index").unwrap(),
+            Credentials::Bearer {
+                token_file: private.clone(),
+            },
+        );
+
+        for path in ["repo/a.stone", "repo/private/b.stone", "other/c.stone"] {
+            get(server.join(path).unwrap()).await.unwrap();
+        }
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                ("/repo/a.stone".to_string(), Some("Bearer repo".to_string())),
+                ("/repo/private/b.stone".to_string(), Some("Bearer private".to_string())),
+                ("/other/c.stone".to_string(), None),
+            ]
+        );
+
+        fs_err::remove_file(&repo).unwrap();
+        fs_err::remove_file(&private).unwrap();
+    }
 }
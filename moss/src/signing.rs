@@ -0,0 +1,414 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Ed25519 signing of repository artefacts
+//!
+//! Keys and signatures are exchanged as base64 text so public keys can be
+//! pasted into repository configs and signatures published as small files
+//! alongside whatever they sign, i.e. `stone.index.sig`.
+//!
+//! Stones instead carry their signatures inline, as a signature payload.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use fs_err::{self as fs, os::unix::fs::OpenOptionsExt, File};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::repository::SignaturePolicy;
+
+/// File extension appended to the name of a signed file for its signature
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// Private half of a signing keypair, kept offline
+pub struct SecretKey(SigningKey);
+
+impl SecretKey {
+    /// Generate a new random key
+    pub fn generate() -> Result<Self, Error> {
+        let mut seed = [0u8; ed25519_dalek::SECRET_KEY_LENGTH];
+        getrandom::getrandom(&mut seed).map_err(Error::Random)?;
+        Ok(Self(SigningKey::from_bytes(&seed)))
+    }
+
+    /// Load a key previously written by [`SecretKey::save`]
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let encoded = fs::read_to_string(path)?;
+        let bytes = decode::<{ ed25519_dalek::SECRET_KEY_LENGTH }>(&encoded)?;
+        Ok(Self(SigningKey::from_bytes(&bytes)))
+    }
+
+    /// Write the key to a new file only readable by its owner
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        io::Write::write_all(
+            &mut file,
+            format!("{}\n", STANDARD.encode(self.0.to_bytes())).as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.verifying_key())
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Signature {
+        Signature(self.0.sign(data))
+    }
+}
+
+/// Public half of a signing keypair, trusted by repository consumers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PublicKey(VerifyingKey);
+
+impl PublicKey {
+    /// Returns true if `signature` over `data` was made by this key
+    pub fn verify(&self, data: &[u8], signature: &Signature) -> bool {
+        self.0.verify_strict(data, &signature.0).is_ok()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", STANDARD.encode(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = decode::<{ ed25519_dalek::PUBLIC_KEY_LENGTH }>(s)?;
+        Ok(Self(VerifyingKey::from_bytes(&bytes).map_err(|_| Error::InvalidKey)?))
+    }
+}
+
+impl TryFrom<String> for PublicKey {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PublicKey> for String {
+    fn from(key: PublicKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// Detached signature over some data
+#[derive(Debug, Clone)]
+pub struct Signature(ed25519_dalek::Signature);
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", STANDARD.encode(self.0.to_bytes()))
+    }
+}
+
+impl FromStr for Signature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = decode::<{ ed25519_dalek::SIGNATURE_LENGTH }>(s)?;
+        Ok(Self(ed25519_dalek::Signature::from_bytes(&bytes)))
+    }
+}
+
+impl TryFrom<&[u8]> for Signature {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self(
+            ed25519_dalek::Signature::from_slice(bytes).map_err(|_| Error::InvalidLength)?,
+        ))
+    }
+}
+
+/// Returns true if `signature` over `data` was made by any of the `trusted` keys
+pub fn verify_any(trusted: &[PublicKey], data: &[u8], signature: &Signature) -> bool {
+    trusted.iter().any(|key| key.verify(data, signature))
+}
+
+/// Trust policy for stones which don't come from a repository, such as local
+/// files passed to `moss extract`, loaded from `signing.d` configs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Keys trusted to sign local stones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_keys: Vec<PublicKey>,
+    #[serde(default)]
+    pub stone_signatures: SignaturePolicy,
+}
+
+impl Config {
+    /// Load and merge all signing configs of `manager`, later policies taking precedence
+    pub fn load(manager: &config::Manager) -> Self {
+        manager
+            .load::<Self>()
+            .into_iter()
+            .fold(Self::default(), |merged, config| Self {
+                trusted_keys: merged.trusted_keys.into_iter().chain(config.trusted_keys).collect(),
+                stone_signatures: config.stone_signatures,
+            })
+    }
+
+    /// Additionally trust `keys`, requiring a trusted signature if any are given
+    pub fn with_trusted_keys(mut self, keys: impl IntoIterator<Item = PublicKey>) -> Self {
+        let count = self.trusted_keys.len();
+        self.trusted_keys.extend(keys);
+        if self.trusted_keys.len() > count {
+            self.stone_signatures = SignaturePolicy::Require;
+        }
+        self
+    }
+
+    /// Check the stone at `path` against this policy, see [`check_stone`]
+    pub fn check(&self, path: &Path) -> Result<StoneSignature, Error> {
+        check_stone(self.stone_signatures, &self.trusted_keys, path)
+    }
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "signing".into()
+    }
+}
+
+/// Outcome of checking the signature payloads of a stone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoneSignature {
+    /// The stone carries no signatures
+    Unsigned,
+    /// None of the signatures were made by a trusted key
+    Untrusted,
+    /// Signed by the given trusted key
+    Trusted(PublicKey),
+}
+
+/// Sign the stone at `path` in place, appending a signature payload
+pub fn sign_stone(key: &SecretKey, path: &Path) -> Result<(), Error> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+
+    let message = stone::read(&mut file)?.signed_message()?;
+    let signature = stone::payload::Signature {
+        key: key.public_key().as_bytes().to_vec(),
+        signature: key.sign(&message).0.to_bytes().to_vec(),
+    };
+
+    stone::write::append_signature(&mut file, &[signature])?;
+
+    Ok(())
+}
+
+/// Check the signature payloads of the stone at `path` against the `trusted` keys
+pub fn verify_stone(trusted: &[PublicKey], path: &Path) -> Result<StoneSignature, Error> {
+    let mut reader = stone::read(File::open(path)?)?;
+
+    let signatures = reader
+        .payloads()?
+        .filter_map(|payload| match payload {
+            Ok(stone::read::PayloadKind::Signature(signatures)) => Some(Ok(signatures.body)),
+            Ok(_) => None,
+            Err(error) => Some(Err(error)),
+        })
+        .flatten_ok()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if signatures.is_empty() {
+        return Ok(StoneSignature::Unsigned);
+    }
+
+    let message = reader.signed_message()?;
+
+    for record in signatures {
+        let Some(key) = trusted.iter().find(|key| key.as_bytes() == record.key) else {
+            continue;
+        };
+        let signature = Signature::try_from(record.signature.as_slice())?;
+
+        if key.verify(&message, &signature) {
+            return Ok(StoneSignature::Trusted(key.clone()));
+        }
+    }
+
+    Ok(StoneSignature::Untrusted)
+}
+
+/// Check the stone at `path` against `policy`, accepting signatures by any of the `trusted` keys
+///
+/// Returns an error if the policy rejects the stone
+pub fn check_stone(policy: SignaturePolicy, trusted: &[PublicKey], path: &Path) -> Result<StoneSignature, Error> {
+    if policy == SignaturePolicy::Ignore {
+        return Ok(StoneSignature::Unsigned);
+    }
+
+    match (verify_stone(trusted, path)?, policy) {
+        (signature @ StoneSignature::Trusted(_), _) => Ok(signature),
+        (StoneSignature::Unsigned, SignaturePolicy::Verify) => Ok(StoneSignature::Unsigned),
+        (StoneSignature::Unsigned, _) => Err(Error::UnsignedStone(path.to_owned())),
+        (StoneSignature::Untrusted, _) => Err(Error::UntrustedStone(path.to_owned())),
+    }
+}
+
+/// Decode base64 text into exactly `N` bytes
+fn decode<const N: usize>(encoded: &str) -> Result<[u8; N], Error> {
+    let bytes = STANDARD.decode(encoded.trim())?;
+    bytes.try_into().map_err(|_| Error::InvalidLength)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io")]
+    Io(#[from] io::Error),
+    #[error("invalid base64")]
+    Base64(#[from] base64::DecodeError),
+    #[error("invalid length")]
+    InvalidLength,
+    #[error("invalid public key")]
+    InvalidKey,
+    #[error("generate random key: {0}")]
+    Random(getrandom::Error),
+    #[error("read stone")]
+    ReadStone(#[from] stone::read::Error),
+    #[error("write stone")]
+    WriteStone(#[from] stone::write::Error),
+    #[error("{0:?} is not signed")]
+    UnsignedStone(PathBuf),
+    #[error("{0:?} is not signed by a trusted key")]
+    UntrustedStone(PathBuf),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sign_verify() {
+        let secret = SecretKey::generate().unwrap();
+        let public = secret.public_key().to_string().parse::<PublicKey>().unwrap();
+        let other = SecretKey::generate().unwrap().public_key();
+
+        let signature = secret.sign(b"stone.index").to_string().parse::<Signature>().unwrap();
+
+        assert!(public.verify(b"stone.index", &signature));
+        assert!(!public.verify(b"tampered", &signature));
+        assert!(!other.verify(b"stone.index", &signature));
+        assert!(verify_any(&[other, public], b"stone.index", &signature));
+    }
+
+    #[test]
+    fn sign_verify_stone() {
+        let path = std::env::temp_dir().join(format!("moss-sign-verify-{}.stone", std::process::id()));
+        fs::write(
+            &path,
+            include_bytes!("../../test/bash-completion-2.11-1-1-x86_64.stone"),
+        )
+        .unwrap();
+
+        let secret = SecretKey::generate().unwrap();
+        let public = secret.public_key();
+        let other = SecretKey::generate().unwrap().public_key();
+
+        assert_eq!(
+            verify_stone(std::slice::from_ref(&public), &path).unwrap(),
+            StoneSignature::Unsigned
+        );
+
+        sign_stone(&secret, &path).unwrap();
+
+        assert_eq!(
+            verify_stone(&[other.clone(), public.clone()], &path).unwrap(),
+            StoneSignature::Trusted(public)
+        );
+        assert_eq!(verify_stone(&[other], &path).unwrap(), StoneSignature::Untrusted);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tampered_content_fails_verification() {
+        let path = std::env::temp_dir().join(format!("moss-tampered-{}.stone", std::process::id()));
+        fs::write(
+            &path,
+            include_bytes!("../../test/bash-completion-2.11-1-1-x86_64.stone"),
+        )
+        .unwrap();
+
+        let secret = SecretKey::generate().unwrap();
+        let public = secret.public_key();
+
+        sign_stone(&secret, &path).unwrap();
+
+        // Walk the payload headers to flip one byte within the content body
+        let mut bytes = io::Cursor::new(fs::read(&path).unwrap());
+        bytes.set_position(stone::Header::SIZE as u64);
+        let offset = loop {
+            let header = stone::payload::Header::decode(&mut bytes).unwrap();
+            if header.kind == stone::payload::Kind::Content {
+                break bytes.position() + header.stored_size / 2;
+            }
+            bytes.set_position(bytes.position() + header.stored_size);
+        };
+        let mut bytes = bytes.into_inner();
+        bytes[offset as usize] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        assert_eq!(
+            verify_stone(std::slice::from_ref(&public), &path).unwrap(),
+            StoneSignature::Untrusted
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn local_policy() {
+        let path = std::env::temp_dir().join(format!("moss-local-policy-{}.stone", std::process::id()));
+        fs::write(
+            &path,
+            include_bytes!("../../test/bash-completion-2.11-1-1-x86_64.stone"),
+        )
+        .unwrap();
+
+        let secret = SecretKey::generate().unwrap();
+        let other = SecretKey::generate().unwrap().public_key();
+
+        assert!(Config::default().check(&path).is_ok());
+        assert!(matches!(
+            Config::default().with_trusted_keys([secret.public_key()]).check(&path),
+            Err(Error::UnsignedStone(_))
+        ));
+
+        sign_stone(&secret, &path).unwrap();
+
+        assert!(Config::default()
+            .with_trusted_keys([secret.public_key()])
+            .check(&path)
+            .is_ok());
+        assert!(matches!(
+            Config::default().with_trusted_keys([other]).check(&path),
+            Err(Error::UntrustedStone(_))
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
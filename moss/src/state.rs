@@ -46,6 +46,8 @@ pub struct State {
     pub summary: Option<String>,
     /// Description for the state (optional)
     pub description: Option<String>,
+    /// Unique name given by the user, protecting the state from pruning (optional)
+    pub tag: Option<String>,
     /// Selections in this state
     pub selections: Vec<Selection>,
     /// Creation timestamp
@@ -80,7 +82,7 @@ impl Selection {
     pub fn transitive(package: package::Id) -> Self {
         Self {
             package,
-            explicit: true,
+            explicit: false,
             reason: None,
         }
     }
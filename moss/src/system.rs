@@ -0,0 +1,42 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Declarative configuration of the packages a system should have
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Packages a system converges on with `moss sync --declarative`,
+/// loaded from `system.yaml` and `system.d` configs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Names or providers of every package which should be explicitly
+    /// selected. Anything else that is explicitly installed gets removed,
+    /// unless still required as a dependency.
+    #[serde(default)]
+    pub packages: BTreeSet<String>,
+}
+
+impl Config {
+    /// Load all system configs of `manager`, being `system.yaml` and every file in
+    /// `system.d` of both the vendor & admin config dirs
+    ///
+    /// The declared packages are the union of all of them, so a config can add
+    /// packages but never drop those declared by another.
+    pub fn load(manager: &config::Manager) -> Self {
+        manager
+            .load::<Self>()
+            .into_iter()
+            .fold(Self::default(), |merged, config| Self {
+                packages: merged.packages.into_iter().chain(config.packages).collect(),
+            })
+    }
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "system".into()
+    }
+}